@@ -0,0 +1,202 @@
+use crate::apu::dmc::DMC;
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::triangle::Triangle;
+use crate::timing::{Timing, NTSC};
+
+// the 2A03's sound channels and the frame counter clocking their envelopes,
+// sweeps and length counters, from https://wiki.nesdev.com/w/index.php/APU
+pub struct APU {
+    pulses: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
+    dmc: DMC,
+    timing: Timing,
+    // CPU cycles since the frame counter sequence started
+    frame_cycle: u32,
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    // CPU cycles until a $4017 write restarts the sequence
+    frame_reset_delay: u8,
+    // the channel timers run at half the CPU rate
+    odd_cycle: bool,
+}
+
+impl APU {
+    pub fn new() -> APU {
+        APU {
+            pulses: [Pulse::new(true), Pulse::new(false)],
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: DMC::new(),
+            timing: NTSC,
+            frame_cycle: 0,
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_reset_delay: 0,
+            odd_cycle: false,
+        }
+    }
+
+    pub fn set_timing(&mut self, timing: &Timing) {
+        self.timing = *timing;
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulses[0].write_register(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulses[1].write_register(address - 0x4004, value),
+            0x4008..=0x400b => self.triangle.write_register(address - 0x4008, value),
+            0x400c..=0x400f => self.noise.write_register(address - 0x400c, value),
+            0x4010..=0x4013 => self.dmc.write_register(address - 0x4010, value),
+            // ---D NT21, enables the channels
+            0x4015 => {
+                self.pulses[0].set_enabled((value & 0b01) != 0);
+                self.pulses[1].set_enabled((value & 0b10) != 0);
+                self.triangle.set_enabled((value & 0b100) != 0);
+                self.noise.set_enabled((value & 0b1000) != 0);
+                self.dmc.set_enabled((value & 0b10000) != 0);
+            }
+            // MI-- ----, from https://wiki.nesdev.com/w/index.php/APU_Frame_Counter
+            0x4017 => {
+                self.five_step_mode = (value & 0x80) != 0;
+                self.irq_inhibit = (value & 0x40) != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                // the sequence restarts 3 or 4 CPU cycles later depending on
+                // where in the APU cycle the write lands, the 5 step mode
+                // clocks everything right away
+                self.frame_reset_delay = if self.odd_cycle { 3 } else { 4 };
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // $4015 reads back which length counters are running, whether the DMC
+    // sample has bytes left and both interrupts. The read only acknowledges
+    // the frame interrupt.
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.pulses[0].is_active() {
+            status |= 0b01;
+        }
+        if self.pulses[1].is_active() {
+            status |= 0b10;
+        }
+        if self.triangle.is_active() {
+            status |= 0b100;
+        }
+        if self.noise.is_active() {
+            status |= 0b1000;
+        }
+        if self.dmc.is_active() {
+            status |= 0b10000;
+        }
+        if self.frame_irq {
+            status |= 0x40;
+        }
+        if self.dmc.get_irq() {
+            status |= 0x80;
+        }
+        self.frame_irq = false;
+        status
+    }
+
+    pub fn get_irq(&self) -> bool {
+        self.frame_irq || self.dmc.get_irq()
+    }
+
+    // the address of the DMC sample byte to fetch, if the DMC needs one
+    pub fn get_dma_address(&self) -> Option<u16> {
+        self.dmc.get_dma_address()
+    }
+
+    pub fn load_dma_sample(&mut self, value: u8) {
+        self.dmc.load_sample(value);
+    }
+
+    // DMA reads land on the cycles the pulse timers are clocked on
+    pub fn is_next_cycle_get(&self) -> bool {
+        !self.odd_cycle
+    }
+
+    pub fn step_cpu(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer(&self.timing.noise_periods);
+        self.dmc.clock_timer(&self.timing.dmc_rates);
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock_timer();
+            }
+        }
+        self.step_frame_counter();
+    }
+
+    fn step_frame_counter(&mut self) {
+        if self.frame_reset_delay > 0 {
+            self.frame_reset_delay -= 1;
+            if self.frame_reset_delay == 0 {
+                self.frame_cycle = 0;
+                return;
+            }
+        }
+
+        self.frame_cycle += 1;
+        let steps = self.timing.frame_counter_steps;
+        if self.frame_cycle == steps[0] || self.frame_cycle == steps[2] {
+            self.clock_quarter_frame();
+        } else if self.frame_cycle == steps[1] {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        }
+
+        // the last step of the 4 step sequence raises the interrupt over 3
+        // cycles, the third of which starts the next sequence
+        let end = if self.five_step_mode { self.timing.frame_counter_5_step_end } else { self.timing.frame_counter_4_step_end };
+        if !self.five_step_mode && !self.irq_inhibit && (end - 1..=end + 1).contains(&self.frame_cycle) {
+            self.frame_irq = true;
+        }
+        if self.frame_cycle == end {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        } else if self.frame_cycle == end + 1 {
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.clock_quarter_frame();
+        }
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.clock_half_frame();
+        }
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    // the nonlinear mixer, from https://wiki.nesdev.com/w/index.php/APU_Mixer
+    pub fn get_output(&self) -> f32 {
+        let pulses = self.pulses[0].get_output() as f32 + self.pulses[1].get_output() as f32;
+        let pulse_output = if pulses == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulses + 100.0) };
+
+        let tnd = self.triangle.get_output() as f32 / 8227.0 +
+            self.noise.get_output() as f32 / 12241.0 +
+            self.dmc.get_output() as f32 / 22638.0;
+        let tnd_output = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+        pulse_output + tnd_output
+    }
+}
@@ -0,0 +1,159 @@
+// from https://wiki.nesdev.com/w/index.php/APU_DMC
+// plays 1 bit delta encoded samples fetched from CPU memory, the fetches are
+// done by Memory, which halts the CPU for them
+pub struct DMC {
+    irq_enabled: bool,
+    looping: bool,
+    rate_index: u8,
+    timer: u16,
+    output_level: u8,
+    // from $4012 and $4013
+    sample_address: u16,
+    sample_length: u16,
+    // memory reader
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    // output unit
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    irq: bool,
+}
+
+impl DMC {
+    pub fn new() -> DMC {
+        DMC {
+            irq_enabled: false,
+            looping: false,
+            rate_index: 0,
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq: false,
+        }
+    }
+
+    // register is the address relative to $4010
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            // IL-- RRRR, clearing the IRQ flag also acknowledges the interrupt
+            0 => {
+                self.irq_enabled = (value & 0x80) != 0;
+                self.looping = (value & 0x40) != 0;
+                self.rate_index = value & 0x0f;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            // -DDD DDDD
+            1 => {
+                self.output_level = value & 0x7f;
+            }
+            // %11AAAAAA.AA000000
+            2 => {
+                self.sample_address = 0xc000 | ((value as u16) << 6);
+            }
+            // %LLLL.LLLL0001
+            _ => {
+                self.sample_length = ((value as u16) << 4) | 1;
+            }
+        }
+    }
+
+    // through $4015, which also acknowledges the interrupt. Enabling only
+    // restarts a sample that has finished.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn get_irq(&self) -> bool {
+        self.irq
+    }
+
+    // the address the memory reader wants to fetch, once the sample buffer
+    // has been emptied into the output unit
+    pub fn get_dma_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    // the byte fetched from get_dma_address, the address wraps around to $8000
+    pub fn load_sample(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.current_address = if self.current_address == 0xffff { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    // every CPU cycle, rates come from the region's table in CPU cycles
+    pub fn clock_timer(&mut self, rates: &[u16; 16]) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = rates[self.rate_index as usize] - 1;
+
+        // each bit moves the level up or down by 2, as long as it stays in 0-127
+        if !self.silence {
+            if (self.shift_register & 0b1) != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+
+        // the next output cycle plays the sample buffer, or silence if it is empty
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => {
+                    self.silence = true;
+                }
+            }
+        }
+    }
+
+    // 0-127
+    pub fn get_output(&self) -> u8 {
+        self.output_level
+    }
+}
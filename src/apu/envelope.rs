@@ -0,0 +1,63 @@
+// from https://wiki.nesdev.com/w/index.php/APU_Envelope
+// a volume decaying from 15 to 0 at a rate set by the volume bits, clocked by
+// quarter frames, or the volume bits themselves in constant volume mode
+pub struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            start: false,
+            looping: false,
+            constant_volume: false,
+            volume: 0,
+            divider: 0,
+            decay: 0,
+        }
+    }
+
+    // --LC VVVV, the loop flag doubles as the length counter halt
+    pub fn write_register(&mut self, value: u8) {
+        self.looping = (value & 0x20) != 0;
+        self.constant_volume = (value & 0x10) != 0;
+        self.volume = value & 0x0f;
+    }
+
+    // on writes to the channel's length register
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+            return;
+        }
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.volume;
+        if self.decay > 0 {
+            self.decay -= 1;
+        } else if self.looping {
+            self.decay = 15;
+        }
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// from https://wiki.nesdev.com/w/index.php/APU_Length_Counter
+const LENGTHS: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// silences its channel once it counts down to 0, clocked by half frames
+pub struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub fn new() -> LengthCounter {
+        LengthCounter {
+            enabled: false,
+            halt: false,
+            counter: 0,
+        }
+    }
+
+    // through $4015, disabling clears the counter right away
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+
+    // the upper 5 bits of the channel's last register index the table
+    pub fn load(&mut self, value: u8) {
+        if self.enabled {
+            self.counter = LENGTHS[(value >> 3) as usize];
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
@@ -0,0 +1,7 @@
+pub mod apu;
+pub mod envelope;
+pub mod length_counter;
+pub mod pulse;
+pub mod triangle;
+pub mod noise;
+pub mod dmc;
@@ -0,0 +1,112 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+// from https://wiki.nesdev.com/w/index.php/APU_Noise
+pub struct Noise {
+    // feedback from bit 6 instead of bit 1, for 93 or 31 step loops
+    short_mode: bool,
+    period_index: u8,
+    timer: u16,
+    shift_register: u16,
+    envelope: Envelope,
+    length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            short_mode: false,
+            period_index: 0,
+            timer: 0,
+            shift_register: 1,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+        }
+    }
+
+    // register is the address relative to $400C
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            // --LC VVVV
+            0 => {
+                self.length_counter.set_halt((value & 0x20) != 0);
+                self.envelope.write_register(value);
+            }
+            1 => {}
+            // M--- PPPP
+            2 => {
+                self.short_mode = (value & 0x80) != 0;
+                self.period_index = value & 0x0f;
+            }
+            // LLLL L---
+            _ => {
+                self.length_counter.load(value);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    // every CPU cycle, periods come from the region's table in CPU cycles
+    pub fn clock_timer(&mut self, periods: &[u16; 16]) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = periods[self.period_index as usize] - 1;
+
+        // 15 bit LFSR
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0b1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    // 0-15, silent while bit 0 of the shift register is set
+    pub fn get_output(&self) -> u8 {
+        if (self.shift_register & 0b1) != 0 || !self.length_counter.is_active() {
+            return 0;
+        }
+        self.envelope.get_volume()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // timer clocks until the shift register comes back to where it started
+    fn count_period(short_mode: bool) -> usize {
+        let mut noise = Noise::new();
+        noise.write_register(2, if short_mode { 0x80 } else { 0x00 });
+        let start = noise.shift_register;
+        let mut steps = 0;
+        loop {
+            noise.clock_timer(&[1; 16]);
+            steps += 1;
+            if noise.shift_register == start {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn lfsr_repeats_after_32767_or_93_steps() {
+        assert_eq!(count_period(false), 32767);
+        assert_eq!(count_period(true), 93);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+// from https://wiki.nesdev.com/w/index.php/APU_Pulse
+// waveforms in the order the sequencer reads them, it counts down from 0
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+// periods below 8 would be ultrasonic and mute the channel
+const MIN_PERIOD: u16 = 8;
+const MAX_PERIOD: u16 = 0x7ff;
+
+// from https://wiki.nesdev.com/w/index.php/APU_Sweep
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+    // pulse 1 negates with the ones' complement, subtracting one more than pulse 2
+    ones_complement: bool,
+}
+
+impl Sweep {
+    fn new(ones_complement: bool) -> Sweep {
+        Sweep {
+            enabled: false,
+            period: 0,
+            negate: false,
+            shift: 0,
+            divider: 0,
+            reload: false,
+            ones_complement,
+        }
+    }
+
+    // EPPP NSSS
+    fn write_register(&mut self, value: u8) {
+        self.enabled = (value & 0x80) != 0;
+        self.period = (value >> 4) & 0b111;
+        self.negate = (value & 0x08) != 0;
+        self.shift = value & 0b111;
+        self.reload = true;
+    }
+
+    // computed all the time, a target past $7FF mutes even with the sweep disabled
+    fn get_target_period(&self, period: u16) -> u16 {
+        let change = period >> self.shift;
+        if !self.negate {
+            period + change
+        } else if self.ones_complement {
+            period.saturating_sub(change + 1)
+        } else {
+            period.saturating_sub(change)
+        }
+    }
+
+    fn is_muting(&self, period: u16) -> bool {
+        period < MIN_PERIOD || self.get_target_period(period) > MAX_PERIOD
+    }
+
+    // clocked by half frames, returns the new timer period
+    fn clock(&mut self, period: u16) -> u16 {
+        let mut new_period = period;
+        if self.divider == 0 && self.enabled && self.shift > 0 && !self.is_muting(period) {
+            new_period = self.get_target_period(period);
+        }
+        if self.divider == 0 || self.reload {
+            self.divider = self.period;
+            self.reload = false;
+        } else {
+            self.divider -= 1;
+        }
+        new_period
+    }
+}
+
+pub struct Pulse {
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    envelope: Envelope,
+    sweep: Sweep,
+    length_counter: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Pulse {
+        Pulse {
+            duty: 0,
+            step: 0,
+            period: 0,
+            timer: 0,
+            envelope: Envelope::new(),
+            sweep: Sweep::new(ones_complement),
+            length_counter: LengthCounter::new(),
+        }
+    }
+
+    // register is the address relative to $4000 or $4004
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            // DDLC VVVV
+            0 => {
+                self.duty = value >> 6;
+                self.length_counter.set_halt((value & 0x20) != 0);
+                self.envelope.write_register(value);
+            }
+            1 => self.sweep.write_register(value),
+            2 => {
+                self.period = (self.period & 0x0700) | value as u16;
+            }
+            // LLLL LHHH, restarts the waveform and the envelope
+            _ => {
+                self.period = (self.period & 0x00ff) | (((value & 0b111) as u16) << 8);
+                self.length_counter.load(value);
+                self.envelope.restart();
+                self.step = 0;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    // once every APU cycle, every other CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 7) & 0b111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+        self.period = self.sweep.clock(self.period);
+    }
+
+    // 0-15
+    pub fn get_output(&self) -> u8 {
+        if DUTY_CYCLES[self.duty as usize][self.step as usize] == 0 ||
+            !self.length_counter.is_active() ||
+            self.sweep.is_muting(self.period) {
+            return 0;
+        }
+        self.envelope.get_volume()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // constant volume 15 on the 75% duty, which is high on the first step
+    fn create_pulse(ones_complement: bool, period: u16, sweep: u8) -> Pulse {
+        let mut pulse = Pulse::new(ones_complement);
+        pulse.set_enabled(true);
+        pulse.write_register(0, 0xff);
+        pulse.write_register(1, sweep);
+        pulse.write_register(2, period as u8);
+        pulse.write_register(3, 0x08 | (period >> 8) as u8);
+        pulse
+    }
+
+    // negating the change of $80 subtracts $81 on pulse 1 and $80 on pulse 2
+    #[test]
+    fn pulse_1_negates_with_ones_complement() {
+        let mut pulse_1 = create_pulse(true, 0x100, 0x89);
+        let mut pulse_2 = create_pulse(false, 0x100, 0x89);
+        pulse_1.clock_half_frame();
+        pulse_2.clock_half_frame();
+        assert_eq!((pulse_1.period, pulse_2.period), (0x7f, 0x80));
+    }
+
+    #[test]
+    fn sweep_target_above_7ff_mutes() {
+        // disabled with a shift of 0 the target is still twice the period
+        assert_eq!(create_pulse(false, 0x3ff, 0x00).get_output(), 15);
+        assert_eq!(create_pulse(false, 0x400, 0x00).get_output(), 0);
+
+        assert_eq!(create_pulse(false, 0x555, 0x81).get_output(), 15);
+        let mut pulse = create_pulse(false, 0x556, 0x81);
+        assert_eq!(pulse.get_output(), 0);
+        // and the period is left alone
+        pulse.clock_half_frame();
+        assert_eq!(pulse.period, 0x556);
+    }
+}
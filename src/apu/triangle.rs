@@ -0,0 +1,132 @@
+use crate::apu::length_counter::LengthCounter;
+
+// from https://wiki.nesdev.com/w/index.php/APU_Triangle
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+// periods below 2 make a tone far above hearing, which only pops through a
+// real amplifier, so the sequencer stops there as most emulators do
+const MIN_PERIOD: u16 = 2;
+
+pub struct Triangle {
+    step: u8,
+    period: u16,
+    timer: u16,
+    // the control flag holds the linear counter reload on and halts the length counter
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    length_counter: LengthCounter,
+}
+
+impl Triangle {
+    pub fn new() -> Triangle {
+        Triangle {
+            step: 0,
+            period: 0,
+            timer: 0,
+            control: false,
+            linear_reload_value: 0,
+            linear_counter: 0,
+            linear_reload: false,
+            length_counter: LengthCounter::new(),
+        }
+    }
+
+    // register is the address relative to $4008
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            // CRRR RRRR
+            0 => {
+                self.control = (value & 0x80) != 0;
+                self.length_counter.set_halt(self.control);
+                self.linear_reload_value = value & 0x7f;
+            }
+            1 => {}
+            2 => {
+                self.period = (self.period & 0x0700) | value as u16;
+            }
+            // LLLL LHHH
+            _ => {
+                self.period = (self.period & 0x00ff) | (((value & 0b111) as u16) << 8);
+                self.length_counter.load(value);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    // every CPU cycle, the sequencer only moves while both counters are running
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period;
+        if self.linear_counter > 0 && self.length_counter.is_active() && self.period >= MIN_PERIOD {
+            self.step = (self.step + 1) & 0x1f;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    // 0-15, a stopped triangle keeps putting out its last step
+    pub fn get_output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // steps the sequencer moves over 64 timer clocks with both counters running
+    fn count_steps(period: u16) -> usize {
+        let mut triangle = Triangle::new();
+        triangle.set_enabled(true);
+        triangle.write_register(0, 0x7f);
+        triangle.write_register(2, period as u8);
+        triangle.write_register(3, 0x08 | (period >> 8) as u8);
+        triangle.clock_quarter_frame();
+        let mut steps = 0;
+        for _ in 0..64 {
+            let step = triangle.step;
+            triangle.clock_timer();
+            if triangle.step != step {
+                steps += 1;
+            }
+        }
+        steps
+    }
+
+    #[test]
+    fn stops_below_period_2() {
+        assert_eq!(count_steps(0), 0);
+        assert_eq!(count_steps(1), 0);
+        // every third clock, starting with the first
+        assert_eq!(count_steps(2), 22);
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use crate::patch::ips;
+use crate::timing::Timing;
+
+const AUTOSAVE_SECONDS: f64 = 5.0;
+
+pub struct BatterySave {
+    path: PathBuf,
+    saved: Vec<u8>,
+    frames: u32,
+    // when set the file holds an IPS patch against this instead of the memory itself
+    base: Option<Vec<u8>>,
+}
+
+impl BatterySave {
+    pub fn new(path: PathBuf) -> BatterySave {
+        BatterySave {
+            path,
+            saved: Vec::new(),
+            frames: 0,
+            base: None,
+        }
+    }
+
+    // for memories too large to copy around, like whole FDS disks
+    pub fn with_patch_base(path: PathBuf, base: Vec<u8>) -> BatterySave {
+        BatterySave {
+            path,
+            saved: base.clone(),
+            frames: 0,
+            base: Some(base),
+        }
+    }
+
+    // saves live next to the rom, "game.nes" saves to "game.sav"
+    pub fn get_path_for_rom(rom_path: &str) -> PathBuf {
+        Path::new(rom_path).with_extension("sav")
+    }
+
+    // "game.fds" saves to "game.ips", so the image itself stays untouched
+    pub fn get_patch_path_for_rom(rom_path: &str) -> PathBuf {
+        Path::new(rom_path).with_extension("ips")
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read(&mut self) -> Result<Option<Vec<u8>>, String> {
+        match fs::read(&self.path) {
+            Ok(bytes) => {
+                let bytes = match &self.base {
+                    Some(base) => ips::apply(base, &bytes).map_err(|error| format!("{}: {}", self.path.display(), error))?,
+                    None => bytes,
+                };
+                self.saved = bytes.clone();
+                Ok(Some(bytes))
+            }
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(format!("{}: {}", self.path.display(), error)),
+        }
+    }
+
+    // only touches the file when the memory changed since the last write
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if bytes == &self.saved[..] {
+            return Ok(());
+        }
+        let contents = match &self.base {
+            Some(base) => ips::create(base, bytes)?,
+            None => bytes.to_vec(),
+        };
+        fs::write(&self.path, contents).map_err(|error| format!("{}: {}", self.path.display(), error))?;
+        self.saved = bytes.to_vec();
+        Ok(())
+    }
+
+    // once per frame, returns true when an autosave is due
+    pub fn end_frame(&mut self, timing: &Timing) -> bool {
+        self.frames += 1;
+        if (self.frames as f64) < AUTOSAVE_SECONDS * timing.get_frame_rate() {
+            return false;
+        }
+        self.frames = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::{NTSC, PAL};
+
+    fn count_frames_to_autosave(timing: &Timing) -> u32 {
+        let mut battery = BatterySave::new(PathBuf::from("unused.sav"));
+        (1..).find(|_| battery.end_frame(timing)).unwrap()
+    }
+
+    #[test]
+    fn autosaves_every_five_seconds_of_frames() {
+        assert_eq!(count_frames_to_autosave(&NTSC), 301);
+        assert_eq!(count_frames_to_autosave(&PAL), 251);
+    }
+}
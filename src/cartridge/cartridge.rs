@@ -0,0 +1,444 @@
+use std::fs;
+use std::path::PathBuf;
+use crate::cartridge::battery::BatterySave;
+use crate::cartridge::database::find_game;
+use crate::cartridge::unif;
+use crate::cartridge::fds;
+use crate::cartridge::nsf::NsfFile;
+use crate::mapper::fds::FDS;
+use crate::mapper::nsf::NSF;
+use crate::crc32::{Crc32, crc32};
+use crate::patch::patch::apply_patch;
+use crate::cartridge::header::{Header, Region, ExpansionDevice, HEADER_SIZE, TRAINER_SIZE, PRG_RAM_UNIT};
+use crate::mapper::mapper::{Mapper, create_mapper};
+use crate::timing::Timing;
+
+// four-screen boards add RAM for the two nametables CIRAM has no room for
+const FOUR_SCREEN_VRAM_SIZE: usize = 2 * 1024;
+const NAMETABLE_SIZE: usize = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenA,
+    SingleScreenB,
+    FourScreen,
+}
+
+impl Mirroring {
+    // physical 1KB page behind a nametable: 0 and 1 are CIRAM, 2 and 3 the
+    // extra VRAM of four-screen boards
+    pub fn get_nametable_page(self, table: usize) -> usize {
+        match self {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 0b1,
+            Mirroring::SingleScreenA => 0,
+            Mirroring::SingleScreenB => 1,
+            Mirroring::FourScreen => table,
+        }
+    }
+}
+
+// what a nametable is wired to, mappers with nametable registers can pick any
+// CIRAM page or a 1KB CHR bank for each of them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NametableSource {
+    Page(usize),
+    Chr(usize),
+}
+
+// Raw memories of a board, shared by all mappers. Bank helpers wrap around
+// the memory size so oversized bank numbers mirror like on real boards.
+pub struct CartridgeData {
+    pub prg_rom: Vec<u8>,
+    pub chr: Vec<u8>,
+    pub chr_is_ram: bool,
+    pub prg_ram: Vec<u8>,
+}
+
+impl CartridgeData {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, chr_ram_size: usize, prg_ram_size: usize) -> CartridgeData {
+        let chr_is_ram = chr_rom.is_empty();
+        CartridgeData {
+            prg_rom,
+            chr: if chr_is_ram { vec![0; chr_ram_size] } else { chr_rom },
+            chr_is_ram,
+            prg_ram: vec![0; prg_ram_size],
+        }
+    }
+
+    pub fn get_prg_bank_count(&self, bank_size: usize) -> usize {
+        self.prg_rom.len() / bank_size
+    }
+
+    pub fn get_chr_bank_count(&self, bank_size: usize) -> usize {
+        self.chr.len() / bank_size
+    }
+
+    pub fn read_prg_rom(&self, bank_size: usize, bank: usize, offset: usize) -> u8 {
+        Self::read_banked(&self.prg_rom, bank_size, bank, offset)
+    }
+
+    pub fn read_chr(&self, bank_size: usize, bank: usize, offset: usize) -> u8 {
+        Self::read_banked(&self.chr, bank_size, bank, offset)
+    }
+
+    pub fn write_chr(&mut self, bank_size: usize, bank: usize, offset: usize, value: u8) {
+        if self.chr_is_ram {
+            Self::write_banked(&mut self.chr, bank_size, bank, offset, value);
+        }
+    }
+
+    pub fn read_prg_ram(&self, bank_size: usize, bank: usize, offset: usize) -> u8 {
+        Self::read_banked(&self.prg_ram, bank_size, bank, offset)
+    }
+
+    pub fn write_prg_ram(&mut self, bank_size: usize, bank: usize, offset: usize, value: u8) {
+        Self::write_banked(&mut self.prg_ram, bank_size, bank, offset, value);
+    }
+
+    fn read_banked(memory: &[u8], bank_size: usize, bank: usize, offset: usize) -> u8 {
+        if memory.is_empty() {
+            return 0;
+        }
+        memory[(bank * bank_size + (offset % bank_size)) % memory.len()]
+    }
+
+    fn write_banked(memory: &mut [u8], bank_size: usize, bank: usize, offset: usize, value: u8) {
+        if memory.is_empty() {
+            return;
+        }
+        let length = memory.len();
+        memory[(bank * bank_size + (offset % bank_size)) % length] = value;
+    }
+}
+
+pub struct Cartridge {
+    header: Header,
+    mapper: Box<dyn Mapper>,
+    battery: Option<BatterySave>,
+    // CRC32 of PRG and CHR ROM, the key into the game database
+    crc32: u32,
+    title: Option<String>,
+    // extra nametable RAM of four-screen boards
+    vram: Vec<u8>,
+}
+
+impl Cartridge {
+    pub fn load(path: &str) -> Result<Cartridge, String> {
+        Cartridge::load_with_patch(path, None)
+    }
+
+    // the patch is applied to the whole file, header included. Patched games
+    // keep their saves next to the patch, as a hack's save may not fit the original
+    pub fn load_with_patch(path: &str, patch_path: Option<&str>) -> Result<Cartridge, String> {
+        let mut bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        if let Some(patch_path) = patch_path {
+            let patch = fs::read(patch_path).map_err(|error| format!("{}: {}", patch_path, error))?;
+            bytes = apply_patch(&bytes, &patch).map_err(|error| format!("{}: {}", patch_path, error))?;
+        }
+        let mut cartridge = Cartridge::from_bytes(&bytes)?;
+        if cartridge.header.battery {
+            cartridge.attach_battery_file(BatterySave::get_path_for_rom(patch_path.unwrap_or(path)))?;
+        }
+        Ok(cartridge)
+    }
+
+    // disk images need the BIOS of the RAM adapter, which is not part of the image
+    pub fn load_fds(path: &str, bios_path: &str) -> Result<Cartridge, String> {
+        let image = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        let bios = fs::read(bios_path).map_err(|error| format!("{}: {}", bios_path, error))?;
+        let mut cartridge = Cartridge::from_fds(&image, bios)?;
+        cartridge.attach_disk_patch_file(BatterySave::get_patch_path_for_rom(path))?;
+        Ok(cartridge)
+    }
+
+    pub fn from_fds(image: &[u8], bios: Vec<u8>) -> Result<Cartridge, String> {
+        let header = Header {
+            prg_rom_size: bios.len(),
+            chr_rom_size: 0,
+            prg_ram_size: 32 * 1024,
+            prg_nvram_size: 0,
+            chr_ram_size: 8 * 1024,
+            chr_nvram_size: 0,
+            mapper: 20,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: true,
+            trainer: false,
+            nes2: false,
+            region: Region::Ntsc,
+            expansion_device: ExpansionDevice::Unspecified,
+        };
+        let data = CartridgeData::new(bios, Vec::new(), header.chr_ram_size, header.prg_ram_size);
+        let mapper = FDS::new(data, image)?;
+        Ok(Cartridge {
+            header,
+            mapper: Box::new(mapper),
+            battery: None,
+            crc32: crc32(image),
+            title: None,
+            vram: Vec::new(),
+        })
+    }
+
+    // NSF rips run on a board the player provides, there is no ROM header to go by
+    pub fn from_nsf(nsf: &NsfFile) -> Cartridge {
+        let header = Header {
+            prg_rom_size: nsf.data.len(),
+            chr_rom_size: 0,
+            prg_ram_size: 8 * 1024,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            mapper: 0,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            trainer: false,
+            nes2: false,
+            region: nsf.region,
+            expansion_device: ExpansionDevice::Unspecified,
+        };
+        Cartridge {
+            header,
+            mapper: Box::new(NSF::new(nsf)),
+            battery: None,
+            crc32: crc32(&nsf.data),
+            title: Some(nsf.title.clone()),
+            vram: Vec::new(),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Cartridge, String> {
+        if fds::is_fds(bytes) {
+            return Err(String::from("FDS disk images need the FDS BIOS, load them with load_fds"));
+        }
+        if unif::is_unif(bytes) {
+            let (header, prg_rom, chr_rom, board) = unif::parse(bytes)?;
+            return Cartridge::from_parts(header, prg_rom, chr_rom, &[])
+                .map_err(|error| format!("UNIF board {}: {}", board, error));
+        }
+
+        let header = Header::parse(bytes)?;
+        let prg_start = HEADER_SIZE + if header.trainer { TRAINER_SIZE } else { 0 };
+        let chr_start = prg_start + header.prg_rom_size;
+        let chr_end = chr_start + header.chr_rom_size;
+        if bytes.len() < chr_end {
+            return Err(format!("rom is truncated: expected {} bytes, found {}", chr_end, bytes.len()));
+        }
+        Cartridge::from_parts(
+            header,
+            bytes[prg_start..chr_start].to_vec(),
+            bytes[chr_start..chr_end].to_vec(),
+            &bytes[HEADER_SIZE..prg_start],
+        )
+    }
+
+    fn from_parts(mut header: Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>, trainer: &[u8]) -> Result<Cartridge, String> {
+        let mut crc = Crc32::new();
+        crc.update(&prg_rom);
+        crc.update(&chr_rom);
+        let crc32 = crc.get_value();
+        let game = find_game(crc32);
+        if let Some(game) = &game {
+            game.apply(&mut header);
+        }
+
+        let mut data = CartridgeData::new(prg_rom, chr_rom, header.get_chr_ram_total(), header.get_prg_ram_total());
+        // trainers are loaded to $7000-$71FF
+        for (i, byte) in trainer.iter().enumerate() {
+            data.write_prg_ram(PRG_RAM_UNIT, 0, 0x1000 + i, *byte);
+        }
+
+        let mapper = create_mapper(&header, data)?;
+        let vram = if header.mirroring == Mirroring::FourScreen { vec![0; FOUR_SCREEN_VRAM_SIZE] } else { Vec::new() };
+        Ok(Cartridge {
+            header,
+            mapper,
+            battery: None,
+            crc32,
+            title: game.map(|game| game.title.clone()),
+            vram,
+        })
+    }
+
+    // restores the battery backed memory from the file and keeps it in sync from now on
+    pub fn attach_battery_file(&mut self, path: PathBuf) -> Result<(), String> {
+        let mut battery = BatterySave::new(path);
+        if let Some(bytes) = battery.read()? {
+            self.mapper.load_battery_ram(&bytes);
+        }
+        self.battery = Some(battery);
+        Ok(())
+    }
+
+    // disk writes are kept as an IPS patch against the disk as it was loaded
+    pub fn attach_disk_patch_file(&mut self, path: PathBuf) -> Result<(), String> {
+        let mut battery = BatterySave::with_patch_base(path, self.mapper.get_battery_ram());
+        if let Some(bytes) = battery.read()? {
+            self.mapper.load_battery_ram(&bytes);
+        }
+        self.battery = Some(battery);
+        Ok(())
+    }
+
+    // dropping the cartridge flushes too, this is for callers that want to
+    // report a failed save
+    pub fn flush_battery(&mut self) -> Result<(), String> {
+        match &mut self.battery {
+            Some(battery) => battery.write(&self.mapper.get_battery_ram()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn get_crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    // only known for dumps found in the game database
+    pub fn get_title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    // CPU bus, $4020-$FFFF
+    pub fn read_prg(&mut self, address: u16) -> u8 {
+        self.mapper.read_prg(address)
+    }
+
+    pub fn write_prg(&mut self, address: u16, value: u8) {
+        self.mapper.write_prg(address, value);
+    }
+
+    // PPU bus, $0000-$1FFF
+    pub fn read_chr(&mut self, address: u16) -> u8 {
+        self.mapper.read_chr(address)
+    }
+
+    pub fn write_chr(&mut self, address: u16, value: u8) {
+        self.mapper.write_chr(address, value);
+    }
+
+    pub fn peek_chr(&mut self, address: u16) -> u8 {
+        self.mapper.peek_chr(address)
+    }
+
+    // nametables 2 and 3 in four-screen mode
+    pub fn read_vram(&self, offset: usize) -> u8 {
+        self.vram.get(offset).cloned().unwrap_or(0)
+    }
+
+    pub fn write_vram(&mut self, offset: usize, value: u8) {
+        if let Some(byte) = self.vram.get_mut(offset) {
+            *byte = value;
+        }
+    }
+
+    pub fn notify_ppu_address(&mut self, address: u16) {
+        self.mapper.notify_ppu_address(address);
+    }
+
+    pub fn get_mirroring(&self) -> Mirroring {
+        self.mapper.get_mirroring()
+    }
+
+    pub fn get_nametable_source(&self, table: usize) -> NametableSource {
+        self.mapper.get_nametable_source(table)
+    }
+
+    pub fn get_pattern_source(&self, address: u16) -> Option<NametableSource> {
+        self.mapper.get_pattern_source(address)
+    }
+
+    // nametables mapped to CHR, in 1KB banks
+    pub fn read_chr_bank(&self, bank: usize, offset: usize) -> u8 {
+        self.mapper.get_data().read_chr(NAMETABLE_SIZE, bank, offset)
+    }
+
+    pub fn write_chr_bank(&mut self, bank: usize, offset: usize, value: u8) {
+        self.mapper.get_data_mut().write_chr(NAMETABLE_SIZE, bank, offset, value);
+    }
+
+    pub fn step_cpu(&mut self) {
+        self.mapper.step_cpu();
+    }
+
+    // called by the bus between frames, writes the battery file when an autosave is due
+    pub fn end_frame(&mut self, timing: &Timing) -> Result<(), String> {
+        let autosave = match &mut self.battery {
+            Some(battery) => battery.end_frame(timing),
+            None => false,
+        };
+        if autosave { self.flush_battery() } else { Ok(()) }
+    }
+
+    pub fn get_irq(&self) -> bool {
+        self.mapper.get_irq()
+    }
+
+    pub fn get_audio_output(&self) -> f32 {
+        self.mapper.get_audio_output()
+    }
+
+    pub fn get_disk_sides(&self) -> usize {
+        self.mapper.get_disk_sides()
+    }
+
+    pub fn get_inserted_disk(&self) -> Option<usize> {
+        self.mapper.get_inserted_disk()
+    }
+
+    // swapping sides ejects the current one first, games wait to see the drive empty
+    pub fn insert_disk(&mut self, side: usize) -> Result<(), String> {
+        if side >= self.get_disk_sides() {
+            return Err(format!("disk side {} does not exist", side));
+        }
+        self.mapper.insert_disk(Some(side));
+        Ok(())
+    }
+
+    pub fn eject_disk(&mut self) {
+        self.mapper.insert_disk(None);
+    }
+}
+
+impl Drop for Cartridge {
+    fn drop(&mut self) {
+        if let Err(error) = self.flush_battery() {
+            eprintln!("saving battery memory failed: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::header::PRG_ROM_UNIT;
+    use crate::crc32::tests::force_crc32;
+
+    // a dump with the CRC32 of the embedded Super Mario Bros. entry, under a
+    // header that gets its mirroring, region and expansion device wrong
+    #[test]
+    fn database_corrects_the_header() {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 2, 1, 0x00, 0, 0, 0x01, 0, 0, 0, 0, 0, 0];
+        rom.extend(vec![0; 2 * PRG_ROM_UNIT + 0x2000 - 4]);
+        let forced = force_crc32(&rom[HEADER_SIZE..], 0x3337ec46);
+        rom.extend_from_slice(&forced);
+
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!((header.mirroring, header.region), (Mirroring::Horizontal, Region::Pal));
+        let chr_start = HEADER_SIZE + header.prg_rom_size;
+        let cartridge = Cartridge::from_parts(header, rom[HEADER_SIZE..chr_start].to_vec(), rom[chr_start..].to_vec(), &[]).unwrap();
+
+        let header = cartridge.get_header();
+        assert_eq!(cartridge.get_crc32(), 0x3337ec46);
+        assert_eq!((header.mapper, header.mirroring, header.region), (0, Mirroring::Vertical, Region::Ntsc));
+        assert_eq!(header.expansion_device, ExpansionDevice::StandardControllers);
+        assert_eq!(cartridge.get_title(), Some("Super Mario Bros."));
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+use crate::cartridge::cartridge::Mirroring;
+use crate::cartridge::header::{Header, Region, ExpansionDevice};
+
+const DATABASE: &str = include_str!("database.txt");
+// database.txt is hand written above this line and generated from nes20db.xml below it
+const GENERATED_MARKER: &str = "# generated from nes20db.xml";
+
+// the database shipped with the emulator, and the one the user may load on top of it
+static EMBEDDED_DATABASE: OnceLock<GameDatabase> = OnceLock::new();
+static EXTERNAL_DATABASE: OnceLock<GameDatabase> = OnceLock::new();
+
+pub struct GameInfo {
+    pub crc32: u32,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Option<Mirroring>,
+    pub region: Region,
+    pub expansion_device: ExpansionDevice,
+    pub title: String,
+}
+
+impl GameInfo {
+    // the database knows better than whatever tool produced the header
+    pub fn apply(&self, header: &mut Header) {
+        header.mapper = self.mapper;
+        header.submapper = self.submapper;
+        if let Some(mirroring) = self.mirroring {
+            header.mirroring = mirroring;
+        }
+        header.region = self.region;
+        header.expansion_device = self.expansion_device;
+    }
+
+    fn parse(line: &str) -> Option<GameInfo> {
+        let fields: Vec<&str> = line.splitn(7, ',').map(|field| field.trim()).collect();
+        if fields.len() != 7 {
+            return None;
+        }
+        let mirroring = match fields[3] {
+            "h" => Some(Mirroring::Horizontal),
+            "v" => Some(Mirroring::Vertical),
+            "4" => Some(Mirroring::FourScreen),
+            _ => None,
+        };
+        let region = match fields[4] {
+            "pal" => Region::Pal,
+            "multi" => Region::Multi,
+            "dendy" => Region::Dendy,
+            _ => Region::Ntsc,
+        };
+        Some(GameInfo {
+            crc32: u32::from_str_radix(fields[0], 16).ok()?,
+            mapper: fields[1].parse().ok()?,
+            submapper: fields[2].parse().ok()?,
+            mirroring,
+            region,
+            expansion_device: ExpansionDevice::from(fields[5].parse::<u8>().ok()?),
+            title: fields[6].to_string(),
+        })
+    }
+
+    // a line of database.txt
+    fn to_line(&self) -> String {
+        let mirroring = match self.mirroring {
+            Some(Mirroring::Horizontal) => "h",
+            Some(Mirroring::Vertical) => "v",
+            Some(Mirroring::FourScreen) => "4",
+            _ => "",
+        };
+        let region = match self.region {
+            Region::Ntsc => "ntsc",
+            Region::Pal => "pal",
+            Region::Multi => "multi",
+            Region::Dendy => "dendy",
+        };
+        format!("{:08X},{},{},{},{},{},{}", self.crc32, self.mapper, self.submapper, mirroring, region,
+                u8::from(self.expansion_device), self.title)
+    }
+
+    // iNES 1.0 headers have no room for these, so dumps carrying one are the
+    // ones most often found with a header that is wrong about it
+    fn needs_nes2_header(&self) -> bool {
+        let device = matches!(self.expansion_device, ExpansionDevice::Unspecified | ExpansionDevice::StandardControllers);
+        self.mapper > 0xff || self.submapper != 0 || self.region != Region::Ntsc || !device
+    }
+
+    // one <game> element of nes20db.xml, titled by the file name in the comment
+    // that goes with it
+    fn parse_xml(game: &str, title: &str) -> Option<GameInfo> {
+        let rom = find_tag(game, "rom")?;
+        let pcb = find_tag(game, "pcb")?;
+        let mirroring = match get_attribute(pcb, "mirroring") {
+            Some("H") => Some(Mirroring::Horizontal),
+            Some("V") => Some(Mirroring::Vertical),
+            Some("4") => Some(Mirroring::FourScreen),
+            _ => None,
+        };
+        let region = match find_tag(game, "console").and_then(|console| get_attribute(console, "region")) {
+            Some("1") => Region::Pal,
+            Some("2") => Region::Multi,
+            Some("3") => Region::Dendy,
+            _ => Region::Ntsc,
+        };
+        let expansion_device = find_tag(game, "expansion")
+            .and_then(|expansion| get_attribute(expansion, "type"))
+            .and_then(|device| device.parse::<u8>().ok())
+            .unwrap_or(0);
+        Some(GameInfo {
+            crc32: u32::from_str_radix(get_attribute(rom, "crc32")?, 16).ok()?,
+            mapper: get_attribute(pcb, "mapper")?.parse().ok()?,
+            submapper: get_attribute(pcb, "submapper").unwrap_or("0").parse().ok()?,
+            mirroring,
+            region,
+            expansion_device: ExpansionDevice::from(expansion_device),
+            title: title.to_string(),
+        })
+    }
+}
+
+// games keyed by the CRC32 of PRG ROM followed by CHR ROM, parsed once
+pub struct GameDatabase {
+    games: HashMap<u32, GameInfo>,
+}
+
+impl GameDatabase {
+    pub fn new() -> GameDatabase {
+        GameDatabase { games: HashMap::new() }
+    }
+
+    // either the format of database.txt or the NES 2.0 XML database,
+    // from https://forums.nesdev.org/viewtopic.php?t=19940
+    pub fn load(path: &str) -> Result<GameDatabase, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let database = if text.trim_start().starts_with('<') {
+            GameDatabase::parse_xml(&text)
+        } else {
+            GameDatabase::parse_text(&text)
+        };
+        if database.games.is_empty() {
+            return Err(format!("{}: no games found", path));
+        }
+        Ok(database)
+    }
+
+    pub fn parse_text(text: &str) -> GameDatabase {
+        let mut database = GameDatabase::new();
+        for line in text.lines().filter(|line| !line.starts_with('#') && !line.trim().is_empty()) {
+            if let Some(game) = GameInfo::parse(line) {
+                database.insert(game);
+            }
+        }
+        database
+    }
+
+    pub fn parse_xml(text: &str) -> GameDatabase {
+        let mut database = GameDatabase::new();
+        let mut title = "";
+        let mut rest = text;
+        while let Some(start) = rest.find('<') {
+            rest = &rest[start..];
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                let end = comment.find("-->").unwrap_or(comment.len());
+                title = get_file_title(comment[..end].trim());
+                rest = &comment[end..];
+            } else if rest.starts_with("<game>") || rest.starts_with("<game ") {
+                let end = rest.find("</game>").unwrap_or(rest.len());
+                let game = &rest[..end];
+                // the comment sits either just before the element or inside it
+                let game_title = match game.find("<!--") {
+                    Some(comment) => {
+                        let comment = &game[comment + 4..];
+                        get_file_title(comment[..comment.find("-->").unwrap_or(comment.len())].trim())
+                    }
+                    None => title,
+                };
+                if let Some(game) = GameInfo::parse_xml(game, game_title) {
+                    database.insert(game);
+                }
+                title = "";
+                rest = &rest[end..];
+            } else {
+                rest = &rest[1..];
+            }
+        }
+        database
+    }
+
+    // a later entry for the same dump replaces the earlier one
+    pub fn insert(&mut self, game: GameInfo) {
+        self.games.insert(game.crc32, game);
+    }
+
+    pub fn find(&self, crc32: u32) -> Option<&GameInfo> {
+        self.games.get(&crc32)
+    }
+
+    // the games database.txt embeds, sorted by CRC32 so regenerating it gives
+    // a readable diff
+    pub fn to_embedded_text(&self) -> String {
+        let mut games: Vec<&GameInfo> = self.games.values().filter(|game| game.needs_nes2_header()).collect();
+        games.sort_by_key(|game| game.crc32);
+        games.iter().map(|game| game.to_line() + "\n").collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+}
+
+// the database the user loaded is checked before the embedded one. It has to
+// be loaded before the first cartridge, as cartridges keep what they found.
+pub fn load_external_database(path: &str) -> Result<(), String> {
+    let database = GameDatabase::load(path)?;
+    EXTERNAL_DATABASE.set(database).map_err(|_| String::from("a game database is already loaded"))
+}
+
+pub fn find_game(crc32: u32) -> Option<&'static GameInfo> {
+    EXTERNAL_DATABASE.get()
+        .and_then(|database| database.find(crc32))
+        .or_else(|| EMBEDDED_DATABASE.get_or_init(|| GameDatabase::parse_text(DATABASE)).find(crc32))
+}
+
+// the first <name .../> tag of an element
+fn find_tag<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = element;
+    loop {
+        let start = rest.find('<')?;
+        rest = &rest[start + 1..];
+        let end = rest.find('>')?;
+        let tag = &rest[..end];
+        if tag.strip_prefix(name).is_some_and(|attributes| attributes.starts_with(char::is_whitespace)) {
+            return Some(tag);
+        }
+        rest = &rest[end..];
+    }
+}
+
+fn get_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    loop {
+        let start = rest.find(name)?;
+        let preceded_by_space = rest[..start].ends_with(char::is_whitespace);
+        rest = &rest[start + name.len()..];
+        if preceded_by_space {
+            if let Some(value) = rest.strip_prefix("=\"") {
+                return value.find('"').map(|end| &value[..end]);
+            }
+        }
+    }
+}
+
+// "NesCartDB\Super Mario Bros. (World).nes" is titled "Super Mario Bros. (World)"
+fn get_file_title(path: &str) -> &str {
+    let name = path.rsplit(['\\', '/']).next().unwrap_or(path);
+    match name.rfind('.') {
+        Some(dot) if name.len() - dot <= 4 => &name[..dot],
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<nes20db date="2020-01-01">
+	<game>
+		<!-- NesCartDB\Super Mario Bros. (World).nes -->
+		<prgrom size="32768" crc32="5CF548D3"/>
+		<chrrom size="8192" crc32="867B51AD"/>
+		<rom size="40960" crc32="3337EC46"/>
+		<pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+		<console type="0" region="0"/>
+		<expansion type="1"/>
+	</game>
+	<!-- Unlicensed\Some Hack.nes -->
+	<game>
+		<prgrom size="131072" crc32="00000001"/>
+		<rom size="131072" crc32="0000ABCD"/>
+		<pcb mapper="2" submapper="1" mirroring="H" battery="0"/>
+		<console type="0" region="1"/>
+	</game>
+</nes20db>
+"#;
+
+    #[test]
+    fn parses_nes20db_xml() {
+        let database = GameDatabase::parse_xml(XML);
+        assert_eq!(database.len(), 2);
+
+        let smb = database.find(0x3337ec46).unwrap();
+        assert_eq!(smb.title, "Super Mario Bros. (World)");
+        assert_eq!(smb.mapper, 0);
+        assert_eq!(smb.mirroring, Some(Mirroring::Vertical));
+        assert_eq!(smb.region, Region::Ntsc);
+
+        let hack = database.find(0xabcd).unwrap();
+        assert_eq!(hack.title, "Some Hack");
+        assert_eq!((hack.mapper, hack.submapper), (2, 1));
+        assert_eq!(hack.mirroring, Some(Mirroring::Horizontal));
+        assert_eq!(hack.region, Region::Pal);
+    }
+
+    #[test]
+    fn parses_text_database() {
+        let database = GameDatabase::parse_text("# comment\n\n0000ABCD,4,0,,pal,1,Some, Title\nbroken line\n");
+        assert_eq!(database.len(), 1);
+        let game = database.find(0xabcd).unwrap();
+        assert_eq!(game.mapper, 4);
+        assert_eq!(game.mirroring, None);
+        assert_eq!(game.title, "Some, Title");
+    }
+
+    #[test]
+    fn writes_what_ines_headers_cannot_hold() {
+        let database = GameDatabase::parse_xml(XML);
+        assert_eq!(database.to_embedded_text(), "0000ABCD,2,1,h,pal,0,Some Hack\n");
+        let reparsed = GameDatabase::parse_text(&database.to_embedded_text());
+        assert_eq!(reparsed.find(0xabcd).map(|game| game.to_line()), database.find(0xabcd).map(|game| game.to_line()));
+    }
+
+    // rewrites the generated part of database.txt, see the comment in there
+    #[test]
+    #[ignore]
+    fn regenerate_embedded_database() {
+        let path = std::env::var("NES20DB").expect("NES20DB is not set");
+        let database = GameDatabase::load(&path).unwrap();
+        let marker = DATABASE.find(GENERATED_MARKER).unwrap();
+        let hand_written = &DATABASE[..marker + DATABASE[marker..].find('\n').unwrap() + 1];
+        let path = format!("{}/src/cartridge/database.txt", env!("CARGO_MANIFEST_DIR"));
+        fs::write(path, hand_written.to_string() + &database.to_embedded_text()).unwrap();
+    }
+
+    #[test]
+    fn embedded_database_is_found() {
+        assert_eq!(find_game(0x3337ec46).map(|game| game.mapper), Some(0));
+        assert!(find_game(0x12345678).is_none());
+    }
+}
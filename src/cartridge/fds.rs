@@ -0,0 +1,156 @@
+// .fds disk images, from https://wiki.nesdev.com/w/index.php/FDS_file_format
+// images only keep the blocks of each side, the drive needs the gaps and
+// CRCs between them back to behave like a real disk
+
+const MAGIC: [u8; 4] = [0x46, 0x44, 0x53, 0x1a];
+pub const HEADER_SIZE: usize = 16;
+pub const SIDE_SIZE: usize = 65500;
+
+// 28300 bits of gap before the first block and 976 bits after each block
+const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+const GAP_END_MARK: u8 = 0x80;
+const CRC_SIZE: usize = 2;
+
+const BLOCK_DISK_INFO: u8 = 1;
+const BLOCK_FILE_AMOUNT: u8 = 2;
+const BLOCK_FILE_HEADER: u8 = 3;
+const BLOCK_FILE_DATA: u8 = 4;
+
+pub fn is_fds(bytes: &[u8]) -> bool {
+    bytes.len() >= HEADER_SIZE && bytes[0..4] == MAGIC
+}
+
+// the fwNES header is optional, headerless images are a plain list of sides
+pub fn get_header_size(bytes: &[u8]) -> usize {
+    if is_fds(bytes) { HEADER_SIZE } else { 0 }
+}
+
+pub fn parse_sides(bytes: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let data = if is_fds(bytes) { &bytes[HEADER_SIZE..] } else { bytes };
+    if data.len() < SIDE_SIZE {
+        return Err(String::from("not an FDS disk image"));
+    }
+    Ok(data.chunks(SIDE_SIZE)
+        .filter(|side| side.len() == SIDE_SIZE)
+        .map(add_gaps)
+        .collect())
+}
+
+// turns the raw drive data of a side back into the image layout, dropping gaps and CRCs
+pub fn rebuild_side(side: &[u8]) -> Vec<u8> {
+    let mut blocks = Vec::with_capacity(SIDE_SIZE);
+    let mut position = 0;
+    let mut previous_size = 0;
+    loop {
+        while position < side.len() && side[position] != GAP_END_MARK {
+            position += 1;
+        }
+        position += 1;
+        if position >= side.len() {
+            break;
+        }
+        let length = match get_block_length(side[position], previous_size) {
+            Some(length) => length,
+            None => break,
+        };
+        if position + length > side.len() || blocks.len() + length > SIDE_SIZE {
+            break;
+        }
+        if side[position] == BLOCK_FILE_HEADER {
+            previous_size = (side[position + 13] as usize) | ((side[position + 14] as usize) << 8);
+        }
+        blocks.extend_from_slice(&side[position..position + length]);
+        position += length + CRC_SIZE;
+    }
+    blocks.resize(SIDE_SIZE, 0);
+    blocks
+}
+
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEADING_GAP];
+    let mut position = 0;
+    let mut previous_size = 0;
+    while position < side.len() {
+        let length = match get_block_length(side[position], previous_size) {
+            Some(length) => length,
+            None => break,
+        };
+        if position + length > side.len() {
+            break;
+        }
+        if side[position] == BLOCK_FILE_HEADER {
+            previous_size = (side[position + 13] as usize) | ((side[position + 14] as usize) << 8);
+        }
+        raw.push(GAP_END_MARK);
+        raw.extend_from_slice(&side[position..position + length]);
+        let crc = calculate_crc(&side[position..position + length]);
+        raw.push((crc & 0xff) as u8);
+        raw.push((crc >> 8) as u8);
+        raw.resize(raw.len() + BLOCK_GAP, 0);
+        position += length;
+    }
+    // leave room at the end for files the game writes
+    if raw.len() < LEADING_GAP + SIDE_SIZE {
+        raw.resize(LEADING_GAP + SIDE_SIZE, 0);
+    }
+    raw
+}
+
+// file data blocks take their size from the preceding file header
+fn get_block_length(block_type: u8, file_size: usize) -> Option<usize> {
+    match block_type {
+        BLOCK_DISK_INFO => Some(56),
+        BLOCK_FILE_AMOUNT => Some(2),
+        BLOCK_FILE_HEADER => Some(16),
+        BLOCK_FILE_DATA => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+// CRC-16 the drive appends to every block, including the gap end mark
+pub fn calculate_crc(block: &[u8]) -> u16 {
+    let mut crc = Crc16::new();
+    crc.update(GAP_END_MARK);
+    for byte in block {
+        crc.update(*byte);
+    }
+    crc.get_value()
+}
+
+pub struct Crc16 {
+    value: u16,
+}
+
+impl Crc16 {
+    pub fn new() -> Crc16 {
+        Crc16 {
+            value: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.value = 0;
+    }
+
+    pub fn update(&mut self, byte: u8) {
+        for bit in 0..8 {
+            let carry = (self.value & 0b1) != 0;
+            self.value >>= 1;
+            if carry {
+                self.value ^= 0x8408;
+            }
+            if ((byte >> bit) & 0b1) != 0 {
+                self.value ^= 0x8000;
+            }
+        }
+    }
+
+    // two more zero bytes flush the value out of the shift register
+    pub fn get_value(&self) -> u16 {
+        let mut crc = Crc16 { value: self.value };
+        crc.update(0);
+        crc.update(0);
+        crc.value
+    }
+}
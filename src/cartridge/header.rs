@@ -0,0 +1,220 @@
+use crate::cartridge::cartridge::Mirroring;
+
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+pub const PRG_ROM_UNIT: usize = 16 * 1024;
+pub const CHR_ROM_UNIT: usize = 8 * 1024;
+pub const PRG_RAM_UNIT: usize = 8 * 1024;
+
+const MAGIC: [u8; 4] = [0x4e, 0x45, 0x53, 0x1a];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    // runs on both, NTSC timing is used
+    Multi,
+    Dendy,
+}
+
+impl std::convert::From<u8> for Region {
+    fn from(timing: u8) -> Region {
+        match timing & 0b11 {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            2 => Region::Multi,
+            _ => Region::Dendy,
+        }
+    }
+}
+
+// default expansion devices from https://wiki.nesdev.com/w/index.php/NES_2.0#Default_Expansion_Device
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExpansionDevice {
+    Unspecified,
+    StandardControllers,
+    FourScore,
+    FamicomFourPlayersAdapter,
+    VsSystem,
+    Zapper,
+    PowerPad,
+    ArkanoidController,
+    FamilyBasicKeyboard,
+    Other(u8),
+}
+
+impl std::convert::From<u8> for ExpansionDevice {
+    fn from(device: u8) -> ExpansionDevice {
+        match device & 0x3f {
+            0x00 => ExpansionDevice::Unspecified,
+            0x01 => ExpansionDevice::StandardControllers,
+            0x02 => ExpansionDevice::FourScore,
+            0x03 => ExpansionDevice::FamicomFourPlayersAdapter,
+            0x04 | 0x05 => ExpansionDevice::VsSystem,
+            0x08 => ExpansionDevice::Zapper,
+            0x0b => ExpansionDevice::PowerPad,
+            0x0f => ExpansionDevice::ArkanoidController,
+            0x23 => ExpansionDevice::FamilyBasicKeyboard,
+            other => ExpansionDevice::Other(other),
+        }
+    }
+}
+
+impl std::convert::From<ExpansionDevice> for u8 {
+    fn from(device: ExpansionDevice) -> u8 {
+        match device {
+            ExpansionDevice::Unspecified => 0x00,
+            ExpansionDevice::StandardControllers => 0x01,
+            ExpansionDevice::FourScore => 0x02,
+            ExpansionDevice::FamicomFourPlayersAdapter => 0x03,
+            ExpansionDevice::VsSystem => 0x04,
+            ExpansionDevice::Zapper => 0x08,
+            ExpansionDevice::PowerPad => 0x0b,
+            ExpansionDevice::ArkanoidController => 0x0f,
+            ExpansionDevice::FamilyBasicKeyboard => 0x23,
+            ExpansionDevice::Other(other) => other,
+        }
+    }
+}
+
+pub struct Header {
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub nes2: bool,
+    pub region: Region,
+    pub expansion_device: ExpansionDevice,
+}
+
+impl Header {
+    // from https://wiki.nesdev.com/w/index.php/INES and https://wiki.nesdev.com/w/index.php/NES_2.0
+    pub fn parse(bytes: &[u8]) -> Result<Header, String> {
+        if bytes.len() < HEADER_SIZE || bytes[0..4] != MAGIC {
+            return Err(String::from("not an iNES file"));
+        }
+
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+        let nes2 = (flags7 & 0x0c) == 0x08;
+        let battery = (flags6 & 0x02) != 0;
+        let trainer = (flags6 & 0x04) != 0;
+        let mirroring = if (flags6 & 0x08) != 0 {
+            Mirroring::FourScreen
+        } else if (flags6 & 0x01) != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let mut mapper = ((flags7 & 0xf0) | (flags6 >> 4)) as u16;
+
+        if !nes2 {
+            // some old dumping tools wrote garbage such as "DiskDude!" to bytes 7-15
+            let dirty = bytes[12..16].iter().any(|&byte| byte != 0);
+            if dirty {
+                mapper &= 0x0f;
+            }
+            let prg_ram_units = if bytes[8] == 0 { 1 } else { bytes[8] as usize };
+            let chr_rom_size = bytes[5] as usize * CHR_ROM_UNIT;
+            return Ok(Header {
+                prg_rom_size: bytes[4] as usize * PRG_ROM_UNIT,
+                chr_rom_size,
+                prg_ram_size: if battery { 0 } else { prg_ram_units * PRG_RAM_UNIT },
+                prg_nvram_size: if battery { prg_ram_units * PRG_RAM_UNIT } else { 0 },
+                chr_ram_size: if chr_rom_size == 0 { CHR_ROM_UNIT } else { 0 },
+                chr_nvram_size: 0,
+                mapper,
+                submapper: 0,
+                mirroring,
+                battery,
+                trainer,
+                nes2,
+                // rarely set, most PAL dumps rely on a database instead
+                region: if !dirty && (bytes[9] & 0b1) != 0 { Region::Pal } else { Region::Ntsc },
+                expansion_device: ExpansionDevice::Unspecified,
+            });
+        }
+
+        mapper |= ((bytes[8] & 0x0f) as u16) << 8;
+        Ok(Header {
+            prg_rom_size: Header::rom_size("PRG", bytes[4], bytes[9] & 0x0f, PRG_ROM_UNIT, bytes.len())?,
+            chr_rom_size: Header::rom_size("CHR", bytes[5], bytes[9] >> 4, CHR_ROM_UNIT, bytes.len())?,
+            prg_ram_size: Header::ram_size(bytes[10] & 0x0f),
+            prg_nvram_size: Header::ram_size(bytes[10] >> 4),
+            chr_ram_size: Header::ram_size(bytes[11] & 0x0f),
+            chr_nvram_size: Header::ram_size(bytes[11] >> 4),
+            mapper,
+            submapper: bytes[8] >> 4,
+            mirroring,
+            battery,
+            trainer,
+            nes2,
+            region: Region::from(bytes[12]),
+            expansion_device: ExpansionDevice::from(bytes[15]),
+        })
+    }
+
+    pub fn get_prg_ram_total(&self) -> usize {
+        self.prg_ram_size + self.prg_nvram_size
+    }
+
+    pub fn get_chr_ram_total(&self) -> usize {
+        self.chr_ram_size + self.chr_nvram_size
+    }
+
+    // the exponent notation reaches sizes no file has, those are rejected
+    // before anything gets allocated for them
+    fn rom_size(name: &str, lsb: u8, msb: u8, unit: usize, file_size: usize) -> Result<usize, String> {
+        let size = if msb == 0x0f {
+            // exponent-multiplier notation: 2^E * (MM * 2 + 1)
+            let exponent = (lsb >> 2) as u32;
+            let multiplier = ((lsb & 0x03) as usize) * 2 + 1;
+            1usize.checked_shl(exponent).and_then(|power| power.checked_mul(multiplier))
+        } else {
+            (((msb as usize) << 8) | lsb as usize).checked_mul(unit)
+        };
+        match size {
+            Some(size) if size <= file_size => Ok(size),
+            _ => Err(format!("{} ROM size is larger than the file", name)),
+        }
+    }
+
+    fn ram_size(shift: u8) -> usize {
+        if shift == 0 { 0 } else { 64 << shift }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_nes2_header(prg_lsb: u8, size_msb: u8) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[prg_lsb, 0, 0, 0x08, 0, size_msb, 0, 0, 0, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn reads_exponent_rom_sizes() {
+        // 2^3 * 3 bytes
+        let mut bytes = build_nes2_header((3 << 2) | 1, 0x0f);
+        bytes.resize(HEADER_SIZE + 24, 0);
+        assert_eq!(Header::parse(&bytes).map(|header| header.prg_rom_size), Ok(24));
+    }
+
+    #[test]
+    fn rejects_rom_sizes_larger_than_the_file() {
+        let error = Err(String::from("PRG ROM size is larger than the file"));
+        // 2^63 * 7 overflows
+        assert_eq!(Header::parse(&build_nes2_header((63 << 2) | 3, 0x0f)).map(|header| header.prg_rom_size), error);
+        assert_eq!(Header::parse(&build_nes2_header((20 << 2) | 1, 0x0f)).map(|header| header.prg_rom_size), error);
+        assert_eq!(Header::parse(&build_nes2_header(0xff, 0x0e)).map(|header| header.prg_rom_size), error);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod header;
+pub mod cartridge;
+pub mod battery;
+pub mod database;
+pub mod unif;
+pub mod fds;
+pub mod nsf;
@@ -0,0 +1,365 @@
+use crate::cartridge::header::Region;
+
+// from https://wiki.nesdev.com/w/index.php/NSF and https://wiki.nesdev.com/w/index.php/NSFe
+const NSF_MAGIC: [u8; 5] = [0x4e, 0x45, 0x53, 0x4d, 0x1a];
+const NSFE_MAGIC: [u8; 4] = [0x4e, 0x53, 0x46, 0x45];
+const NSF_HEADER_SIZE: usize = 0x80;
+const NSF_STRING_SIZE: usize = 32;
+
+// expansion sound chip flags
+pub const CHIP_VRC6: u8 = 0x01;
+pub const CHIP_VRC7: u8 = 0x02;
+pub const CHIP_FDS: u8 = 0x04;
+pub const CHIP_MMC5: u8 = 0x08;
+pub const CHIP_NAMCO163: u8 = 0x10;
+pub const CHIP_SUNSOFT5B: u8 = 0x20;
+
+// play rates in microseconds used when the file leaves them out
+const DEFAULT_NTSC_SPEED: u16 = 16639;
+const DEFAULT_PAL_SPEED: u16 = 19997;
+
+pub struct NsfFile {
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub songs: u8,
+    // 0 based
+    pub starting_song: u8,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ripper: String,
+    // play routine period in microseconds
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    pub region: Region,
+    pub bank_init: [u8; 8],
+    pub chips: u8,
+    pub data: Vec<u8>,
+    // NSFe only, one entry per song when present
+    pub track_names: Vec<String>,
+    pub track_durations: Vec<Option<u32>>,
+    pub track_fades: Vec<Option<u32>>,
+    pub playlist: Vec<u8>,
+}
+
+impl NsfFile {
+    pub fn parse(bytes: &[u8]) -> Result<NsfFile, String> {
+        if is_nsfe(bytes) {
+            return parse_nsfe(bytes);
+        }
+        if !is_nsf(bytes) {
+            return Err(String::from("not an NSF or NSFe file"));
+        }
+        if bytes.len() <= NSF_HEADER_SIZE {
+            return Err(String::from("NSF file has no program data"));
+        }
+
+        let read_string = |offset: usize| read_string(&bytes[offset..offset + NSF_STRING_SIZE]);
+        let mut bank_init = [0; 8];
+        bank_init.copy_from_slice(&bytes[0x70..0x78]);
+        // NSF2 may end the program data early to make room for NSFe metadata
+        let data_length = read_u24(&bytes[0x7d..]);
+        let data_end = if data_length > 0 { (NSF_HEADER_SIZE + data_length).min(bytes.len()) } else { bytes.len() };
+
+        Ok(NsfFile {
+            load_address: read_u16(&bytes[0x08..]),
+            init_address: read_u16(&bytes[0x0a..]),
+            play_address: read_u16(&bytes[0x0c..]),
+            songs: bytes[0x06],
+            starting_song: bytes[0x07].saturating_sub(1),
+            title: read_string(0x0e),
+            artist: read_string(0x2e),
+            copyright: read_string(0x4e),
+            ripper: String::new(),
+            ntsc_speed: get_speed(read_u16(&bytes[0x6e..]), DEFAULT_NTSC_SPEED),
+            pal_speed: get_speed(read_u16(&bytes[0x78..]), DEFAULT_PAL_SPEED),
+            region: get_region(bytes[0x7a]),
+            bank_init,
+            chips: bytes[0x7b],
+            data: bytes[NSF_HEADER_SIZE..data_end].to_vec(),
+            track_names: Vec::new(),
+            track_durations: Vec::new(),
+            track_fades: Vec::new(),
+            playlist: Vec::new(),
+        })
+    }
+
+    // tunes with all bank registers at 0 are loaded as one block at the load address
+    pub fn is_bankswitched(&self) -> bool {
+        self.bank_init.iter().any(|bank| *bank != 0)
+    }
+
+    pub fn has_chip(&self, chip: u8) -> bool {
+        (self.chips & chip) != 0
+    }
+
+    pub fn get_track_name(&self, song: u8) -> Option<&str> {
+        self.track_names.get(song as usize).map(|name| name.as_str()).filter(|name| !name.is_empty())
+    }
+
+    // in milliseconds, without the fade out
+    pub fn get_track_duration(&self, song: u8) -> Option<u32> {
+        self.track_durations.get(song as usize).cloned().flatten()
+    }
+
+    pub fn get_track_fade(&self, song: u8) -> Option<u32> {
+        self.track_fades.get(song as usize).cloned().flatten()
+    }
+
+    // order the songs are meant to be played in, every song when there is no playlist
+    pub fn get_playlist(&self) -> Vec<u8> {
+        if self.playlist.is_empty() {
+            (0..self.songs).collect()
+        } else {
+            self.playlist.clone()
+        }
+    }
+}
+
+pub fn is_nsf(bytes: &[u8]) -> bool {
+    bytes.len() >= NSF_HEADER_SIZE && bytes[0..5] == NSF_MAGIC
+}
+
+pub fn is_nsfe(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && bytes[0..4] == NSFE_MAGIC
+}
+
+fn parse_nsfe(bytes: &[u8]) -> Result<NsfFile, String> {
+    let mut nsf = NsfFile {
+        load_address: 0,
+        init_address: 0,
+        play_address: 0,
+        songs: 1,
+        starting_song: 0,
+        title: String::new(),
+        artist: String::new(),
+        copyright: String::new(),
+        ripper: String::new(),
+        ntsc_speed: DEFAULT_NTSC_SPEED,
+        pal_speed: DEFAULT_PAL_SPEED,
+        region: Region::Ntsc,
+        bank_init: [0; 8],
+        chips: 0,
+        data: Vec::new(),
+        track_names: Vec::new(),
+        track_durations: Vec::new(),
+        track_fades: Vec::new(),
+        playlist: Vec::new(),
+    };
+    let mut has_info = false;
+
+    let mut position = NSFE_MAGIC.len();
+    loop {
+        if position + 8 > bytes.len() {
+            return Err(String::from("NSFe file is truncated, NEND chunk missing"));
+        }
+        let length = read_u32(&bytes[position..]) as usize;
+        let id = &bytes[position + 4..position + 8];
+        position += 8;
+        if position + length > bytes.len() {
+            return Err(format!("NSFe chunk {} is truncated", String::from_utf8_lossy(id)));
+        }
+        let chunk = &bytes[position..position + length];
+        position += length;
+
+        match id {
+            b"INFO" => {
+                if chunk.len() < 8 {
+                    return Err(String::from("NSFe INFO chunk is too short"));
+                }
+                nsf.load_address = read_u16(chunk);
+                nsf.init_address = read_u16(&chunk[2..]);
+                nsf.play_address = read_u16(&chunk[4..]);
+                nsf.region = get_region(chunk[6]);
+                nsf.chips = chunk[7];
+                // song count and starting song are optional
+                nsf.songs = chunk.get(8).cloned().unwrap_or(1);
+                nsf.starting_song = chunk.get(9).cloned().unwrap_or(0);
+                has_info = true;
+            }
+            b"DATA" => nsf.data = chunk.to_vec(),
+            b"BANK" => {
+                let length = chunk.len().min(8);
+                nsf.bank_init[..length].copy_from_slice(&chunk[..length]);
+            }
+            b"RATE" => {
+                if chunk.len() >= 2 {
+                    nsf.ntsc_speed = get_speed(read_u16(chunk), DEFAULT_NTSC_SPEED);
+                }
+                if chunk.len() >= 4 {
+                    nsf.pal_speed = get_speed(read_u16(&chunk[2..]), DEFAULT_PAL_SPEED);
+                }
+            }
+            b"NEND" => break,
+            b"auth" => {
+                let mut strings = read_strings(chunk).into_iter();
+                nsf.title = strings.next().unwrap_or_default();
+                nsf.artist = strings.next().unwrap_or_default();
+                nsf.copyright = strings.next().unwrap_or_default();
+                nsf.ripper = strings.next().unwrap_or_default();
+            }
+            b"tlbl" => nsf.track_names = read_strings(chunk),
+            b"time" => nsf.track_durations = read_times(chunk),
+            b"fade" => nsf.track_fades = read_times(chunk),
+            b"plst" => nsf.playlist = chunk.to_vec(),
+            // chunks starting with an upper case letter must be understood to play the file
+            _ if id[0].is_ascii_uppercase() => {
+                return Err(format!("unsupported NSFe chunk {}", String::from_utf8_lossy(id)));
+            }
+            _ => {}
+        }
+    }
+
+    if !has_info || nsf.data.is_empty() {
+        return Err(String::from("NSFe file is missing its INFO or DATA chunk"));
+    }
+    Ok(nsf)
+}
+
+fn get_region(flags: u8) -> Region {
+    if (flags & 0b10) != 0 {
+        Region::Multi
+    } else if (flags & 0b01) != 0 {
+        Region::Pal
+    } else {
+        Region::Ntsc
+    }
+}
+
+fn get_speed(speed: u16, default: u16) -> u16 {
+    if speed == 0 { default } else { speed }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16) | ((bytes[1] as u16) << 8)
+}
+
+fn read_u24(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) | ((bytes[1] as usize) << 8) | ((bytes[2] as usize) << 16)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) | ((bytes[1] as u32) << 8) | ((bytes[2] as u32) << 16) | ((bytes[3] as u32) << 24)
+}
+
+// zero terminated, or filling the whole field
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn read_strings(bytes: &[u8]) -> Vec<String> {
+    let mut strings: Vec<String> = bytes.split(|byte| *byte == 0).map(read_string).collect();
+    // the last terminator leaves an empty piece behind
+    if bytes.last() == Some(&0) {
+        strings.pop();
+    }
+    strings
+}
+
+// signed milliseconds, negative for unknown
+fn read_times(bytes: &[u8]) -> Vec<Option<u32>> {
+    bytes.chunks(4)
+        .filter(|time| time.len() == 4)
+        .map(|time| {
+            let time = read_u32(time) as i32;
+            if time < 0 { None } else { Some(time as u32) }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // an NSF header in front of the program data, leaving the speeds at 0
+    pub fn create_nsf(load_address: u16, init_address: u16, play_address: u16, bank_init: [u8; 8], chips: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; NSF_HEADER_SIZE];
+        bytes[..5].copy_from_slice(&NSF_MAGIC);
+        bytes[0x05] = 1;
+        bytes[0x06] = 3;
+        bytes[0x07] = 2;
+        for (offset, address) in [(0x08, load_address), (0x0a, init_address), (0x0c, play_address)] {
+            bytes[offset..offset + 2].copy_from_slice(&address.to_le_bytes());
+        }
+        bytes[0x0e..0x13].copy_from_slice(b"Title");
+        // a field filled to the end has no terminator
+        bytes[0x2e..0x4e].copy_from_slice(&[b'A'; NSF_STRING_SIZE]);
+        bytes[0x70..0x78].copy_from_slice(&bank_init);
+        bytes[0x7b] = chips;
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = (data.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(id);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn parses_nsf_headers() {
+        let mut bytes = create_nsf(0x8000, 0x8003, 0x8006, [0, 0, 0, 0, 0, 0, 0, 0], CHIP_VRC6 | CHIP_FDS, &[1, 2, 3, 4]);
+        bytes[0x6e..0x70].copy_from_slice(&10000_u16.to_le_bytes());
+        bytes[0x7a] = 0b01;
+        let nsf = NsfFile::parse(&bytes).unwrap();
+        assert_eq!((nsf.load_address, nsf.init_address, nsf.play_address), (0x8000, 0x8003, 0x8006));
+        assert_eq!((nsf.songs, nsf.starting_song), (3, 1));
+        assert_eq!((nsf.title.as_str(), nsf.artist.len(), nsf.copyright.as_str()), ("Title", NSF_STRING_SIZE, ""));
+        assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (10000, DEFAULT_PAL_SPEED));
+        assert_eq!(nsf.region, Region::Pal);
+        assert!(nsf.has_chip(CHIP_FDS) && !nsf.has_chip(CHIP_NAMCO163));
+        assert!(!nsf.is_bankswitched());
+        assert_eq!(nsf.data, [1, 2, 3, 4]);
+        assert_eq!(nsf.get_playlist(), [0, 1, 2]);
+
+        // NSF2 program data stops at its length, metadata follows
+        bytes[0x7d] = 2;
+        assert_eq!(NsfFile::parse(&bytes).unwrap().data, [1, 2]);
+
+        assert!(NsfFile::parse(&bytes[..NSF_HEADER_SIZE]).is_err());
+        bytes[0] = 0;
+        assert!(NsfFile::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn parses_nsfe_chunks() {
+        let mut bytes = NSFE_MAGIC.to_vec();
+        bytes.extend(chunk(b"INFO", &[0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0b10, CHIP_NAMCO163, 2, 1]));
+        bytes.extend(chunk(b"BANK", &[0, 1, 2]));
+        bytes.extend(chunk(b"RATE", &10000_u16.to_le_bytes()));
+        bytes.extend(chunk(b"auth", b"Title\0Artist\0\0Ripper\0"));
+        bytes.extend(chunk(b"tlbl", b"First\0\0"));
+        bytes.extend(chunk(b"time", &[&90000_i32.to_le_bytes()[..], &(-1_i32).to_le_bytes()].concat()));
+        bytes.extend(chunk(b"fade", &5000_i32.to_le_bytes()));
+        bytes.extend(chunk(b"plst", &[1, 0]));
+        // lower case chunks may be skipped
+        bytes.extend(chunk(b"xtra", &[0xff]));
+        bytes.extend(chunk(b"DATA", &[0xea; 16]));
+        bytes.extend(chunk(b"NEND", &[]));
+
+        let nsf = NsfFile::parse(&bytes).unwrap();
+        assert_eq!((nsf.load_address, nsf.init_address, nsf.play_address), (0x8000, 0x8003, 0x8006));
+        assert_eq!((nsf.region, nsf.chips, nsf.songs, nsf.starting_song), (Region::Multi, CHIP_NAMCO163, 2, 1));
+        assert_eq!(nsf.bank_init, [0, 1, 2, 0, 0, 0, 0, 0]);
+        assert!(nsf.is_bankswitched());
+        assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (10000, DEFAULT_PAL_SPEED));
+        assert_eq!((nsf.title.as_str(), nsf.artist.as_str(), nsf.copyright.as_str(), nsf.ripper.as_str()),
+                   ("Title", "Artist", "", "Ripper"));
+        assert_eq!((nsf.get_track_name(0), nsf.get_track_name(1)), (Some("First"), None));
+        assert_eq!((nsf.get_track_duration(0), nsf.get_track_duration(1)), (Some(90000), None));
+        assert_eq!((nsf.get_track_fade(0), nsf.get_track_fade(1)), (Some(5000), None));
+        assert_eq!(nsf.get_playlist(), [1, 0]);
+        assert_eq!(nsf.data.len(), 16);
+
+        // chunks past the end are never reached
+        let end = bytes.len() - 8;
+        assert!(NsfFile::parse(&bytes[..end]).err().unwrap().contains("NEND"));
+        assert!(NsfFile::parse(&bytes[..end - 4]).err().unwrap().contains("DATA"));
+        let mut unknown = bytes[..end].to_vec();
+        unknown.extend(chunk(b"VRC7", &[]));
+        assert!(NsfFile::parse(&unknown).err().unwrap().contains("VRC7"));
+    }
+}
@@ -0,0 +1,197 @@
+use crate::cartridge::cartridge::Mirroring;
+use crate::cartridge::header::{Header, Region, ExpansionDevice, CHR_ROM_UNIT, PRG_RAM_UNIT};
+
+// from https://wiki.nesdev.com/w/index.php/UNIF
+const MAGIC: [u8; 4] = [0x55, 0x4e, 0x49, 0x46];
+const HEADER_SIZE: usize = 32;
+const CHUNK_HEADER_SIZE: usize = 8;
+
+// prefixes only tell who made the board, the rest names the circuit
+const BOARD_PREFIXES: [&str; 7] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-", "KONAMI-"];
+
+// board name, mapper, submapper, PRG RAM in 8KB units
+const BOARDS: [(&str, u16, u8, usize); 46] = [
+    ("NROM", 0, 0, 1),       ("NROM-128", 0, 0, 1),   ("NROM-256", 0, 0, 1),   ("RROM", 0, 0, 0),
+    ("RROM-128", 0, 0, 0),   ("SAROM", 1, 0, 1),      ("SBROM", 1, 0, 0),      ("SCROM", 1, 0, 0),
+    ("SEROM", 1, 0, 0),      ("SGROM", 1, 0, 0),      ("SKROM", 1, 0, 1),      ("SLROM", 1, 0, 0),
+    ("SL1ROM", 1, 0, 0),     ("SNROM", 1, 0, 1),      ("SOROM", 1, 0, 2),      ("SUROM", 1, 0, 1),
+    ("SXROM", 1, 0, 4),      ("UNROM", 2, 2, 0),      ("UOROM", 2, 2, 0),      ("UN1ROM", 2, 0, 0),
+    ("CNROM", 3, 2, 0),      ("TBROM", 4, 0, 0),      ("TEROM", 4, 0, 0),      ("TFROM", 4, 0, 0),
+    ("TGROM", 4, 0, 0),      ("TKROM", 4, 0, 1),      ("TLROM", 4, 0, 0),      ("TL1ROM", 4, 0, 0),
+    ("TNROM", 4, 0, 1),      ("TR1ROM", 4, 0, 0),     ("TSROM", 4, 0, 1),      ("TVROM", 4, 0, 0),
+    ("HKROM", 4, 1, 0),      ("AMROM", 7, 2, 0),      ("ANROM", 7, 1, 0),      ("AN1ROM", 7, 1, 0),
+    ("AOROM", 7, 0, 0),      ("PNROM", 9, 0, 0),      ("PEEOROM", 9, 0, 0),    ("FJROM", 10, 0, 1),
+    ("FKROM", 10, 0, 1),     ("GNROM", 66, 0, 0),     ("MHROM", 66, 0, 0),     ("JLROM", 69, 0, 0),
+    ("JSROM", 69, 0, 1),     ("BTR", 69, 0, 1),
+];
+
+// unlicensed boards and multicarts, named without their UNL- or BMC- prefix, from
+// https://wiki.nesdev.com/w/index.php/UNIF_to_NES_2.0_Mapping. Most need mappers
+// this emulator lacks, knowing their number lets the error say which.
+const UNLICENSED_BOARDS: [(&str, u16, u8, usize); 60] = [
+    ("UNROM-512-8", 30, 0, 0),        ("UNROM-512-16", 30, 0, 0),       ("UNROM-512-32", 30, 0, 0),
+    ("SUNSOFT_UNROM", 93, 0, 0),      ("SA-NROM", 143, 0, 0),           ("SA-72007", 145, 0, 0),
+    ("SA-72008", 133, 0, 0),          ("SA-0036", 149, 0, 0),           ("SA-0037", 148, 0, 0),
+    ("SA-016-1M", 146, 0, 0),         ("SA-009", 160, 0, 0),            ("SA-002", 136, 0, 0),
+    ("TC-U01-1.5M", 147, 0, 0),       ("SACHEN-8259A", 141, 0, 0),      ("SACHEN-8259B", 138, 0, 0),
+    ("SACHEN-8259C", 139, 0, 0),      ("SACHEN-8259D", 137, 0, 0),      ("SACHEN-74LS374N", 150, 0, 0),
+    ("SL1632", 14, 0, 0),             ("SL12", 116, 0, 0),              ("TEK90", 90, 0, 0),
+    ("H2288", 123, 0, 0),             ("22211", 132, 0, 0),             ("8237", 215, 0, 0),
+    ("8237A", 215, 1, 0),             ("AC08", 42, 0, 0),               ("SMB2J", 304, 0, 0),
+    ("KOF97", 263, 0, 0),             ("CC-21", 27, 0, 0),              ("TF1201", 298, 0, 0),
+    ("SHERO", 262, 0, 0),             ("KS7032", 142, 0, 0),            ("CITYFIGHT", 266, 0, 0),
+    ("MARIO1-MALEE2", 55, 0, 0),      ("AX5705", 530, 0, 0),            ("VRC7", 85, 0, 0),
+    ("GK-192", 58, 0, 0),             ("SC-127", 35, 0, 0),             ("D1038", 59, 0, 0),
+    ("A65AS", 285, 0, 0),             ("SUPERVISION16IN1", 53, 0, 0),   ("SUPER24IN1SC03", 176, 0, 0),
+    ("FK23C", 176, 0, 0),             ("42IN1RESETSWITCH", 226, 0, 0),  ("GHOSTBUSTERS63IN1", 226, 0, 0),
+    ("64IN1NOREPEAT", 314, 0, 0),     ("70IN1", 236, 0, 0),             ("190IN1", 300, 0, 0),
+    ("NTD-03", 290, 0, 0),            ("8157", 301, 0, 0),              ("11160", 299, 0, 0),
+    ("12-IN-1", 331, 0, 0),           ("BS-5", 286, 0, 0),              ("411120-C", 287, 0, 0),
+    ("810544-C-A1", 261, 0, 0),       ("GS-2004", 283, 0, 0),           ("GS-2013", 283, 0, 0),
+    ("T-262", 265, 0, 0),             ("JC-016-2", 205, 0, 0),          ("SUPERHIK8IN1", 45, 0, 0),
+];
+
+pub fn is_unif(bytes: &[u8]) -> bool {
+    bytes.len() >= HEADER_SIZE && bytes[0..4] == MAGIC
+}
+
+// returns the same header, PRG ROM and CHR ROM the iNES loader produces,
+// and the board name for errors about the mapper
+pub fn parse(bytes: &[u8]) -> Result<(Header, Vec<u8>, Vec<u8>, String), String> {
+    if !is_unif(bytes) {
+        return Err(String::from("not a UNIF file"));
+    }
+
+    let mut board: Option<String> = None;
+    let mut prg_chunks: Vec<Option<&[u8]>> = vec![None; 16];
+    let mut chr_chunks: Vec<Option<&[u8]>> = vec![None; 16];
+    let mut mirroring_type: Option<u8> = None;
+    let mut battery = false;
+    let mut region = Region::Ntsc;
+
+    let mut position = HEADER_SIZE;
+    while position + CHUNK_HEADER_SIZE <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let length = u32::from_le_bytes([
+            bytes[position + 4], bytes[position + 5], bytes[position + 6], bytes[position + 7],
+        ]) as usize;
+        let start = position + CHUNK_HEADER_SIZE;
+        let end = start + length;
+        if end > bytes.len() {
+            return Err(format!("UNIF chunk {} is truncated", String::from_utf8_lossy(id)));
+        }
+        let chunk = &bytes[start..end];
+
+        match id {
+            b"MAPR" => {
+                let name = chunk.split(|&byte| byte == 0).next().unwrap_or(&[]);
+                board = Some(String::from_utf8_lossy(name).trim().to_string());
+            }
+            b"MIRR" if !chunk.is_empty() => { mirroring_type = Some(chunk[0]); }
+            b"BATR" => { battery = chunk.first().is_none_or(|&value| value != 0); }
+            b"TVCI" if !chunk.is_empty() => {
+                region = match chunk[0] {
+                    1 => Region::Pal,
+                    2 => Region::Multi,
+                    _ => Region::Ntsc,
+                };
+            }
+            _ => {
+                // PRG0-PRGF and CHR0-CHRF, numbered in hexadecimal
+                let index = (id[3] as char).to_digit(16).map(|digit| digit as usize);
+                match (&id[0..3], index) {
+                    (b"PRG", Some(index)) => { prg_chunks[index] = Some(chunk); }
+                    (b"CHR", Some(index)) => { chr_chunks[index] = Some(chunk); }
+                    _ => {}
+                }
+            }
+        }
+        position = end;
+    }
+
+    let board = board.ok_or_else(|| String::from("UNIF file has no MAPR chunk"))?;
+    let (mapper, submapper, prg_ram_units) = find_board(&board)
+        .ok_or_else(|| format!("UNIF board {} is not supported", board))?;
+    let prg_rom: Vec<u8> = prg_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+    let chr_rom: Vec<u8> = chr_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+    if prg_rom.is_empty() {
+        return Err(String::from("UNIF file has no PRG chunks"));
+    }
+
+    // boards switching mirroring themselves use MIRR 5 and ignore it
+    let mirroring = match mirroring_type {
+        Some(1) => Mirroring::Vertical,
+        Some(2) => Mirroring::SingleScreenA,
+        Some(3) => Mirroring::SingleScreenB,
+        Some(4) => Mirroring::FourScreen,
+        _ => Mirroring::Horizontal,
+    };
+    // some dumps have a battery without the board needing one in the table
+    let prg_ram_units = if battery { prg_ram_units.max(1) } else { prg_ram_units };
+    let prg_ram_size = prg_ram_units * PRG_RAM_UNIT;
+
+    let header = Header {
+        prg_rom_size: prg_rom.len(),
+        chr_rom_size: chr_rom.len(),
+        prg_ram_size: if battery { 0 } else { prg_ram_size },
+        prg_nvram_size: if battery { prg_ram_size } else { 0 },
+        chr_ram_size: if chr_rom.is_empty() { CHR_ROM_UNIT } else { 0 },
+        chr_nvram_size: 0,
+        mapper,
+        submapper,
+        mirroring,
+        battery,
+        trainer: false,
+        nes2: false,
+        region,
+        expansion_device: ExpansionDevice::Unspecified,
+    };
+    Ok((header, prg_rom, chr_rom, board))
+}
+
+fn find_board(name: &str) -> Option<(u16, u8, usize)> {
+    let name = name.to_uppercase();
+    let stripped = BOARD_PREFIXES.iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(&name);
+    BOARDS.iter()
+        .chain(UNLICENSED_BOARDS.iter())
+        .find(|board| board.0 == stripped)
+        .map(|board| (board.1, board.2, board.3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::cartridge::Cartridge;
+
+    fn build_unif(board: &str) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.resize(HEADER_SIZE, 0);
+        for (id, chunk) in [(b"MAPR", board.as_bytes().to_vec()), (b"PRG0", vec![0xea; 0x8000])] {
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&chunk);
+        }
+        bytes
+    }
+
+    #[test]
+    fn unlicensed_prefixes_are_stripped() {
+        let (header, _, _, board) = parse(&build_unif("UNL-UNROM")).unwrap();
+        assert_eq!((header.mapper, header.submapper), (2, 2));
+        assert_eq!(board, "UNL-UNROM");
+
+        let (header, _, _, _) = parse(&build_unif("UNL-Sachen-8259A")).unwrap();
+        assert_eq!(header.mapper, 141);
+    }
+
+    #[test]
+    fn errors_name_the_board() {
+        let error = Cartridge::from_bytes(&build_unif("UNL-SA-NROM")).err().unwrap();
+        assert_eq!(error, "UNIF board UNL-SA-NROM: mapper 143 is not supported");
+
+        let error = Cartridge::from_bytes(&build_unif("BMC-Unheard-Of")).err().unwrap();
+        assert_eq!(error, "UNIF board BMC-Unheard-Of is not supported");
+    }
+}
@@ -0,0 +1,56 @@
+// standard controller, from https://wiki.nesdev.com/w/index.php/Standard_controller
+// buttons in the order the shift register reports them
+pub const BUTTON_A: u8 = 0x01;
+pub const BUTTON_B: u8 = 0x02;
+pub const BUTTON_SELECT: u8 = 0x04;
+pub const BUTTON_START: u8 = 0x08;
+pub const BUTTON_UP: u8 = 0x10;
+pub const BUTTON_DOWN: u8 = 0x20;
+pub const BUTTON_LEFT: u8 = 0x40;
+pub const BUTTON_RIGHT: u8 = 0x80;
+
+// the upper bits of $4016/$4017 are open bus, usually the $40 of the address
+const OPEN_BUS: u8 = 0x40;
+
+pub struct Controller {
+    // pressed buttons, set by the frontend
+    buttons: u8,
+    strobe: bool,
+    shift_register: u8,
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller {
+            buttons: 0,
+            strobe: false,
+            shift_register: 0,
+        }
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift_register = buttons;
+        }
+    }
+
+    // bit 0 of $4016 writes, the register keeps reloading while it is high
+    pub fn write_strobe(&mut self, value: u8) {
+        self.strobe = (value & 0b1) != 0;
+        if self.strobe {
+            self.shift_register = self.buttons;
+        }
+    }
+
+    // each read clocks the next button out, official controllers report 1
+    // once all 8 have been read
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return OPEN_BUS | (self.buttons & 0b1);
+        }
+        let bit = self.shift_register & 0b1;
+        self.shift_register = (self.shift_register >> 1) | 0x80;
+        OPEN_BUS | bit
+    }
+}
@@ -1,13 +1,51 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::cpu::registers::{Registers, RegisterType, FlagsRegister};
-use crate::cpu::instructions::{Instruction, InstructionType, AddressingMode};
+use crate::cpu::instructions::{Instruction, InstructionReader, InstructionType, AddressingMode};
 use crate::memory::Memory;
 
+const NMI_VECTOR: u16 = 0xfffa;
+const RESET_VECTOR: u16 = 0xfffc;
+const IRQ_VECTOR: u16 = 0xfffe;
+const INTERRUPT_CYCLES: u8 = 7;
+
+// base cycles per opcode, from https://wiki.nesdev.com/w/index.php/6502_cycle_times
+// page crossings and taken branches add to these
+const OPCODE_CYCLES: [u8; 256] = [
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+];
+
 pub struct CPU {
     registers: Registers,
     flags_register: Rc<RefCell<FlagsRegister>>,
-    memory: Rc<RefCell<Memory>>
+    memory: Rc<RefCell<Memory>>,
+    instruction_reader: InstructionReader,
+    // cycles added by the current instruction on top of its base cycles
+    extra_cycles: u8,
+    page_crossed: bool,
+    cycles: u64,
+    // while an instruction runs, each bus access first catches the rest of the
+    // bus up to the cycle it happens on
+    in_step: bool,
+    accesses: u8,
+    bus_cycles: u8,
+    // cycles DMA took from the current step
+    stolen_cycles: u16,
 }
 
 impl CPU {
@@ -18,54 +56,128 @@ impl CPU {
             registers,
             flags_register,
             memory,
+            instruction_reader: InstructionReader::new(),
+            extra_cycles: 0,
+            page_crossed: false,
+            cycles: 0,
+            in_step: false,
+            accesses: 0,
+            bus_cycles: 0,
+            stolen_cycles: 0,
+        }
+    }
+
+    // from https://wiki.nesdev.com/w/index.php/CPU_power_up_state
+    pub fn reset(&mut self) {
+        self.registers.set_register(RegisterType::S, 0xfd);
+        (*self.flags_register.borrow_mut()).set_interrupt(true);
+        let pc = self.read_word(RESET_VECTOR);
+        self.registers.set_pc(pc);
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.registers.get_pc()
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.registers.set_pc(pc);
+    }
+
+    pub fn get_register(&self, register: RegisterType) -> u8 {
+        self.registers.get_register(register)
+    }
+
+    pub fn set_register(&mut self, register: RegisterType, value: u8) {
+        self.registers.set_register(register, value);
+    }
+
+    // total cycles run since power on
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // fetches, decodes and executes the instruction at PC, running the rest of
+    // the bus alongside, then takes a pending interrupt. Returns the cycles taken.
+    pub fn step(&mut self) -> u16 {
+        self.start_bus_cycles();
+        self.stolen_cycles = 0;
+        let pc = self.registers.get_pc();
+        let opcode = self.read_byte(pc);
+        let length = self.instruction_reader.get_length(opcode);
+        let low = if length > 1 { self.read_byte(pc.wrapping_add(1)) } else { 0 };
+        let high = if length > 2 { self.read_byte(pc.wrapping_add(2)) } else { 0 };
+        self.registers.change_pc(length);
+
+        self.extra_cycles = 0;
+        self.page_crossed = false;
+        if let Some(instruction) = self.instruction_reader.decode(opcode, low, high) {
+            let has_penalty = Self::has_page_cross_penalty(instruction.get_value());
+            self.execute(instruction);
+            if has_penalty && self.page_crossed {
+                self.extra_cycles += 1;
+            }
         }
+
+        // interrupt lines are polled before the last cycle of the instruction,
+        // from https://wiki.nesdev.com/w/index.php/CPU_interrupts
+        let instruction_cycles = OPCODE_CYCLES[opcode as usize] + self.extra_cycles;
+        self.run_bus_cycles(instruction_cycles - 1);
+        let (nmi, irq) = {
+            let memory = self.memory.borrow();
+            ((*memory).get_nmi(), (*memory).get_irq())
+        };
+        self.run_bus_cycles(instruction_cycles);
+
+        let mut cycles = instruction_cycles as u16;
+        if nmi {
+            (*self.memory.borrow_mut()).acknowledge_nmi();
+            cycles += self.interrupt(NMI_VECTOR) as u16;
+        } else if irq && !(*self.flags_register.borrow()).get_interrupt() {
+            cycles += self.interrupt(IRQ_VECTOR) as u16;
+        }
+        self.in_step = false;
+        cycles += self.stolen_cycles;
+        self.cycles += cycles as u64;
+        cycles
     }
 
     pub fn execute(&mut self, instruction: Instruction) {
-        let (address_value, address) = self.resolve_addressing_mode(instruction.get_address());
-        match instruction.get_value() {
+        let mode = instruction.get_address();
+        let instruction_type = instruction.get_value();
+        let (address_value, address) = self.resolve_addressing_mode(mode, instruction_type);
+        match instruction_type {
             // Load/Store Operations
             InstructionType::LDA => {
                 self.load(RegisterType::A, address_value);
-                self.pc_inc();
             }
             InstructionType::LDX => {
                 self.load(RegisterType::X, address_value);
-                self.pc_inc();
             }
             InstructionType::LDY => {
                 self.load(RegisterType::Y, address_value);
-                self.pc_inc();
             }
             InstructionType::STA => {
                 self.store(RegisterType::A, address);
-                self.pc_inc();
             }
             InstructionType::STX => {
                 self.store(RegisterType::X, address);
-                self.pc_inc();
             }
             InstructionType::STY => {
                 self.store(RegisterType::Y, address);
-                self.pc_inc();
             }
 
             // Register Transfers
             InstructionType::TAX => {
                 self.transfer(RegisterType::A, RegisterType::X);
-                self.pc_inc();
             }
             InstructionType::TAY => {
                 self.transfer(RegisterType::A, RegisterType::Y);
-                self.pc_inc();
             }
             InstructionType::TXA => {
                 self.transfer(RegisterType::X, RegisterType::A);
-                self.pc_inc();
             }
             InstructionType::TYA => {
                 self.transfer(RegisterType::Y, RegisterType::A);
-                self.pc_inc();
             }
 
             // Stack Operations
@@ -74,34 +186,35 @@ impl CPU {
                 self.registers.set_register(RegisterType::X, stack_pointer);
                 (*self.flags_register.borrow_mut()).set_zero(stack_pointer == 0);
                 (*self.flags_register.borrow_mut()).set_negative(((stack_pointer >> 7) & 0b1) == 1);
-                self.pc_inc();
             }
             InstructionType::TXS => {
                 let value = self.registers.get_register(RegisterType::X);
                 self.registers.set_register(RegisterType::S, value);
-                self.pc_inc();
             }
+            // pushes follow a dummy read of the next byte, pulls also a cycle
+            // incrementing S
             InstructionType::PHA => {
+                self.dummy_cycles(1);
                 let value = self.registers.get_register(RegisterType::A);
                 self.push(value);
-                self.pc_inc();
             }
             InstructionType::PHP => {
-                let status_flags: u8 = *self.flags_register.borrow_mut();
+                // B and bit 5 are set on the pushed copy
+                let status_flags: u8 = u8::from(&*self.flags_register.borrow()) | 0x30;
+                self.dummy_cycles(1);
                 self.push(status_flags);
-                self.pc_inc();
             }
             InstructionType::PLA => {
+                self.dummy_cycles(2);
                 let value = self.pop();
                 self.registers.set_register(RegisterType::A, value);
                 (*self.flags_register.borrow_mut()).set_zero(value == 0);
                 (*self.flags_register.borrow_mut()).set_negative(((value >> 7) & 0b1) == 1);
-                self.pc_inc();
             }
             InstructionType::PLP => {
+                self.dummy_cycles(2);
                 let value = self.pop();
                 (*self.flags_register.borrow_mut()).load(value);
-                self.pc_inc();
             }
 
             // Logical
@@ -111,7 +224,6 @@ impl CPU {
                     address_value
                 );
                 self.registers.set_register(RegisterType::A, and_result);
-                self.pc_inc();
             }
             InstructionType::EOR => {
                 let xor_result = self.logical_xor(
@@ -119,7 +231,6 @@ impl CPU {
                     address_value
                 );
                 self.registers.set_register(RegisterType::A, xor_result);
-                self.pc_inc();
             }
             InstructionType::ORA => {
                 let or_result = self.logical_or(
@@ -127,14 +238,12 @@ impl CPU {
                     address_value
                 );
                 self.registers.set_register(RegisterType::A, or_result);
-                self.pc_inc();
             }
             InstructionType::BIT => {
                 self.logical_bit_test(
                     self.registers.get_register(RegisterType::A),
                     address_value
                 );
-                self.pc_inc();
             }
 
             // Arithmetic
@@ -144,7 +253,6 @@ impl CPU {
                     address_value
                 );
                 self.registers.set_register(RegisterType::A, add_result);
-                self.pc_inc();
             }
             InstructionType::SBC => {
                 let sub_result = self.arithmetic_sub(
@@ -152,78 +260,68 @@ impl CPU {
                     address_value
                 );
                 self.registers.set_register(RegisterType::A, sub_result);
-                self.pc_inc();
             }
             InstructionType::CMP => {
                 self.arithmetic_cmp(
                     self.registers.get_register(RegisterType::A),
                     address_value
                 );
-                self.pc_inc();
             }
             InstructionType::CPX => {
                 self.arithmetic_cmp(
                     self.registers.get_register(RegisterType::X),
                     address_value
                 );
-                self.pc_inc();
             }
             InstructionType::CPY => {
                 self.arithmetic_cmp(
                     self.registers.get_register(RegisterType::Y),
                     address_value
                 );
-                self.pc_inc();
             }
 
             // Increments & Decrements
             InstructionType::INC => {
                 let increment_result = self.increment(address_value);
-                (*self.memory.borrow_mut()).set_byte(address, increment_result);
-                self.pc_inc();
+                self.read_modify_write(address, address_value, increment_result);
             }
             InstructionType::INX => {
                 let increment_result = self.increment(self.registers.get_register(RegisterType::X));
                 self.registers.set_register(RegisterType::X, increment_result);
-                self.pc_inc();
             }
             InstructionType::INY => {
                 let increment_result = self.increment(self.registers.get_register(RegisterType::Y));
                 self.registers.set_register(RegisterType::Y, increment_result);
-                self.pc_inc();
             }
             InstructionType::DEC => {
                 let decrement_result = self.decrement(address_value);
-                (*self.memory.borrow_mut()).set_byte(address, decrement_result);
-                self.pc_inc();
+                self.read_modify_write(address, address_value, decrement_result);
             }
             InstructionType::DEX => {
                 let decrement_result = self.decrement(self.registers.get_register(RegisterType::X));
                 self.registers.set_register(RegisterType::X, decrement_result);
-                self.pc_inc();
             }
             InstructionType::DEY => {
                 let decrement_result = self.decrement(self.registers.get_register(RegisterType::Y));
                 self.registers.set_register(RegisterType::Y, decrement_result);
-                self.pc_inc();
             }
-            
+
             // Shifts
             InstructionType::ASL => {
-                // acc or mem
-                self.pc_inc();
+                let shift_result = self.arithmetic_shift_left(address_value);
+                self.write_shift_result(mode, address, address_value, shift_result);
             }
             InstructionType::LSR => {
-                // acc or mem
-                self.pc_inc();
+                let shift_result = self.logical_shift_right(address_value);
+                self.write_shift_result(mode, address, address_value, shift_result);
             }
             InstructionType::ROL => {
-                // acc or mem
-                self.pc_inc();
+                let shift_result = self.rotate_left(address_value);
+                self.write_shift_result(mode, address, address_value, shift_result);
             }
             InstructionType::ROR => {
-                // acc or mem
-                self.pc_inc();
+                let shift_result = self.rotate_right(address_value);
+                self.write_shift_result(mode, address, address_value, shift_result);
             }
 
             // Jumps & Calls
@@ -231,7 +329,8 @@ impl CPU {
                 self.registers.set_pc(address);
             }
             InstructionType::JSR => {
-                let pc = self.registers.get_pc();
+                // the pushed address is the last byte of the JSR itself
+                let pc = self.registers.get_pc().wrapping_sub(1);
                 let msb = ((pc & 0xff00) >> 8) as u8;
                 let lsb = (pc & 0x00ff) as u8;
                 self.push(msb);
@@ -239,36 +338,45 @@ impl CPU {
                 self.registers.set_pc(address);
             }
             InstructionType::RTS => {
+                self.dummy_cycles(2);
                 let lsb = self.pop() as u16;
                 let msb = self.pop() as u16;
                 let address = msb * 256 + lsb;
-                self.registers.set_pc(address);
+                self.registers.set_pc(address.wrapping_add(1));
             }
 
             // Branch
             InstructionType::BCC => {
-                self.branch(address, !(*self.flags_register.borrow_mut()).get_carry());
+                let flag = !(*self.flags_register.borrow()).get_carry();
+                self.branch(address, flag);
             }
             InstructionType::BCS => {
-                self.branch(address, (*self.flags_register.borrow_mut()).get_carry());
+                let flag = (*self.flags_register.borrow()).get_carry();
+                self.branch(address, flag);
             }
             InstructionType::BEQ => {
-                self.branch(address, (*self.flags_register.borrow_mut()).get_zero());
+                let flag = (*self.flags_register.borrow()).get_zero();
+                self.branch(address, flag);
             }
             InstructionType::BMI => {
-                self.branch(address, (*self.flags_register.borrow_mut()).get_negative());
+                let flag = (*self.flags_register.borrow()).get_negative();
+                self.branch(address, flag);
             }
             InstructionType::BNE => {
-                self.branch(address, !(*self.flags_register.borrow_mut()).get_zero());
+                let flag = !(*self.flags_register.borrow()).get_zero();
+                self.branch(address, flag);
             }
             InstructionType::BPL => {
-                self.branch(address, !(*self.flags_register.borrow_mut()).get_negative());
+                let flag = !(*self.flags_register.borrow()).get_negative();
+                self.branch(address, flag);
             }
             InstructionType::BVC => {
-                self.branch(address, !(*self.flags_register.borrow_mut()).get_overflow());
+                let flag = !(*self.flags_register.borrow()).get_overflow();
+                self.branch(address, flag);
             }
             InstructionType::BVS => {
-                self.branch(address, (*self.flags_register.borrow_mut()).get_overflow());
+                let flag = (*self.flags_register.borrow()).get_overflow();
+                self.branch(address, flag);
             }
 
             // Status Flag Changes
@@ -296,16 +404,134 @@ impl CPU {
 
             // System Functions
             InstructionType::BRK => {
-
-            }
-            InstructionType::NOP => {
-                self.pc_inc();
+                // BRK skips the padding byte after it, which it reads as a dummy
+                self.registers.change_pc(1);
+                self.dummy_cycles(1);
+                let pc = self.registers.get_pc();
+                self.push(((pc & 0xff00) >> 8) as u8);
+                self.push((pc & 0x00ff) as u8);
+                let status_flags = u8::from(&*self.flags_register.borrow()) | 0x30;
+                self.push(status_flags);
+                (*self.flags_register.borrow_mut()).set_interrupt(true);
+                let pc = self.read_word(IRQ_VECTOR);
+                self.registers.set_pc(pc);
             }
+            InstructionType::NOP => {}
             InstructionType::RTI => {
-
+                self.dummy_cycles(2);
+                let value = self.pop();
+                (*self.flags_register.borrow_mut()).load(value);
+                let lsb = self.pop() as u16;
+                let msb = self.pop() as u16;
+                self.registers.set_pc(msb * 256 + lsb);
             }
 
-            _ => panic!(),
+            // Unofficial
+            // read-modify-write combined with an operation on A
+            InstructionType::SLO => {
+                let shift_result = self.arithmetic_shift_left(address_value);
+                self.read_modify_write(address, address_value, shift_result);
+                let or_result = self.logical_or(self.registers.get_register(RegisterType::A), shift_result);
+                self.registers.set_register(RegisterType::A, or_result);
+            }
+            InstructionType::RLA => {
+                let shift_result = self.rotate_left(address_value);
+                self.read_modify_write(address, address_value, shift_result);
+                let and_result = self.logical_and(self.registers.get_register(RegisterType::A), shift_result);
+                self.registers.set_register(RegisterType::A, and_result);
+            }
+            InstructionType::SRE => {
+                let shift_result = self.logical_shift_right(address_value);
+                self.read_modify_write(address, address_value, shift_result);
+                let xor_result = self.logical_xor(self.registers.get_register(RegisterType::A), shift_result);
+                self.registers.set_register(RegisterType::A, xor_result);
+            }
+            InstructionType::RRA => {
+                let shift_result = self.rotate_right(address_value);
+                self.read_modify_write(address, address_value, shift_result);
+                let add_result = self.arithmetic_add(self.registers.get_register(RegisterType::A), shift_result);
+                self.registers.set_register(RegisterType::A, add_result);
+            }
+            InstructionType::DCP => {
+                let decrement_result = self.decrement(address_value);
+                self.read_modify_write(address, address_value, decrement_result);
+                self.arithmetic_cmp(self.registers.get_register(RegisterType::A), decrement_result);
+            }
+            InstructionType::ISC => {
+                let increment_result = self.increment(address_value);
+                self.read_modify_write(address, address_value, increment_result);
+                let sub_result = self.arithmetic_sub(self.registers.get_register(RegisterType::A), increment_result);
+                self.registers.set_register(RegisterType::A, sub_result);
+            }
+            InstructionType::SAX => {
+                let value = self.registers.get_register(RegisterType::A) & self.registers.get_register(RegisterType::X);
+                self.write_byte(address, value);
+            }
+            InstructionType::LAX => {
+                self.load(RegisterType::A, address_value);
+                self.load(RegisterType::X, address_value);
+            }
+            // immediate operations on A
+            InstructionType::ANC => {
+                let and_result = self.logical_and(self.registers.get_register(RegisterType::A), address_value);
+                self.registers.set_register(RegisterType::A, and_result);
+                (*self.flags_register.borrow_mut()).set_carry(((and_result >> 7) & 0b1) == 1);
+            }
+            InstructionType::ALR => {
+                let and_result = self.registers.get_register(RegisterType::A) & address_value;
+                let shift_result = self.logical_shift_right(and_result);
+                self.registers.set_register(RegisterType::A, shift_result);
+            }
+            InstructionType::ARR => {
+                let carry = if (*self.flags_register.borrow()).get_carry() { 0x80 } else { 0 };
+                let result = ((self.registers.get_register(RegisterType::A) & address_value) >> 1) | carry;
+                self.registers.set_register(RegisterType::A, result);
+                let mut flags = self.flags_register.borrow_mut();
+                (*flags).set_zero(result == 0);
+                (*flags).set_negative(((result >> 7) & 0b1) == 1);
+                (*flags).set_carry(((result >> 6) & 0b1) == 1);
+                (*flags).set_overflow((((result >> 6) ^ (result >> 5)) & 0b1) == 1);
+            }
+            InstructionType::AXS => {
+                let and_result = self.registers.get_register(RegisterType::A) & self.registers.get_register(RegisterType::X);
+                self.arithmetic_cmp(and_result, address_value);
+                self.registers.set_register(RegisterType::X, and_result.wrapping_sub(address_value));
+            }
+            InstructionType::LAS => {
+                let value = address_value & self.registers.get_register(RegisterType::S);
+                self.registers.set_register(RegisterType::S, value);
+                self.load(RegisterType::A, value);
+                self.load(RegisterType::X, value);
+            }
+            // the unstable ones, as most revisions of the chip behave
+            InstructionType::SHY => {
+                let (y, x) = (self.registers.get_register(RegisterType::Y), self.registers.get_register(RegisterType::X));
+                self.store_and_high_byte(y, address, x);
+            }
+            InstructionType::SHX => {
+                let (x, y) = (self.registers.get_register(RegisterType::X), self.registers.get_register(RegisterType::Y));
+                self.store_and_high_byte(x, address, y);
+            }
+            InstructionType::AHX => {
+                let value = self.registers.get_register(RegisterType::A) & self.registers.get_register(RegisterType::X);
+                let y = self.registers.get_register(RegisterType::Y);
+                self.store_and_high_byte(value, address, y);
+            }
+            InstructionType::TAS => {
+                let value = self.registers.get_register(RegisterType::A) & self.registers.get_register(RegisterType::X);
+                self.registers.set_register(RegisterType::S, value);
+                let y = self.registers.get_register(RegisterType::Y);
+                self.store_and_high_byte(value, address, y);
+            }
+            InstructionType::XAA => {
+                let value = (self.registers.get_register(RegisterType::A) | 0xee) & self.registers.get_register(RegisterType::X) & address_value;
+                self.load(RegisterType::A, value);
+            }
+            // the CPU locks up fetching the same opcode until it is reset
+            InstructionType::JAM => {
+                let pc = self.registers.get_pc().wrapping_sub(1);
+                self.registers.set_pc(pc);
+            }
         }
     }
 
@@ -318,7 +544,7 @@ impl CPU {
 
     fn store(&mut self, register: RegisterType, address: u16) {
         let register_value = self.registers.get_register(register);
-        (*self.memory.borrow_mut()).set_byte(address, register_value);
+        self.write_byte(address, register_value);
     }
 
     // Register Transfers
@@ -330,17 +556,16 @@ impl CPU {
     }
 
     // Stack Operations
-    fn push(&mut self, value: u8) {
+    pub fn push(&mut self, value: u8) {
         let address = (self.registers.get_register(RegisterType::S) as u16) + 0x0100;
-        (*self.memory.borrow_mut()).set_byte(address, value);
+        self.write_byte(address, value);
         self.registers.push_stack();
     }
 
-    fn pop(&mut self) -> u8 {
-        let address = (self.registers.get_register(RegisterType::S) as u16) + 0x0100;
-        let value = (*self.memory.borrow_mut()).get_byte(address);
+    pub fn pop(&mut self) -> u8 {
         self.registers.pop_stack();
-        value
+        let address = (self.registers.get_register(RegisterType::S) as u16) + 0x0100;
+        self.read_byte(address)
     }
 
     // Logical Functions
@@ -373,51 +598,42 @@ impl CPU {
     }
 
     // Arithmetic Functions
+    // the 2A03 has no decimal mode, so the D flag is ignored
     pub fn arithmetic_add(&mut self, reg_value: u8, mem_value: u8) -> u8 {
-        let carry = if (*self.flags_register.borrow_mut()).get_carry() { 1 } else { 0 };
-        let signed_reg_value: i16 = (reg_value as i8) as i16;
-        let signed_mem_value: i16 = (mem_value as i8) as i16;
-        let sum: i16 = signed_reg_value + signed_mem_value + carry;
+        let carry = if (*self.flags_register.borrow()).get_carry() { 1 } else { 0 };
+        let sum: u16 = reg_value as u16 + mem_value as u16 + carry;
         let unsigned_sum: u8 = (sum & 0xff) as u8;
-        (*self.flags_register.borrow_mut()).set_carry(((sum >> 8) & 0b1) == 1);
+        // overflow when both operands share a sign the result does not have
+        let overflow = ((reg_value ^ unsigned_sum) & (mem_value ^ unsigned_sum) & 0x80) != 0;
+        (*self.flags_register.borrow_mut()).set_carry(sum > 0xff);
         (*self.flags_register.borrow_mut()).set_zero(unsigned_sum == 0);
-        (*self.flags_register.borrow_mut()).set_overflow(((sum >> 8) & 0b1) != ((sum >> 7) & 0b1));
-        (*self.flags_register.borrow_mut()).set_negative(((sum >> 7) & 0b1) == 1);
+        (*self.flags_register.borrow_mut()).set_overflow(overflow);
+        (*self.flags_register.borrow_mut()).set_negative(((unsigned_sum >> 7) & 0b1) == 1);
         unsigned_sum
     }
 
+    // subtracting is adding the ones' complement, with carry as the inverted borrow
     pub fn arithmetic_sub(&mut self, reg_value: u8, mem_value: u8) -> u8 {
-        let carry = if (*self.flags_register.borrow_mut()).get_carry() { 1 } else { 0 };
-        let signed_reg_value: i16 = (reg_value as i8) as i16;
-        let signed_mem_value: i16 = (mem_value as i8) as i16;
-        let sub: i16 = signed_reg_value - signed_mem_value - (1 - carry);
-        let unsigned_sub: u8 = (sub & 0xff) as u8;
-        (*self.flags_register.borrow_mut()).set_carry(((sub >> 8) & 0b1) == 0);
-        (*self.flags_register.borrow_mut()).set_zero(unsigned_sub == 0);
-        (*self.flags_register.borrow_mut()).set_overflow(((sub >> 8) & 0b1) != ((sub >> 7) & 0b1));
-        (*self.flags_register.borrow_mut()).set_negative(((sub >> 7) & 0b1) == 1);
-        unsigned_sub
+        self.arithmetic_add(reg_value, !mem_value)
     }
 
     pub fn arithmetic_cmp(&mut self, reg_value: u8, mem_value: u8) {
-        let signed_reg_value: i16 = (reg_value as i8) as i16;
-        let signed_mem_value: i16 = (mem_value as i8) as i16;
-        let sub: i16 = signed_reg_value - signed_mem_value;
-        (*self.flags_register.borrow_mut()).set_carry(signed_reg_value >= signed_mem_value);
-        (*self.flags_register.borrow_mut()).set_zero(signed_reg_value == signed_mem_value);
+        let sub = reg_value.wrapping_sub(mem_value);
+        (*self.flags_register.borrow_mut()).set_carry(reg_value >= mem_value);
+        (*self.flags_register.borrow_mut()).set_zero(reg_value == mem_value);
         (*self.flags_register.borrow_mut()).set_negative(((sub >> 7) & 0b1) == 1);
     }
 
     // Increments & Decrements
     pub fn increment(&mut self, value: u8) -> u8 {
-        let incremented = (((value as u16) + 1) & 0xff) as u8;
+        let incremented = value.wrapping_add(1);
         (*self.flags_register.borrow_mut()).set_zero(incremented == 0);
         (*self.flags_register.borrow_mut()).set_negative(((incremented >> 7) & 0b1) == 1);
         incremented
     }
 
     pub fn decrement(&mut self, value: u8) -> u8 {
-        let decremented = (((value as u16) - 1) & 0xff) as u8;
+        let decremented = value.wrapping_sub(1);
         (*self.flags_register.borrow_mut()).set_zero(decremented == 0);
         (*self.flags_register.borrow_mut()).set_negative(((decremented >> 7) & 0b1) == 1);
         decremented
@@ -425,9 +641,8 @@ impl CPU {
 
     // Shifts
     pub fn arithmetic_shift_left(&mut self, value: u8) -> u8 {
-        let shift_result = (((value as u16) * 2) & 0xff) as u8;
+        let shift_result = value << 1;
         (*self.flags_register.borrow_mut()).set_carry(((value >> 7) & 0b1) == 1);
-        // TODO: zero only if in accumulator mode
         (*self.flags_register.borrow_mut()).set_zero(shift_result == 0);
         (*self.flags_register.borrow_mut()).set_negative(((shift_result >> 7) & 0b1) == 1);
         shift_result
@@ -435,53 +650,171 @@ impl CPU {
 
     pub fn logical_shift_right(&mut self, value: u8) -> u8 {
         let shift_result = value >> 1;
-        (*self.flags_register.borrow_mut()).set_carry(((value >> 7) & 0b1) == 1);
+        (*self.flags_register.borrow_mut()).set_carry((value & 0b1) == 1);
         (*self.flags_register.borrow_mut()).set_zero(shift_result == 0);
-        (*self.flags_register.borrow_mut()).set_negative(((shift_result >> 7) & 0b1) == 1);
+        (*self.flags_register.borrow_mut()).set_negative(false);
         shift_result
     }
 
     pub fn rotate_left(&mut self, value: u8) -> u8 {
-        let shift_result = ((((value as u16) * 2) & 0xff) as u8) | (value & 0x80);
+        let carry = if (*self.flags_register.borrow()).get_carry() { 1 } else { 0 };
+        let shift_result = (value << 1) | carry;
         (*self.flags_register.borrow_mut()).set_carry(((value >> 7) & 0b1) == 1);
-        // TODO: zero only if in accumulator mode
         (*self.flags_register.borrow_mut()).set_zero(shift_result == 0);
         (*self.flags_register.borrow_mut()).set_negative(((shift_result >> 7) & 0b1) == 1);
         shift_result
     }
 
-    pub fn rotate_left(&mut self, value: u8) -> u8 {
-        let shift_result = (value >> 1) | (value & 0x01);
-        (*self.flags_register.borrow_mut()).set_carry(((value >> 7) & 0b1) == 1);
-        // TODO: zero only if in accumulator mode
+    pub fn rotate_right(&mut self, value: u8) -> u8 {
+        let carry = if (*self.flags_register.borrow()).get_carry() { 0x80 } else { 0 };
+        let shift_result = (value >> 1) | carry;
+        (*self.flags_register.borrow_mut()).set_carry((value & 0b1) == 1);
         (*self.flags_register.borrow_mut()).set_zero(shift_result == 0);
         (*self.flags_register.borrow_mut()).set_negative(((shift_result >> 7) & 0b1) == 1);
         shift_result
     }
 
-    // Branch
-    pub fn pc_inc(&mut self) {
-        self.registers.change_pc(1);
+    fn write_shift_result(&mut self, mode: AddressingMode, address: u16, value: u8, shift_result: u8) {
+        match mode {
+            AddressingMode::Accumulator => self.registers.set_register(RegisterType::A, shift_result),
+            _ => self.read_modify_write(address, value, shift_result),
+        }
+    }
+
+    // read-modify-write instructions write the unmodified value back before the
+    // result, which registers with write side effects can see
+    fn read_modify_write(&mut self, address: u16, value: u8, result: u8) {
+        self.write_byte(address, value);
+        self.write_byte(address, result);
+    }
+
+    // stores of a register ANDed with the high byte of the base address plus
+    // one, which on a page crossing also becomes the high byte written to
+    fn store_and_high_byte(&mut self, value: u8, address: u16, index: u8) {
+        let base_high = (address.wrapping_sub(index as u16) >> 8) as u8;
+        let result = value & base_high.wrapping_add(1);
+        let address = if self.page_crossed { ((result as u16) << 8) | (address & 0x00ff) } else { address };
+        self.write_byte(address, result);
     }
 
+    // Branch
+    // taken branches cost a cycle, and one more when they land on another page
     pub fn branch(&mut self, address: u16, flag: bool) {
-        if (flag) {
-            self.registers.change_pc(address);
+        if flag {
+            let pc = self.registers.get_pc();
+            self.extra_cycles += if (pc & 0xff00) != (address & 0xff00) { 2 } else { 1 };
+            self.registers.set_pc(address);
+        }
+    }
+
+    // Interrupts
+    // the interrupt sequence runs after the instruction, returns its cycles
+    fn interrupt(&mut self, vector: u16) -> u8 {
+        self.start_bus_cycles();
+        // two dummy reads of PC before the pushes
+        self.dummy_cycles(2);
+        let pc = self.registers.get_pc();
+        self.push(((pc & 0xff00) >> 8) as u8);
+        self.push((pc & 0x00ff) as u8);
+        // bit 5 always reads back set, B stays clear for hardware interrupts
+        let status_flags = u8::from(&*self.flags_register.borrow()) | 0x20;
+        self.push(status_flags);
+        (*self.flags_register.borrow_mut()).set_interrupt(true);
+        let pc = self.read_word(vector);
+        self.registers.set_pc(pc);
+        self.run_bus_cycles(INTERRUPT_CYCLES);
+        INTERRUPT_CYCLES
+    }
+
+    // Bus timing
+    fn start_bus_cycles(&mut self) {
+        self.in_step = true;
+        self.accesses = 0;
+        self.bus_cycles = 0;
+    }
+
+    // runs the rest of the bus until the given cycle of the instruction
+    fn run_bus_cycles(&mut self, cycles: u8) {
+        while self.bus_cycles < cycles {
+            (*self.memory.borrow_mut()).step_cpu();
+            self.bus_cycles += 1;
+        }
+    }
+
+    // the nth access of an instruction happens on its nth cycle, dummy cycles
+    // count as accesses too
+    fn synchronize_access(&mut self) {
+        if self.in_step {
+            self.accesses += 1;
+            self.run_bus_cycles(self.accesses - 1);
+        }
+    }
+
+    // cycles where the CPU reads a value and throws it away, or works on its
+    // own. Only their timing is kept, the reads themselves are left out.
+    fn dummy_cycles(&mut self, cycles: u8) {
+        if self.in_step {
+            self.accesses += cycles;
         }
     }
 
+    // Memory
+    // DMA can only halt the CPU on reads
+    fn read_byte(&mut self, address: u16) -> u8 {
+        self.synchronize_access();
+        let mut memory = self.memory.borrow_mut();
+        self.stolen_cycles += (*memory).run_dmc_dma(address) as u16;
+        self.stolen_cycles += (*memory).run_oam_dma(address);
+        (*memory).get_byte(address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.synchronize_access();
+        (*self.memory.borrow_mut()).set_byte(address, value);
+    }
+
+    fn read_word(&mut self, address: u16) -> u16 {
+        let lsb = self.read_byte(address) as u16;
+        let msb = self.read_byte(address.wrapping_add(1)) as u16;
+        msb * 256 + lsb
+    }
+
+    // pointers in the zero page wrap around within it
+    fn read_zero_page_word(&mut self, address: u8) -> u16 {
+        let lsb = self.read_byte(address as u16) as u16;
+        let msb = self.read_byte(address.wrapping_add(1) as u16) as u16;
+        msb * 256 + lsb
+    }
+
+    fn add_index(&mut self, base: u16, index: u8) -> u16 {
+        let address = base.wrapping_add(index as u16);
+        self.page_crossed = (base & 0xff00) != (address & 0xff00);
+        address
+    }
+
     // Addressing
-    // TODO: implement all addressing modes (with acc in rotates in mind)
-    fn resolve_addressing_mode(&self, addressing_mode: AddressingMode) -> (u8, u16) {
-        match addressing_mode {
-            AddressingMode::Relative(relative) => {
-                let signed: bool = ((relative >> 7) & 0b1) == 1;
-                let value: u8 = relative & 0x7f;
-                let signed_value = if signed { !value + 1 } else { value };
-                (signed_value, signed_value as u16)
+    // stores and jumps only need the address, reading their operand could
+    // trigger side effects of memory mapped registers. Indexing costs a dummy
+    // read: always for zero page and indexed indirect addresses, for indexed
+    // absolute and indirect indexed ones when crossing a page or when the
+    // instruction writes.
+    fn resolve_addressing_mode(&mut self, addressing_mode: AddressingMode, instruction_type: InstructionType) -> (u8, u16) {
+        let read = Self::reads_operand(instruction_type);
+        let always_fixes_page = !Self::has_page_cross_penalty(instruction_type);
+        let address = match addressing_mode {
+            AddressingMode::Implied => {
+                return (0, 0);
+            }
+            AddressingMode::Accumulator => {
+                return (self.registers.get_register(RegisterType::A), 0);
             }
             AddressingMode::Immediate(immediate) => {
-                (immediate, immediate as u16)
+                return (immediate, immediate as u16);
+            }
+            AddressingMode::Relative(relative) => {
+                // relative to the instruction after the branch
+                let target = self.registers.get_pc().wrapping_add((relative as i8) as u16);
+                return (relative, target);
             }
             AddressingMode::ZeroPage(address, register) => {
                 // ZeroPage
@@ -494,26 +827,211 @@ impl CPU {
                 if register == 2 {
                     register_value = self.registers.get_register(RegisterType::Y);
                 }
-                let new_address: u8 = address + register_value;
-                let address_value: u8 = (*self.memory.borrow_mut()).get_byte(new_address as u16);
-                (address_value as u8, new_address as u16)
+                if register != 0 {
+                    self.dummy_cycles(1);
+                }
+                address.wrapping_add(register_value) as u16
             }
             AddressingMode::Absolute(address, register) => {
                 // Absolute
-                let mut register_value: u16 = 0;
+                let mut register_value: u8 = 0;
                 // AbsoluteX
                 if register == 1 {
-                    register_value = self.registers.get_register(RegisterType::X) as u16;
+                    register_value = self.registers.get_register(RegisterType::X);
                 }
                 // AbsoluteY
                 if register == 2 {
-                    register_value = self.registers.get_register(RegisterType::Y) as u16;
+                    register_value = self.registers.get_register(RegisterType::Y);
                 }
-                let new_address: u16 = address + register_value;
-                let address_value: u8 = (*self.memory.borrow_mut()).get_byte(new_address);
-                (address_value as u8, new_address as u16)
+                let indexed = self.add_index(address, register_value);
+                if register != 0 && (self.page_crossed || always_fixes_page) {
+                    self.dummy_cycles(1);
+                }
+                indexed
+            }
+            AddressingMode::Indirect(pointer) => {
+                // the high byte is fetched without carrying into the pointer's page
+                let lsb = self.read_byte(pointer) as u16;
+                let msb = self.read_byte((pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff)) as u16;
+                msb * 256 + lsb
+            }
+            AddressingMode::IndexedIndirect(pointer) => {
+                let x = self.registers.get_register(RegisterType::X);
+                self.dummy_cycles(1);
+                self.read_zero_page_word(pointer.wrapping_add(x))
+            }
+            AddressingMode::IndirectIndexed(pointer) => {
+                let base = self.read_zero_page_word(pointer);
+                let y = self.registers.get_register(RegisterType::Y);
+                let indexed = self.add_index(base, y);
+                if self.page_crossed || always_fixes_page {
+                    self.dummy_cycles(1);
+                }
+                indexed
             }
-            _ => { (0, 0) }
+        };
+        let address_value = if read { self.read_byte(address) } else { 0 };
+        (address_value, address)
+    }
+
+    fn reads_operand(instruction_type: InstructionType) -> bool {
+        !matches!(
+            instruction_type,
+            InstructionType::STA | InstructionType::STX | InstructionType::STY | InstructionType::JMP | InstructionType::JSR |
+            InstructionType::SAX | InstructionType::SHY | InstructionType::SHX | InstructionType::AHX | InstructionType::TAS
+        )
+    }
+
+    // indexed reads crossing a page redo the read, stores and read-modify-write
+    // instructions always take the extra cycle and have it in their base cycles
+    fn has_page_cross_penalty(instruction_type: InstructionType) -> bool {
+        matches!(
+            instruction_type,
+            InstructionType::LDA | InstructionType::LDX | InstructionType::LDY |
+            InstructionType::AND | InstructionType::EOR | InstructionType::ORA |
+            InstructionType::ADC | InstructionType::SBC | InstructionType::CMP |
+            InstructionType::NOP | InstructionType::LAX | InstructionType::LAS
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::cartridge::Cartridge;
+    use crate::ppu::ppu::PPU;
+
+    // a CPU about to run the program from $0200 in RAM
+    fn load_program(program: &[u8]) -> (CPU, Rc<RefCell<Memory>>) {
+        let memory = Rc::new(RefCell::new(Memory::new()));
+        for (offset, byte) in program.iter().enumerate() {
+            (*memory.borrow_mut()).set_byte(0x0200 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(Rc::clone(&memory));
+        cpu.set_pc(0x0200);
+        (cpu, memory)
+    }
+
+    #[test]
+    fn unofficial_opcodes_have_their_lengths() {
+        let program = [
+            0x80, 0xff, // DOP #$FF
+            0x04, 0x10, // DOP $10
+            0x0c, 0x00, 0x03, // TOP $0300
+            0x1c, 0x00, 0x03, // TOP $0300,X
+            0x1a, // NOP
+            0xa7, 0x10, // LAX $10
+            0xe8, // INX
+            0x87, 0x11, // SAX $11
+            0xc7, 0x12, // DCP $12
+        ];
+        let (mut cpu, memory) = load_program(&program);
+        (*memory.borrow_mut()).set_byte(0x0010, 0x0f);
+        (*memory.borrow_mut()).set_byte(0x0012, 0x10);
+        let cycles: Vec<u16> = (0..9).map(|_| cpu.step()).collect();
+
+        assert_eq!(cpu.get_pc(), 0x0200 + program.len() as u16);
+        assert_eq!(cycles, vec![2, 3, 4, 4, 2, 3, 2, 3, 5]);
+        assert_eq!(cpu.get_register(RegisterType::A), 0x0f);
+        assert_eq!(cpu.get_register(RegisterType::X), 0x10);
+        assert_eq!((*memory.borrow_mut()).get_byte(0x0011), 0x00);
+        // $10 - 1 compared with A
+        assert_eq!((*memory.borrow_mut()).get_byte(0x0012), 0x0f);
+        assert!((*cpu.flags_register.borrow()).get_zero());
+    }
+
+    #[test]
+    fn jam_locks_up() {
+        let (mut cpu, _) = load_program(&[0xea, 0x02, 0xea]);
+        for _ in 0..5 {
+            cpu.step();
         }
+        assert_eq!(cpu.get_pc(), 0x0201);
     }
-}
\ No newline at end of file
+
+    // the last bus access of each instruction lands on the cycle it takes on
+    // hardware, dummy cycles included
+    #[test]
+    fn accesses_count_dummy_cycles() {
+        let cases: [(&[u8], u8, u8); 13] = [
+            (&[0xb5, 0x10], 0x01, 4),       // LDA $10,X
+            (&[0xbd, 0x00, 0x03], 0x01, 4), // LDA $0300,X
+            (&[0xbd, 0xff, 0x03], 0x01, 5), // LDA $03FF,X crossing a page
+            (&[0x9d, 0x00, 0x03], 0x01, 5), // STA $0300,X
+            (&[0xfe, 0x00, 0x03], 0x01, 7), // INC $0300,X
+            (&[0xa1, 0x10], 0x00, 6),       // LDA ($10,X)
+            (&[0xb1, 0x10], 0x00, 5),       // LDA ($10),Y
+            (&[0x91, 0x10], 0x00, 6),       // STA ($10),Y
+            (&[0x48], 0x00, 3),             // PHA
+            (&[0x68], 0x00, 4),             // PLA
+            (&[0x40], 0x00, 6),             // RTI
+            (&[0x60], 0x00, 5),             // RTS, the last cycle increments PC
+            (&[0x00, 0x00], 0x00, 7),       // BRK
+        ];
+        for (program, x, accesses) in cases.iter() {
+            let (mut cpu, _) = load_program(program);
+            cpu.set_register(RegisterType::X, *x);
+            cpu.step();
+            assert_eq!(cpu.accesses, *accesses, "{:02X?}", program);
+        }
+    }
+
+    #[test]
+    fn interrupts_push_after_two_dummy_cycles() {
+        let (mut cpu, _) = load_program(&[]);
+        assert_eq!(cpu.interrupt(NMI_VECTOR), INTERRUPT_CYCLES);
+        assert_eq!(cpu.accesses, INTERRUPT_CYCLES);
+    }
+
+    // the copy halts the read after the $4014 write, 513 cycles when that read
+    // lands on a put cycle and 514 when it has to wait for one
+    #[test]
+    fn oam_dma_stalls_the_cpu() {
+        let cases: [(&[u8], Vec<u16>); 2] = [
+            (&[0x8d, 0x14, 0x40, 0xea], vec![4, 2 + 514]),             // STA $4014, NOP
+            (&[0xa5, 0x10, 0x8d, 0x14, 0x40, 0xea], vec![3, 4, 2 + 513]), // LDA $10, STA $4014, NOP
+        ];
+        for (program, expected) in cases.iter() {
+            let (mut cpu, memory) = load_program(program);
+            let ppu = Rc::new(RefCell::new(PPU::new()));
+            (*memory.borrow_mut()).insert_ppu(Rc::clone(&ppu));
+            for offset in 0..=0xff {
+                (*memory.borrow_mut()).set_byte(0x0300 + offset, offset as u8);
+            }
+            (*memory.borrow_mut()).set_byte(0x0010, 0x03);
+            cpu.set_register(RegisterType::A, 0x03);
+
+            let cycles: Vec<u16> = expected.iter().map(|_| cpu.step()).collect();
+            assert_eq!(&cycles, expected, "{:02X?}", program);
+            let oam: Vec<u8> = (0..=0xff).collect();
+            assert_eq!((*ppu.borrow()).get_oam(), &oam[..]);
+        }
+    }
+
+    // blargg's test ROMs write 1 to $F8 once every test passed
+    fn run_test_rom(path: &str) -> u8 {
+        let cartridge = Rc::new(RefCell::new(Cartridge::load(path).unwrap()));
+        let ppu = Rc::new(RefCell::new(PPU::new()));
+        (*ppu.borrow_mut()).insert_cartridge(Rc::clone(&cartridge));
+        let memory = Rc::new(RefCell::new(Memory::new()));
+        (*memory.borrow_mut()).insert_cartridge(cartridge);
+        (*memory.borrow_mut()).insert_ppu(Rc::clone(&ppu));
+        let mut cpu = CPU::new(Rc::clone(&memory));
+        cpu.reset();
+
+        let mut result = 0;
+        while result != 1 && (*ppu.borrow()).get_frame() < 600 {
+            cpu.step();
+            result = (*memory.borrow_mut()).get_byte(0x00f8);
+        }
+        result
+    }
+
+    #[test]
+    fn passes_branch_timing_tests() {
+        for name in ["1.Branch_Basics", "2.Backward_Branch", "3.Forward_Branch"] {
+            let path = format!("{}/tests/branch_timing_tests/{}.nes", env!("CARGO_MANIFEST_DIR"), name);
+            assert_eq!(run_test_rom(&path), 1, "{}", name);
+        }
+    }
+}
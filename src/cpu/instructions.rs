@@ -19,7 +19,15 @@ impl InstructionReader {
             "2E", "3E", "6A", "66", "76", "6E", "7E", "40", "60", "E9", "E5", "F5", "ED", "FD", "F9", "E1",
             "F1", "38", "F8", "78", "85", "95", "8D", "9D", "99", "81", "91", "86", "96", "8E", "84", "94",
             "8C", "AA", "A8", "BA", "8A", "9A", "98",
-        ].into_iter().map(|x| String::from(x)).collect();
+            // unofficial, from https://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+            "1A", "3A", "5A", "7A", "DA", "FA", "80", "82", "89", "C2", "E2", "04", "44", "64", "14", "34",
+            "54", "74", "D4", "F4", "0C", "1C", "3C", "5C", "7C", "DC", "FC", "02", "12", "22", "32", "42",
+            "52", "62", "72", "92", "B2", "D2", "F2", "07", "17", "0F", "1F", "1B", "03", "13", "27", "37",
+            "2F", "3F", "3B", "23", "33", "47", "57", "4F", "5F", "5B", "43", "53", "67", "77", "6F", "7F",
+            "7B", "63", "73", "C7", "D7", "CF", "DF", "DB", "C3", "D3", "E7", "F7", "EF", "FF", "FB", "E3",
+            "F3", "87", "97", "8F", "83", "A7", "B7", "AF", "BF", "A3", "B3", "AB", "0B", "2B", "4B", "6B",
+            "CB", "EB", "BB", "9C", "9E", "9F", "93", "9B", "8B",
+        ].into_iter().map(String::from).collect();
 
         let instruction_names_and_modes: Vec<(String, String)> = vec![
             ("ADC", "Immediate"),    ("ADC", "ZeroPage"),     ("ADC", "ZeroPage,X"),   ("ADC", "Absolute"),
@@ -60,16 +68,43 @@ impl InstructionReader {
             ("STX", "ZeroPage,Y"),   ("STX", "Absolute"),     ("STY", "ZeroPage"),     ("STY", "ZeroPage,X"),
             ("STY", "Absolute"),     ("TAX", "Implied"),      ("TAY", "Implied"),      ("TSX", "Implied"),
             ("TXA", "Implied"),      ("TXS", "Implied"),      ("TYA", "Implied"),
+            ("NOP", "Implied"),      ("NOP", "Implied"),      ("NOP", "Implied"),      ("NOP", "Implied"),
+            ("NOP", "Implied"),      ("NOP", "Implied"),      ("NOP", "Immediate"),    ("NOP", "Immediate"),
+            ("NOP", "Immediate"),    ("NOP", "Immediate"),    ("NOP", "Immediate"),    ("NOP", "ZeroPage"),
+            ("NOP", "ZeroPage"),     ("NOP", "ZeroPage"),     ("NOP", "ZeroPage,X"),   ("NOP", "ZeroPage,X"),
+            ("NOP", "ZeroPage,X"),   ("NOP", "ZeroPage,X"),   ("NOP", "ZeroPage,X"),   ("NOP", "ZeroPage,X"),
+            ("NOP", "Absolute"),     ("NOP", "Absolute,X"),   ("NOP", "Absolute,X"),   ("NOP", "Absolute,X"),
+            ("NOP", "Absolute,X"),   ("NOP", "Absolute,X"),   ("NOP", "Absolute,X"),   ("JAM", "Implied"),
+            ("JAM", "Implied"),      ("JAM", "Implied"),      ("JAM", "Implied"),      ("JAM", "Implied"),
+            ("JAM", "Implied"),      ("JAM", "Implied"),      ("JAM", "Implied"),      ("JAM", "Implied"),
+            ("JAM", "Implied"),      ("JAM", "Implied"),      ("JAM", "Implied"),      ("SLO", "ZeroPage"),
+            ("SLO", "ZeroPage,X"),   ("SLO", "Absolute"),     ("SLO", "Absolute,X"),   ("SLO", "Absolute,Y"),
+            ("SLO", "(Indirect,X)"), ("SLO", "(Indirect),Y"), ("RLA", "ZeroPage"),     ("RLA", "ZeroPage,X"),
+            ("RLA", "Absolute"),     ("RLA", "Absolute,X"),   ("RLA", "Absolute,Y"),   ("RLA", "(Indirect,X)"),
+            ("RLA", "(Indirect),Y"), ("SRE", "ZeroPage"),     ("SRE", "ZeroPage,X"),   ("SRE", "Absolute"),
+            ("SRE", "Absolute,X"),   ("SRE", "Absolute,Y"),   ("SRE", "(Indirect,X)"), ("SRE", "(Indirect),Y"),
+            ("RRA", "ZeroPage"),     ("RRA", "ZeroPage,X"),   ("RRA", "Absolute"),     ("RRA", "Absolute,X"),
+            ("RRA", "Absolute,Y"),   ("RRA", "(Indirect,X)"), ("RRA", "(Indirect),Y"), ("DCP", "ZeroPage"),
+            ("DCP", "ZeroPage,X"),   ("DCP", "Absolute"),     ("DCP", "Absolute,X"),   ("DCP", "Absolute,Y"),
+            ("DCP", "(Indirect,X)"), ("DCP", "(Indirect),Y"), ("ISC", "ZeroPage"),     ("ISC", "ZeroPage,X"),
+            ("ISC", "Absolute"),     ("ISC", "Absolute,X"),   ("ISC", "Absolute,Y"),   ("ISC", "(Indirect,X)"),
+            ("ISC", "(Indirect),Y"), ("SAX", "ZeroPage"),     ("SAX", "ZeroPage,Y"),   ("SAX", "Absolute"),
+            ("SAX", "(Indirect,X)"), ("LAX", "ZeroPage"),     ("LAX", "ZeroPage,Y"),   ("LAX", "Absolute"),
+            ("LAX", "Absolute,Y"),   ("LAX", "(Indirect,X)"), ("LAX", "(Indirect),Y"), ("LAX", "Immediate"),
+            ("ANC", "Immediate"),    ("ANC", "Immediate"),    ("ALR", "Immediate"),    ("ARR", "Immediate"),
+            ("AXS", "Immediate"),    ("SBC", "Immediate"),    ("LAS", "Absolute,Y"),   ("SHY", "Absolute,X"),
+            ("SHX", "Absolute,Y"),   ("AHX", "Absolute,Y"),   ("AHX", "(Indirect),Y"), ("TAS", "Absolute,Y"),
+            ("XAA", "Immediate"),
         ].into_iter().map(|x| (String::from(x.0), String::from(x.1))).collect();
 
         InstructionReader {
-            instruction_map: instruction_opcodes.into_iter().zip(instruction_names_and_modes.into_iter()).collect()
+            instruction_map: instruction_opcodes.into_iter().zip(instruction_names_and_modes).collect()
         }
     }
 
     pub fn read(&mut self, s: &str) -> Instruction {
         let padded_string = format!("{:0<6}", s);
-        let opcode = (&padded_string[0..2]).to_string();
+        let opcode = padded_string[0..2].to_string();
         let operand1 = u16::from_str_radix(&padded_string[2..4], 16).unwrap();
         let operand2 = u16::from_str_radix(&padded_string[4..6], 16).unwrap();
 
@@ -83,6 +118,33 @@ impl InstructionReader {
         }
     }
 
+    // decodes an instruction as laid out in CPU memory, 16 bit operands are little endian
+    pub fn decode(&self, opcode: u8, low: u8, high: u8) -> Option<Instruction> {
+        let (inst_name, inst_mode) = self.instruction_map.get(&format!("{:02X}", opcode))?;
+        let instruction: InstructionType = inst_name.parse().ok()?;
+        let address = match self.get_length(opcode) {
+            3 => self.mode_to_enum(inst_mode, high as u16, low as u16),
+            _ => self.mode_to_enum(inst_mode, low as u16, 0),
+        };
+
+        Some(Instruction {
+            instruction,
+            address,
+        })
+    }
+
+    // in bytes, including the opcode
+    pub fn get_length(&self, opcode: u8) -> u16 {
+        match self.instruction_map.get(&format!("{:02X}", opcode)) {
+            Some((_, mode)) => match mode.as_str() {
+                "Implied" | "Accumulator" => 1,
+                "Absolute" | "Absolute,X" | "Absolute,Y" | "Indirect" => 3,
+                _ => 2,
+            },
+            None => 1,
+        }
+    }
+
     fn mode_to_enum(&self, mode: &str, op1: u16, op2: u16) -> AddressingMode {
         match mode {
             "Accumulator" =>  { AddressingMode::Accumulator }
@@ -118,7 +180,6 @@ impl Instruction {
     }
 }
 
-// TODO: unofficial opcodes http://wiki.nesdev.com/w/index.php/Programming_with_unofficial_opcodes
 custom_derive! {
     #[derive(Debug, EnumFromStr, Copy, Clone)]
     pub enum InstructionType {
@@ -144,6 +205,8 @@ custom_derive! {
         CLC, CLD, CLI, CLV, SEC, SED, SEI,
         // SystemFunctionsInstructions
         BRK, NOP, RTI,
+        // UnofficialInstructions, from http://wiki.nesdev.com/w/index.php/Programming_with_unofficial_opcodes
+        SLO, RLA, SRE, RRA, DCP, ISC, SAX, LAX, ANC, ALR, ARR, AXS, LAS, SHY, SHX, AHX, TAS, XAA, JAM,
     }
 }
 
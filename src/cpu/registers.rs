@@ -40,6 +40,9 @@ impl Registers {
             RegisterType::Y => {
                 self.y = value;
             }
+            RegisterType::S => {
+                self.s = value;
+            }
             RegisterType::P => {
                 self.p = value;
             }
@@ -47,15 +50,16 @@ impl Registers {
         }
     }
 
+    // the stack grows down from $01FF
     pub fn push_stack(&mut self) {
-        self.s = self.s + 1;
+        self.s = self.s.wrapping_sub(1);
     }
 
     pub fn pop_stack(&mut self) {
-        self.s = self.s - 1;
+        self.s = self.s.wrapping_add(1);
     }
 
-    pub fn get_pc(&mut self) -> u16 {
+    pub fn get_pc(&self) -> u16 {
         self.pc
     }
 
@@ -68,7 +72,7 @@ impl Registers {
     }
 
     pub fn change_pc(&mut self, change: u16) {
-        self.pc += change;
+        self.pc = self.pc.wrapping_add(change);
     }
 }
 
@@ -100,13 +104,13 @@ impl FlagsRegister {
         }
     }
 
-    pub fn load(&mut self, bytes: u8) {
+    pub fn load(&mut self, byte: u8) {
         self.negative = ((byte >> NEGATIVE_FLAG_BYTE_POSITION) & 0b1) != 0;
         self.overflow = ((byte >> OVERFLOW_FLAG_BYTE_POSITION) & 0b1) != 0;
         self.decimal = ((byte >> DECIMAL_FLAG_BYTE_POSITION) & 0b1) != 0;
-        self.interrupt = ((byte >> OVERFLOW_FLAG_BYTE_POSITION) & 0b1) != 0;
+        self.interrupt = ((byte >> INTERRUPT_FLAG_BYTE_POSITION) & 0b1) != 0;
         self.zero = ((byte >> ZERO_FLAG_BYTE_POSITION) & 0b1) != 0;
-        self.carry = ((byte >> OVERFLOW_FLAG_BYTE_POSITION) & 0b1) != 0;
+        self.carry = ((byte >> CARRY_FLAG_BYTE_POSITION) & 0b1) != 0;
     }
 
     pub fn set_negative(&mut self, value: bool) {
@@ -169,14 +173,25 @@ impl std::convert::From<FlagsRegister> for u8 {
     }
 }
 
+impl std::convert::From<&FlagsRegister> for u8 {
+    fn from(flag: &FlagsRegister) -> u8 {
+        (if flag.negative   { 1 } else { 0 }) << NEGATIVE_FLAG_BYTE_POSITION |
+        (if flag.overflow   { 1 } else { 0 }) << OVERFLOW_FLAG_BYTE_POSITION |
+        (if flag.decimal    { 1 } else { 0 }) << DECIMAL_FLAG_BYTE_POSITION |
+        (if flag.interrupt  { 1 } else { 0 }) << INTERRUPT_FLAG_BYTE_POSITION |
+        (if flag.zero       { 1 } else { 0 }) << ZERO_FLAG_BYTE_POSITION |
+        (if flag.carry      { 1 } else { 0 }) << CARRY_FLAG_BYTE_POSITION
+    }
+}
+
 impl std::convert::From<u8> for FlagsRegister {
     fn from(byte: u8) -> FlagsRegister {
         let negative = ((byte >> NEGATIVE_FLAG_BYTE_POSITION) & 0b1) != 0;
         let overflow = ((byte >> OVERFLOW_FLAG_BYTE_POSITION) & 0b1) != 0;
         let decimal = ((byte >> DECIMAL_FLAG_BYTE_POSITION) & 0b1) != 0;
-        let interrupt = ((byte >> OVERFLOW_FLAG_BYTE_POSITION) & 0b1) != 0;
+        let interrupt = ((byte >> INTERRUPT_FLAG_BYTE_POSITION) & 0b1) != 0;
         let zero = ((byte >> ZERO_FLAG_BYTE_POSITION) & 0b1) != 0;
-        let carry = ((byte >> OVERFLOW_FLAG_BYTE_POSITION) & 0b1) != 0;
+        let carry = ((byte >> CARRY_FLAG_BYTE_POSITION) & 0b1) != 0;
 
         FlagsRegister {
             negative,
@@ -0,0 +1,80 @@
+// CRC-32 (IEEE 802.3), as used by rom databases, zip files and UPS/BPS patches
+const POLYNOMIAL: u32 = 0xedb88320;
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut value = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if (value & 1) != 0 { (value >> 1) ^ POLYNOMIAL } else { value >> 1 };
+            bit += 1;
+        }
+        table[i] = value;
+        i += 1;
+    }
+    table
+}
+
+pub struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 {
+            value: 0xffffffff,
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.value = TABLE[((self.value ^ *byte as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    pub fn get_value(&self) -> u32 {
+        !self.value
+    }
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.get_value()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // four bytes that make the CRC32 of bytes followed by them come out as
+    // target, by running the table lookups of those four bytes backwards
+    pub fn force_crc32(bytes: &[u8], target: u32) -> [u8; 4] {
+        let mut indices = [0; 4];
+        let mut value = !target;
+        for index in indices.iter_mut().rev() {
+            *index = TABLE.iter().position(|entry| (entry >> 24) == (value >> 24)).unwrap();
+            value = (value ^ TABLE[*index]) << 8;
+        }
+
+        let mut crc = Crc32::new();
+        crc.update(bytes);
+        let mut value = crc.value;
+        let mut forced = [0; 4];
+        for (byte, index) in forced.iter_mut().zip(indices.iter()) {
+            *byte = ((value as usize ^ index) & 0xff) as u8;
+            value = TABLE[*index] ^ (value >> 8);
+        }
+        forced
+    }
+
+    #[test]
+    fn computes_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        let forced = force_crc32(b"123456789", 0x12345678);
+        assert_eq!(crc32(&[&b"123456789"[..], &forced].concat()), 0x12345678);
+    }
+}
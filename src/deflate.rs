@@ -0,0 +1,282 @@
+// zlib streams of a single fixed Huffman block, or stored blocks when that
+// does not pay off, from https://www.rfc-editor.org/rfc/rfc1950 and
+// https://www.rfc-editor.org/rfc/rfc1951
+const MAX_STORED_BLOCK: usize = 0xffff;
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_SIZE: usize = 1 << 15;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+// deflate packs bits starting from the least significant one
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u8) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn write_code(&mut self, code: u32, count: u8) {
+        let reversed = code.reverse_bits() >> (32 - count);
+        self.write_bits(reversed, count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+pub fn zlib_compress(bytes: &[u8]) -> Vec<u8> {
+    // 32KB window, no dictionary, check bits making the header a multiple of 31
+    let mut output = vec![0x78, 0x01];
+    let compressed = deflate_fixed(bytes);
+    if compressed.len() < bytes.len() {
+        output.extend_from_slice(&compressed);
+    } else {
+        output.extend_from_slice(&deflate_stored(bytes));
+    }
+    output.extend_from_slice(&adler32(bytes).to_be_bytes());
+    output
+}
+
+fn deflate_stored(bytes: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(bytes.len() + bytes.len() / MAX_STORED_BLOCK * 5 + 5);
+    let mut blocks = bytes.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        return vec![0x01, 0x00, 0x00, 0xff, 0xff];
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        output.push(if last { 0x01 } else { 0x00 });
+        output.extend_from_slice(&length.to_le_bytes());
+        output.extend_from_slice(&(!length).to_le_bytes());
+        output.extend_from_slice(block);
+    }
+    output
+}
+
+// greedy LZ77 remembering the last position of every 3 byte prefix
+fn deflate_fixed(bytes: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    // final block, fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut last_positions = vec![usize::MAX; HASH_SIZE];
+    let hash = |position: usize| {
+        let value = (bytes[position] as usize) << 16 | (bytes[position + 1] as usize) << 8 | bytes[position + 2] as usize;
+        (value.wrapping_mul(2_654_435_761) >> 8) & (HASH_SIZE - 1)
+    };
+
+    let mut position = 0;
+    while position < bytes.len() {
+        let mut length = 0;
+        let mut distance = 0;
+        if position + MIN_MATCH <= bytes.len() {
+            let key = hash(position);
+            let candidate = last_positions[key];
+            last_positions[key] = position;
+            if candidate != usize::MAX && position - candidate <= WINDOW_SIZE {
+                let limit = MAX_MATCH.min(bytes.len() - position);
+                while length < limit && bytes[candidate + length] == bytes[position + length] {
+                    length += 1;
+                }
+                distance = position - candidate;
+            }
+        }
+
+        if length >= MIN_MATCH {
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+            for skipped in position + 1..position + length {
+                if skipped + MIN_MATCH <= bytes.len() {
+                    last_positions[hash(skipped)] = skipped;
+                }
+            }
+            position += length;
+        } else {
+            write_literal(&mut writer, bytes[position] as u16);
+            position += 1;
+        }
+    }
+    write_literal(&mut writer, 256);
+    writer.finish()
+}
+
+// fixed literal/length code lengths: 0-143 8 bits, 144-255 9, 256-279 7, 280-287 8
+fn write_literal(writer: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_length(writer: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASES.iter().rposition(|base| *base as usize <= length).unwrap_or(0);
+    write_literal(writer, 257 + index as u16);
+    let extra = LENGTH_EXTRA_BITS[index];
+    if extra > 0 {
+        writer.write_bits((length - LENGTH_BASES[index] as usize) as u32, extra);
+    }
+}
+
+fn write_distance(writer: &mut BitWriter, distance: usize) {
+    let index = DISTANCE_BASES.iter().rposition(|base| *base as usize <= distance).unwrap_or(0);
+    writer.write_code(index as u32, 5);
+    let extra = DISTANCE_EXTRA_BITS[index];
+    if extra > 0 {
+        writer.write_bits((distance - DISTANCE_BASES[index] as usize) as u32, extra);
+    }
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+        bit: u8,
+    }
+
+    impl BitReader<'_> {
+        fn read_bit(&mut self) -> u32 {
+            let bit = (self.bytes[self.position] >> self.bit) & 0b1;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+            bit as u32
+        }
+
+        fn read_bits(&mut self, count: u8) -> u32 {
+            (0..count).fold(0, |value, index| value | (self.read_bit() << index))
+        }
+
+        fn read_code(&mut self, count: u8) -> u32 {
+            (0..count).fold(0, |code, _| (code << 1) | self.read_bit())
+        }
+
+        fn align(&mut self) {
+            if self.bit > 0 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+    }
+
+    // a decoder for the stored and fixed Huffman blocks zlib_compress writes
+    pub fn zlib_decompress(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+        let mut reader = BitReader { bytes: &stream[2..], position: 0, bit: 0 };
+        let mut output: Vec<u8> = Vec::new();
+        loop {
+            let last = reader.read_bits(1) == 1;
+            match reader.read_bits(2) {
+                0 => {
+                    reader.align();
+                    let start = reader.position;
+                    let length = u16::from_le_bytes([reader.bytes[start], reader.bytes[start + 1]]) as usize;
+                    assert_eq!(!u16::from_le_bytes([reader.bytes[start + 2], reader.bytes[start + 3]]) as usize, length);
+                    output.extend_from_slice(&reader.bytes[start + 4..start + 4 + length]);
+                    reader.position = start + 4 + length;
+                }
+                1 => loop {
+                    let symbol = read_fixed_literal(&mut reader);
+                    if symbol < 256 {
+                        output.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let index = (symbol - 257) as usize;
+                    let length = LENGTH_BASES[index] as usize + reader.read_bits(LENGTH_EXTRA_BITS[index]) as usize;
+                    let index = reader.read_code(5) as usize;
+                    let distance = DISTANCE_BASES[index] as usize + reader.read_bits(DISTANCE_EXTRA_BITS[index]) as usize;
+                    for _ in 0..length {
+                        output.push(output[output.len() - distance]);
+                    }
+                },
+                block_type => panic!("unexpected block type {}", block_type),
+            }
+            if last {
+                break;
+            }
+        }
+        reader.align();
+        let checksum = &reader.bytes[reader.position..reader.position + 4];
+        assert_eq!(checksum, adler32(&output).to_be_bytes());
+        output
+    }
+
+    fn read_fixed_literal(reader: &mut BitReader) -> u32 {
+        let mut code = reader.read_code(7);
+        if code <= 0x17 {
+            return code + 256;
+        }
+        code = (code << 1) | reader.read_bit();
+        match code {
+            0x30..=0xbf => code - 0x30,
+            0xc0..=0xc7 => code - 0xc0 + 280,
+            _ => ((code << 1) | reader.read_bit()) - 0x190 + 144,
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let repeated: Vec<u8> = b"NES ".iter().cycle().take(5000).cloned().collect();
+        let noise: Vec<u8> = (0..70000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        for bytes in [Vec::new(), vec![7], repeated, noise, all_bytes] {
+            assert_eq!(zlib_decompress(&zlib_compress(&bytes)), bytes);
+        }
+    }
+}
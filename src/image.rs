@@ -0,0 +1,226 @@
+use std::fs;
+use std::path::Path;
+use crate::crc32::Crc32;
+use crate::deflate::zlib_compress;
+use crate::ppu::palette::Palette;
+use crate::ppu::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+
+// scanlines most NTSC TVs hide at the top and bottom of the picture
+pub const OVERSCAN_LINES: usize = 8;
+
+// 24 bit RGB pixels, row by row
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![0; width * height * 3],
+        }
+    }
+
+    // the PPU's framebuffer through a palette
+    pub fn from_framebuffer(framebuffer: &[u16], palette: &Palette) -> Image {
+        let mut image = Image::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+        for (pixel, color) in image.pixels.chunks_mut(3).zip(framebuffer.iter()) {
+            pixel.copy_from_slice(&palette.get_color(*color));
+        }
+        image
+    }
+
+    // RGBA8 frames such as the NTSC filter's, alpha is dropped
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Image {
+        let mut image = Image::new(width, height);
+        for (pixel, color) in image.pixels.chunks_mut(3).zip(rgba.chunks(4)) {
+            pixel.copy_from_slice(&color[..3]);
+        }
+        image
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let offset = (y * self.width + x) * 3;
+        [self.pixels[offset], self.pixels[offset + 1], self.pixels[offset + 2]]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let offset = (y * self.width + x) * 3;
+            self.pixels[offset..offset + 3].copy_from_slice(&color);
+        }
+    }
+
+    // the part of the image inside the given margins
+    pub fn crop(&self, left: usize, top: usize, right: usize, bottom: usize) -> Image {
+        let width = self.width.saturating_sub(left.saturating_add(right));
+        let height = self.height.saturating_sub(top.saturating_add(bottom));
+        let mut image = Image::new(width, height);
+        if width == 0 || height == 0 {
+            return image;
+        }
+        for y in 0..height {
+            let source = ((y + top) * self.width + left) * 3;
+            image.pixels[y * width * 3..(y + 1) * width * 3].copy_from_slice(&self.pixels[source..source + width * 3]);
+        }
+        image
+    }
+
+    pub fn crop_overscan(&self) -> Image {
+        self.crop(0, OVERSCAN_LINES, 0, OVERSCAN_LINES)
+    }
+
+    // binary PPM, from http://netpbm.sourceforge.net/doc/ppm.html
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend_from_slice(&self.pixels);
+        bytes
+    }
+
+    // 8 bit truecolor PNG, from https://www.w3.org/TR/png/
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // bit depth 8, color type 2 (RGB), default compression and filter, no interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        // every row starts with its filter type, 0 leaves it unfiltered
+        let mut rows = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        let stride = self.width * 3;
+        for y in 0..self.height {
+            rows.push(0);
+            rows.extend_from_slice(&self.pixels[y * stride..(y + 1) * stride]);
+        }
+
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        write_png_chunk(&mut bytes, b"IHDR", &header);
+        write_png_chunk(&mut bytes, b"IDAT", &zlib_compress(&rows));
+        write_png_chunk(&mut bytes, b"IEND", &[]);
+        bytes
+    }
+
+    pub fn save_ppm(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_ppm()).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_png()).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    // PPM for .ppm files, PNG for everything else
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let extension = Path::new(path).extension().and_then(|extension| extension.to_str());
+        match extension {
+            Some(extension) if extension.eq_ignore_ascii_case("ppm") => self.save_ppm(path),
+            _ => self.save_png(path),
+        }
+    }
+}
+
+// writes the frame as PNG, or PPM by the file extension
+pub fn save_screenshot(framebuffer: &[u16], palette: &Palette, path: &str, crop_overscan: bool) -> Result<(), String> {
+    let image = Image::from_framebuffer(framebuffer, palette);
+    if crop_overscan {
+        image.crop_overscan().save(path)
+    } else {
+        image.save(path)
+    }
+}
+
+fn write_png_chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(data);
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    bytes.extend_from_slice(&crc.get_value().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crc32::crc32;
+    use crate::deflate::tests::zlib_decompress;
+
+    fn build_image(width: usize, height: usize) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, [x as u8, y as u8, (x ^ y) as u8]);
+            }
+        }
+        image
+    }
+
+    // reads back the chunks, checking their CRCs, and the unfiltered rows
+    fn decode_png(bytes: &[u8]) -> Image {
+        assert_eq!(&bytes[..8], PNG_SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut position = 8;
+        while position < bytes.len() {
+            let length = u32::from_be_bytes([bytes[position], bytes[position + 1], bytes[position + 2], bytes[position + 3]]) as usize;
+            let kind_and_data = &bytes[position + 4..position + 8 + length];
+            let crc = &bytes[position + 8 + length..position + 12 + length];
+            assert_eq!(crc, crc32(kind_and_data).to_be_bytes());
+            chunks.push((&kind_and_data[..4], &kind_and_data[4..]));
+            position += 12 + length;
+        }
+        let kinds: Vec<&[u8]> = chunks.iter().map(|chunk| chunk.0).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+
+        let header = chunks[0].1;
+        let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        assert_eq!(&header[8..], [8, 2, 0, 0, 0]);
+        let rows = zlib_decompress(chunks[1].1);
+        assert_eq!(rows.len(), (width * 3 + 1) * height);
+        let mut pixels = Vec::new();
+        for row in rows.chunks(width * 3 + 1) {
+            assert_eq!(row[0], 0);
+            pixels.extend_from_slice(&row[1..]);
+        }
+        Image::from_rgba(width, height, &pixels.chunks(3).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xff]).collect::<Vec<u8>>())
+    }
+
+    #[test]
+    fn png_round_trips() {
+        for (width, height) in [(256, 240), (3, 1), (0, 0)] {
+            let image = build_image(width, height);
+            let decoded = decode_png(&image.to_png());
+            assert_eq!((decoded.get_width(), decoded.get_height()), (width, height));
+            assert_eq!(decoded.get_pixels(), image.get_pixels());
+        }
+    }
+
+    #[test]
+    fn crops_down_to_nothing() {
+        let image = build_image(256, 240);
+        let cropped = image.crop(300, 0, 0, 0);
+        assert_eq!((cropped.get_width(), cropped.get_height()), (0, 240));
+        assert!(cropped.get_pixels().is_empty());
+        let cropped = image.crop(0, 120, 0, 120);
+        assert_eq!((cropped.get_width(), cropped.get_height()), (256, 0));
+        let cropped = image.crop(16, 8, 0, 8);
+        assert_eq!((cropped.get_width(), cropped.get_height()), (240, 224));
+        assert_eq!(cropped.get_pixel(0, 0), image.get_pixel(16, 8));
+    }
+}
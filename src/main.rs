@@ -1,7 +1,35 @@
+// CPU, NROM and friends are named after the hardware, and modules share the
+// name of the type they hold
+#![allow(clippy::upper_case_acronyms, clippy::module_inception)]
+// EnumFromStr recurses once per instruction name
+#![recursion_limit = "256"]
+
 #[allow(dead_code)]
 mod cpu;
 #[allow(dead_code)]
 mod memory;
+#[allow(dead_code)]
+mod apu;
+#[allow(dead_code)]
+mod ppu;
+#[allow(dead_code)]
+mod cartridge;
+#[allow(dead_code)]
+mod mapper;
+#[allow(dead_code)]
+mod crc32;
+#[allow(dead_code)]
+mod deflate;
+#[allow(dead_code)]
+mod image;
+#[allow(dead_code)]
+mod patch;
+#[allow(dead_code)]
+mod nsf_player;
+#[allow(dead_code)]
+mod timing;
+#[allow(dead_code)]
+mod controller;
 #[macro_use] extern crate custom_derive;
 #[macro_use] extern crate enum_derive;
 
@@ -23,5 +51,5 @@ fn main() {
     let mem1: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
     let mut cpu1: CPU = CPU::new(Rc::clone(&mem1));
     cpu1.execute(instruction1);
-    println!("{}", ((255 as u8) as i8) as i16);
+    println!("{}", (255_u8 as i8) as i16);
 }
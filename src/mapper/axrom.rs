@@ -0,0 +1,61 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+
+// from https://wiki.nesdev.com/w/index.php/AxROM
+const PRG_BANK_SIZE: usize = 32 * 1024;
+const CHR_BANK_SIZE: usize = 8 * 1024;
+
+pub struct AxROM {
+    data: CartridgeData,
+    bus_conflicts: bool,
+    prg_bank: u8,
+    mirroring: Mirroring,
+}
+
+impl AxROM {
+    pub fn new(data: CartridgeData, bus_conflicts: bool) -> AxROM {
+        AxROM {
+            data,
+            bus_conflicts,
+            prg_bank: 0,
+            mirroring: Mirroring::SingleScreenA,
+        }
+    }
+}
+
+impl Mapper for AxROM {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xffff => self.data.read_prg_rom(PRG_BANK_SIZE, self.prg_bank as usize, (address & 0x7fff) as usize),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            let value = if self.bus_conflicts { value & self.read_prg(address) } else { value };
+            self.prg_bank = value & 0x07;
+            self.mirroring = if (value & 0x10) != 0 { Mirroring::SingleScreenB } else { Mirroring::SingleScreenA };
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.data.read_chr(CHR_BANK_SIZE, 0, address as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.data.write_chr(CHR_BANK_SIZE, 0, address as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
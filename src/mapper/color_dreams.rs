@@ -0,0 +1,63 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+
+// from https://wiki.nesdev.com/w/index.php/Color_Dreams
+const PRG_BANK_SIZE: usize = 32 * 1024;
+const CHR_BANK_SIZE: usize = 8 * 1024;
+
+pub struct ColorDreams {
+    data: CartridgeData,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    prg_bank: u8,
+    chr_bank: u8,
+}
+
+impl ColorDreams {
+    pub fn new(data: CartridgeData, mirroring: Mirroring, bus_conflicts: bool) -> ColorDreams {
+        ColorDreams {
+            data,
+            mirroring,
+            bus_conflicts,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xffff => self.data.read_prg_rom(PRG_BANK_SIZE, self.prg_bank as usize, (address & 0x7fff) as usize),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            let value = if self.bus_conflicts { value & self.read_prg(address) } else { value };
+            self.prg_bank = value & 0b11;
+            self.chr_bank = value >> 4;
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.data.read_chr(CHR_BANK_SIZE, self.chr_bank as usize, address as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.data.write_chr(CHR_BANK_SIZE, self.chr_bank as usize, address as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
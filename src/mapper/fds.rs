@@ -0,0 +1,440 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::cartridge::fds;
+use crate::mapper::mapper::Mapper;
+use crate::mapper::fds_audio::FdsAudio;
+
+// from https://wiki.nesdev.com/w/index.php/Family_Computer_Disk_System
+const BIOS_SIZE: usize = 8 * 1024;
+const PRG_RAM_SIZE: usize = 32 * 1024;
+
+// the drive needs a while to spin up before it reaches the first block
+const END_OF_HEAD_DELAY: u32 = 50000;
+// about 96.4kHz bit rate, one byte every 150 CPU cycles
+const BYTE_DELAY: u32 = 150;
+// a swapped disk stays out of the drive for about a second so the BIOS notices
+const INSERT_DELAY: u32 = 1_789_773;
+
+pub struct FDS {
+    data: CartridgeData,
+    mirroring: Mirroring,
+    audio: FdsAudio,
+
+    // the image in its file layout, the save. Sides the drive wrote to are
+    // rebuilt into it when the save is taken, the others keep the loaded bytes.
+    image: Vec<u8>,
+    dirty_sides: Vec<bool>,
+    // raw drive data of every side, with gaps and CRCs
+    sides: Vec<Vec<u8>>,
+    disk: Option<usize>,
+    pending_disk: Option<usize>,
+    insert_delay: u32,
+
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq: bool,
+
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    previous_crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+    disk_irq: bool,
+
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    transfer_complete: bool,
+    position: usize,
+    delay: u32,
+    read_data: u8,
+    write_data: u8,
+    crc: fds::Crc16,
+}
+
+impl FDS {
+    pub fn new(data: CartridgeData, image: &[u8]) -> Result<FDS, String> {
+        if data.prg_rom.len() != BIOS_SIZE {
+            return Err(format!("FDS BIOS must be {} bytes, found {}", BIOS_SIZE, data.prg_rom.len()));
+        }
+        if data.prg_ram.len() < PRG_RAM_SIZE {
+            return Err(String::from("FDS needs 32KB of PRG RAM"));
+        }
+        let sides = fds::parse_sides(image)?;
+        Ok(FDS {
+            data,
+            mirroring: Mirroring::Horizontal,
+            audio: FdsAudio::new(),
+            image: image.to_vec(),
+            dirty_sides: vec![false; sides.len()],
+            // the first side is in the drive at power on
+            disk: Some(0),
+            sides,
+            pending_disk: None,
+            insert_delay: 0,
+            disk_registers_enabled: true,
+            sound_registers_enabled: true,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            previous_crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            transfer_complete: false,
+            position: 0,
+            delay: 0,
+            read_data: 0,
+            write_data: 0,
+            crc: fds::Crc16::new(),
+        })
+    }
+
+    fn is_disk_inserted(&self) -> bool {
+        self.disk.is_some() && self.insert_delay == 0
+    }
+
+    fn read_register(&mut self, address: u16) -> u8 {
+        match address {
+            0x4030 => {
+                let mut value = 0;
+                if self.timer_irq {
+                    value |= 0x01;
+                }
+                if self.transfer_complete {
+                    value |= 0x02;
+                }
+                if self.end_of_head {
+                    value |= 0x40;
+                }
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+                value
+            }
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                self.read_data
+            }
+            0x4032 => {
+                let inserted = self.is_disk_inserted();
+                let mut value = 0x40;
+                if !inserted {
+                    // no disk, so not ready and not writable either
+                    value |= 0b101;
+                }
+                if !inserted || !self.scanning {
+                    value |= 0b010;
+                }
+                value
+            }
+            // battery of the drive is good
+            0x4033 => 0x80,
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4020 => { self.timer_reload = (self.timer_reload & 0xff00) | value as u16; }
+            0x4021 => { self.timer_reload = (self.timer_reload & 0x00ff) | ((value as u16) << 8); }
+            0x4022 => {
+                self.timer_repeat = (value & 0x01) != 0;
+                self.timer_enabled = (value & 0x02) != 0 && self.disk_registers_enabled;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = (value & 0x01) != 0;
+                self.sound_registers_enabled = (value & 0x02) != 0;
+                if !self.disk_registers_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 => {
+                self.write_data = value;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 => {
+                self.motor_on = (value & 0x01) != 0;
+                self.reset_transfer = (value & 0x02) != 0;
+                self.read_mode = (value & 0x04) != 0;
+                self.mirroring = if (value & 0x08) != 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+                self.crc_control = (value & 0x10) != 0;
+                self.disk_ready = (value & 0x40) != 0;
+                self.disk_irq_enabled = (value & 0x80) != 0;
+                self.disk_irq = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn step_timer(&mut self) {
+        if !self.timer_enabled {
+            return;
+        }
+        if self.timer_counter > 0 {
+            self.timer_counter -= 1;
+            return;
+        }
+        self.timer_irq = true;
+        self.timer_counter = self.timer_reload;
+        if !self.timer_repeat {
+            self.timer_enabled = false;
+        }
+    }
+
+    fn step_drive(&mut self) {
+        if self.insert_delay > 0 {
+            self.insert_delay -= 1;
+            if self.insert_delay == 0 {
+                self.disk = self.pending_disk.take();
+            }
+            return;
+        }
+        let side = match self.disk {
+            Some(side) if self.motor_on => side,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = END_OF_HEAD_DELAY;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        if self.read_mode {
+            self.read_byte(side);
+        } else {
+            self.write_byte(side);
+        }
+        self.previous_crc_control = self.crc_control;
+
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.end_of_head = true;
+        } else {
+            self.delay = BYTE_DELAY;
+        }
+    }
+
+    fn read_byte(&mut self, side: usize) {
+        let value = self.sides[side][self.position];
+        if !self.previous_crc_control {
+            self.crc.update(value);
+        }
+        let mut irq = self.disk_irq_enabled;
+        if !self.disk_ready {
+            self.gap_ended = false;
+            self.crc.reset();
+        } else if value != 0 && !self.gap_ended {
+            // the gap end mark itself is not handed to the BIOS
+            self.gap_ended = true;
+            irq = false;
+        }
+        if self.gap_ended {
+            self.transfer_complete = true;
+            self.read_data = value;
+            if irq {
+                self.disk_irq = true;
+            }
+        }
+    }
+
+    fn write_byte(&mut self, side: usize) {
+        let mut value = 0;
+        if !self.crc_control {
+            self.transfer_complete = true;
+            value = self.write_data;
+            if self.disk_irq_enabled {
+                self.disk_irq = true;
+            }
+        }
+        if !self.disk_ready {
+            value = 0;
+            self.crc.reset();
+        }
+        if !self.crc_control {
+            self.crc.update(value);
+        } else {
+            // the drive writes the two CRC bytes itself, low byte first
+            let crc = self.crc.get_value();
+            value = if !self.previous_crc_control { (crc & 0xff) as u8 } else { (crc >> 8) as u8 };
+        }
+        self.sides[side][self.position] = value;
+        self.dirty_sides[side] = true;
+        self.gap_ended = false;
+    }
+}
+
+impl Mapper for FDS {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x4030..=0x4033 if self.disk_registers_enabled => self.read_register(address),
+            0x4040..=0x4092 if self.sound_registers_enabled => self.audio.read_register(address),
+            0x6000..=0xdfff => self.data.read_prg_ram(PRG_RAM_SIZE, 0, (address - 0x6000) as usize),
+            0xe000..=0xffff => self.data.read_prg_rom(BIOS_SIZE, 0, (address - 0xe000) as usize),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x4020..=0x4022 => self.write_register(address, value),
+            0x4023..=0x4025 if self.disk_registers_enabled || address == 0x4023 => self.write_register(address, value),
+            0x4040..=0x408a if self.sound_registers_enabled => self.audio.write_register(address, value),
+            0x6000..=0xdfff => self.data.write_prg_ram(PRG_RAM_SIZE, 0, (address - 0x6000) as usize, value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.data.read_chr(8 * 1024, 0, address as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.data.write_chr(8 * 1024, 0, address as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn step_cpu(&mut self) {
+        self.step_timer();
+        self.audio.step_cpu();
+        self.step_drive();
+    }
+
+    fn get_irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+
+    // the disk itself is the save, written back in the image layout
+    fn get_battery_ram(&mut self) -> Vec<u8> {
+        let header_size = fds::get_header_size(&self.image);
+        for (index, side) in self.sides.iter().enumerate() {
+            if self.dirty_sides[index] {
+                let start = header_size + index * fds::SIDE_SIZE;
+                self.image[start..start + fds::SIDE_SIZE].copy_from_slice(&fds::rebuild_side(side));
+                self.dirty_sides[index] = false;
+            }
+        }
+        self.image.clone()
+    }
+
+    fn load_battery_ram(&mut self, bytes: &[u8]) {
+        if let Ok(sides) = fds::parse_sides(bytes) {
+            if sides.len() == self.sides.len() {
+                self.image = bytes.to_vec();
+                self.dirty_sides = vec![false; sides.len()];
+                self.sides = sides;
+            }
+        }
+    }
+
+    fn get_disk_sides(&self) -> usize {
+        self.sides.len()
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.disk = None;
+        self.pending_disk = side.filter(|side| *side < self.sides.len());
+        self.insert_delay = INSERT_DELAY;
+    }
+
+    fn get_inserted_disk(&self) -> Option<usize> {
+        if self.insert_delay > 0 { self.pending_disk } else { self.disk }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two sides holding a disk info and a file amount block, padded with
+    // $FF where a rebuilt side would have zeros
+    fn build_image() -> Vec<u8> {
+        let mut image = vec![0x46, 0x44, 0x53, 0x1a, 2];
+        image.resize(fds::HEADER_SIZE, 0);
+        for _ in 0..2 {
+            let mut side = vec![1];
+            side.extend_from_slice(b"*NINTENDO-HVC*");
+            side.resize(56, 0);
+            side.extend_from_slice(&[2, 0]);
+            side.resize(fds::SIDE_SIZE, 0xff);
+            image.extend_from_slice(&side);
+        }
+        image
+    }
+
+    fn create_fds(image: &[u8]) -> FDS {
+        FDS::new(CartridgeData::new(vec![0; BIOS_SIZE], Vec::new(), 8 * 1024, PRG_RAM_SIZE), image).unwrap()
+    }
+
+    #[test]
+    fn untouched_disk_saves_as_loaded() {
+        let image = build_image();
+        assert_eq!(create_fds(&image).get_battery_ram(), image);
+    }
+
+    #[test]
+    fn only_written_sides_are_rebuilt() {
+        let image = build_image();
+        let mut fds = create_fds(&image);
+        fds.dirty_sides[1] = true;
+        let saved = fds.get_battery_ram();
+        let second_side = fds::HEADER_SIZE + fds::SIDE_SIZE;
+        assert_eq!(saved[..second_side], image[..second_side]);
+        assert_eq!(saved[second_side..second_side + 58], image[second_side..second_side + 58]);
+        assert!(saved[second_side + 58..].iter().all(|&byte| byte == 0));
+        assert!(!fds.dirty_sides[1]);
+    }
+}
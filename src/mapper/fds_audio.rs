@@ -0,0 +1,249 @@
+// from https://wiki.nesdev.com/w/index.php/FDS_audio
+
+const WAVE_TABLE_SIZE: usize = 64;
+const MOD_TABLE_SIZE: usize = 64;
+// mod table entries add these to the mod counter, entry 4 resets it
+const MOD_ADJUSTMENTS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+const MOD_RESET: u8 = 4;
+const MAX_GAIN: u8 = 32;
+// 2/2, 2/3, 2/4 and 2/5
+const MASTER_VOLUMES: [f32; 4] = [1.0, 2.0 / 3.0, 0.5, 0.4];
+// the full wave (63 * 32) peaks a little above a single APU pulse channel
+const OUTPUT_SCALE: f32 = 0.0001;
+
+struct Envelope {
+    speed: u8,
+    gain: u8,
+    increase: bool,
+    disabled: bool,
+    timer: u32,
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope {
+            speed: 0,
+            gain: 0,
+            increase: false,
+            disabled: true,
+            timer: 0,
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        self.speed = value & 0x3f;
+        self.increase = (value & 0x40) != 0;
+        self.disabled = (value & 0x80) != 0;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.timer = 0;
+    }
+
+    fn tick(&mut self, master_speed: u8) {
+        if self.disabled || master_speed == 0 {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < 8 * (self.speed as u32 + 1) * master_speed as u32 {
+            return;
+        }
+        self.timer = 0;
+        if self.increase && self.gain < MAX_GAIN {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+struct Modulator {
+    envelope: Envelope,
+    frequency: u16,
+    halted: bool,
+    accumulator: u32,
+    position: usize,
+    // 7 bit signed
+    counter: i8,
+    table: [u8; MOD_TABLE_SIZE],
+}
+
+impl Modulator {
+    fn new() -> Modulator {
+        Modulator {
+            envelope: Envelope::new(),
+            frequency: 0,
+            halted: true,
+            accumulator: 0,
+            position: 0,
+            counter: 0,
+            table: [0; MOD_TABLE_SIZE],
+        }
+    }
+
+    fn set_counter(&mut self, value: u8) {
+        // sign extend the 7 bit value
+        self.counter = ((value << 1) as i8) >> 1;
+    }
+
+    // each write fills two consecutive entries, only while the unit is halted
+    fn write_table(&mut self, value: u8) {
+        if !self.halted {
+            return;
+        }
+        for _ in 0..2 {
+            self.table[self.position] = value & 0x07;
+            self.position = (self.position + 1) % MOD_TABLE_SIZE;
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.halted || self.frequency == 0 {
+            return;
+        }
+        self.accumulator += self.frequency as u32;
+        if self.accumulator < 0x10000 {
+            return;
+        }
+        self.accumulator &= 0xffff;
+
+        let entry = self.table[self.position];
+        let counter = if entry == MOD_RESET { 0 } else { self.counter as i16 + MOD_ADJUSTMENTS[entry as usize] as i16 };
+        // wraps around within 7 bits
+        self.set_counter((counter & 0x7f) as u8);
+        self.position = (self.position + 1) % MOD_TABLE_SIZE;
+    }
+
+    fn get_pitch(&self, pitch: u16) -> u32 {
+        if self.halted {
+            return pitch as u32;
+        }
+        let counter = self.counter as i32;
+        let mut temp = counter * self.envelope.gain as i32;
+        let remainder = temp & 0x0f;
+        temp >>= 4;
+        if remainder > 0 && (temp & 0x80) == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= pitch as i32;
+        let remainder = temp & 0x3f;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (pitch as i32 + temp).max(0) as u32
+    }
+}
+
+pub struct FdsAudio {
+    wave_table: [u8; WAVE_TABLE_SIZE],
+    wave_write_enabled: bool,
+    wave_halted: bool,
+    wave_frequency: u16,
+    wave_accumulator: u32,
+    wave_position: usize,
+    volume: Envelope,
+    // the volume gain only takes effect at the start of a wave cycle
+    volume_latch: u8,
+    envelopes_disabled: bool,
+    master_envelope_speed: u8,
+    master_volume: usize,
+    modulator: Modulator,
+}
+
+impl FdsAudio {
+    pub fn new() -> FdsAudio {
+        FdsAudio {
+            wave_table: [0; WAVE_TABLE_SIZE],
+            wave_write_enabled: false,
+            wave_halted: true,
+            wave_frequency: 0,
+            wave_accumulator: 0,
+            wave_position: 0,
+            volume: Envelope::new(),
+            volume_latch: 0,
+            envelopes_disabled: false,
+            master_envelope_speed: 0xe8,
+            master_volume: 0,
+            modulator: Modulator::new(),
+        }
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            0x4040..=0x407f => self.wave_table[(address - 0x4040) as usize] | 0x40,
+            0x4090 => self.volume.gain | 0x40,
+            0x4092 => self.modulator.envelope.gain | 0x40,
+            _ => 0,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4040..=0x407f if self.wave_write_enabled => {
+                self.wave_table[(address - 0x4040) as usize] = value & 0x3f;
+            }
+            0x4080 => self.volume.write(value),
+            0x4082 => { self.wave_frequency = (self.wave_frequency & 0x0f00) | value as u16; }
+            0x4083 => {
+                self.wave_frequency = (self.wave_frequency & 0x00ff) | (((value & 0x0f) as u16) << 8);
+                self.envelopes_disabled = (value & 0x40) != 0;
+                self.wave_halted = (value & 0x80) != 0;
+                if self.wave_halted {
+                    self.wave_accumulator = 0;
+                    self.wave_position = 0;
+                }
+            }
+            0x4084 => self.modulator.envelope.write(value),
+            0x4085 => self.modulator.set_counter(value & 0x7f),
+            0x4086 => { self.modulator.frequency = (self.modulator.frequency & 0x0f00) | value as u16; }
+            0x4087 => {
+                self.modulator.frequency = (self.modulator.frequency & 0x00ff) | (((value & 0x0f) as u16) << 8);
+                self.modulator.halted = (value & 0x80) != 0;
+                if self.modulator.halted {
+                    self.modulator.accumulator = 0;
+                }
+            }
+            0x4088 => self.modulator.write_table(value),
+            0x4089 => {
+                self.wave_write_enabled = (value & 0x80) != 0;
+                self.master_volume = (value & 0b11) as usize;
+            }
+            0x408a => { self.master_envelope_speed = value; }
+            _ => {}
+        }
+    }
+
+    pub fn step_cpu(&mut self) {
+        if !self.wave_halted && !self.envelopes_disabled {
+            self.volume.tick(self.master_envelope_speed);
+            self.modulator.envelope.tick(self.master_envelope_speed);
+        }
+        self.modulator.clock();
+
+        // the wave holds its position while the table is being written
+        if self.wave_halted || self.wave_write_enabled {
+            return;
+        }
+        self.wave_accumulator += self.modulator.get_pitch(self.wave_frequency);
+        if self.wave_accumulator < 0x10000 {
+            return;
+        }
+        self.wave_accumulator &= 0xffff;
+        self.wave_position = (self.wave_position + 1) % WAVE_TABLE_SIZE;
+        if self.wave_position == 0 {
+            self.volume_latch = self.volume.gain.min(MAX_GAIN);
+        }
+    }
+
+    pub fn get_output(&self) -> f32 {
+        let sample = self.wave_table[self.wave_position] as f32 * self.volume_latch as f32;
+        sample * MASTER_VOLUMES[self.master_volume] * OUTPUT_SCALE
+    }
+}
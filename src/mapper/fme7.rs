@@ -0,0 +1,150 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+use crate::mapper::sunsoft5b_audio::Sunsoft5bAudio;
+
+// from https://wiki.nesdev.com/w/index.php/Sunsoft_FME-7
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+
+pub struct FME7 {
+    data: CartridgeData,
+    command: u8,
+    chr_banks: [u8; 8],
+    // $6000, $8000, $A000 and $C000
+    prg_banks: [u8; 4],
+    mirroring: Mirroring,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq: bool,
+    audio: Sunsoft5bAudio,
+}
+
+impl FME7 {
+    pub fn new(data: CartridgeData) -> FME7 {
+        FME7 {
+            data,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: Mirroring::Vertical,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq: false,
+            audio: Sunsoft5bAudio::new(),
+        }
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0..=7 => { self.chr_banks[self.command as usize] = value; }
+            8..=11 => { self.prg_banks[(self.command - 8) as usize] = value; }
+            12 => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                };
+            }
+            13 => {
+                self.irq_enabled = (value & 0x01) != 0;
+                self.irq_counter_enabled = (value & 0x80) != 0;
+                self.irq = false;
+            }
+            14 => { self.irq_counter = (self.irq_counter & 0xff00) | value as u16; }
+            _ => { self.irq_counter = (self.irq_counter & 0x00ff) | ((value as u16) << 8); }
+        }
+    }
+
+    // $6000 bank: bit 6 selects RAM instead of ROM, bit 7 enables the RAM
+    fn is_prg_ram_selected(&self) -> bool {
+        (self.prg_banks[0] & 0x40) != 0
+    }
+
+    fn is_prg_ram_enabled(&self) -> bool {
+        (self.prg_banks[0] & 0x80) != 0
+    }
+}
+
+impl Mapper for FME7 {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff => {
+                let bank = (self.prg_banks[0] & 0x3f) as usize;
+                let offset = (address & 0x1fff) as usize;
+                if !self.is_prg_ram_selected() {
+                    self.data.read_prg_rom(PRG_BANK_SIZE, bank, offset)
+                } else if self.is_prg_ram_enabled() {
+                    self.data.read_prg_ram(PRG_BANK_SIZE, bank, offset)
+                } else {
+                    0
+                }
+            }
+            0x8000..=0xdfff => {
+                let bank = (self.prg_banks[(((address - 0x8000) >> 13) + 1) as usize] & 0x3f) as usize;
+                self.data.read_prg_rom(PRG_BANK_SIZE, bank, (address & 0x1fff) as usize)
+            }
+            0xe000..=0xffff => {
+                let last_bank = self.data.get_prg_bank_count(PRG_BANK_SIZE).max(1) - 1;
+                self.data.read_prg_rom(PRG_BANK_SIZE, last_bank, (address & 0x1fff) as usize)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7fff if self.is_prg_ram_selected() && self.is_prg_ram_enabled() => {
+                let bank = (self.prg_banks[0] & 0x3f) as usize;
+                self.data.write_prg_ram(PRG_BANK_SIZE, bank, (address & 0x1fff) as usize, value);
+            }
+            0x8000..=0x9fff => { self.command = value & 0x0f; }
+            0xa000..=0xbfff => self.write_parameter(value),
+            0xc000..=0xdfff => self.audio.select_register(value),
+            0xe000..=0xffff => self.audio.write_register(value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.chr_banks[(address >> 10) as usize] as usize;
+        self.data.read_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_banks[(address >> 10) as usize] as usize;
+        self.data.write_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn step_cpu(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xffff && self.irq_enabled {
+                self.irq = true;
+            }
+        }
+        self.audio.step_cpu();
+    }
+
+    fn get_irq(&self) -> bool {
+        self.irq
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+}
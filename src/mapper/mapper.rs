@@ -0,0 +1,218 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring, NametableSource};
+use crate::cartridge::header::Header;
+use crate::mapper::nrom::NROM;
+use crate::mapper::mmc1::MMC1;
+use crate::mapper::mmc2::{MMC2, MMC2Variant};
+use crate::mapper::mmc3::{MMC3, MMC3Variant};
+use crate::mapper::uxrom::UxROM;
+use crate::mapper::cnrom::CNROM;
+use crate::mapper::axrom::AxROM;
+use crate::mapper::gxrom::GxROM;
+use crate::mapper::color_dreams::ColorDreams;
+use crate::mapper::namco163::Namco163;
+use crate::mapper::vrc2::{VRC2, VRC2Variant};
+use crate::mapper::vrc6::VRC6;
+use crate::mapper::fme7::FME7;
+
+pub trait Mapper {
+    fn get_data(&self) -> &CartridgeData;
+    fn get_data_mut(&mut self) -> &mut CartridgeData;
+
+    // CPU bus, $4020-$FFFF
+    fn read_prg(&mut self, address: u16) -> u8;
+    fn write_prg(&mut self, address: u16, value: u8);
+
+    // PPU bus, $0000-$1FFF
+    fn read_chr(&mut self, address: u16) -> u8;
+    fn write_chr(&mut self, address: u16, value: u8);
+
+    // CHR as the PPU would see it, without the side effects of a fetch, for debuggers
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        self.read_chr(address)
+    }
+
+    // PPU bus accesses outside of CHR (nametable fetches, PPUADDR writes),
+    // for mappers watching the address lines
+    fn notify_ppu_address(&mut self, _address: u16) {}
+
+    fn get_mirroring(&self) -> Mirroring;
+
+    // asked on every nametable access, table is 0-3 for $2000, $2400, $2800 and $2C00
+    fn get_nametable_source(&self, table: usize) -> NametableSource {
+        NametableSource::Page(self.get_mirroring().get_nametable_page(table))
+    }
+
+    // asked on every pattern table access, for mappers that can put nametable
+    // memory in place of a 1KB CHR bank. None reads CHR through read_chr.
+    fn get_pattern_source(&self, _address: u16) -> Option<NametableSource> {
+        None
+    }
+
+    // called once per CPU cycle (M2) for mappers with cycle based logic
+    fn step_cpu(&mut self) {}
+
+    fn get_irq(&self) -> bool {
+        false
+    }
+
+    // expansion audio, on the same scale as the APU mixer output
+    fn get_audio_output(&self) -> f32 {
+        0.0
+    }
+
+    // memory kept alive by the battery, PRG RAM unless the mapper has its own NVRAM.
+    // Mutable so mappers can bring a cached save layout up to date first.
+    fn get_battery_ram(&mut self) -> Vec<u8> {
+        self.get_data().prg_ram.clone()
+    }
+
+    fn load_battery_ram(&mut self, bytes: &[u8]) {
+        let prg_ram = &mut self.get_data_mut().prg_ram;
+        let length = prg_ram.len().min(bytes.len());
+        prg_ram[..length].copy_from_slice(&bytes[..length]);
+    }
+
+    // disk drive of the Famicom Disk System, sides are numbered from 0
+    fn get_disk_sides(&self) -> usize {
+        0
+    }
+
+    // None ejects the disk
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+
+    fn get_inserted_disk(&self) -> Option<usize> {
+        None
+    }
+}
+
+pub fn create_mapper(header: &Header, data: CartridgeData) -> Result<Box<dyn Mapper>, String> {
+    let mirroring = header.mirroring;
+    match header.mapper {
+        0 => Ok(Box::new(NROM::new(data, mirroring))),
+        1 => Ok(Box::new(MMC1::new(data))),
+        2 => Ok(Box::new(UxROM::new(data, mirroring, has_bus_conflicts(header, true)))),
+        3 => Ok(Box::new(CNROM::new(data, mirroring, has_bus_conflicts(header, true)))),
+        4 => {
+            let variant = match header.submapper {
+                1 => MMC3Variant::MMC6,
+                4 => MMC3Variant::MMC3A,
+                _ => MMC3Variant::MMC3,
+            };
+            Ok(Box::new(MMC3::new(data, mirroring, variant)))
+        }
+        // ANROM has no bus conflicts, AMROM and AOROM do
+        7 => Ok(Box::new(AxROM::new(data, has_bus_conflicts(header, false)))),
+        9 => Ok(Box::new(MMC2::new(data, MMC2Variant::MMC2))),
+        10 => Ok(Box::new(MMC2::new(data, MMC2Variant::MMC4))),
+        11 => Ok(Box::new(ColorDreams::new(data, mirroring, has_bus_conflicts(header, true)))),
+        19 => Ok(Box::new(Namco163::new(data))),
+        21 | 22 | 23 | 25 => {
+            let (variant, register_lines, chr_shift) = get_vrc2_configuration(header.mapper, header.submapper);
+            Ok(Box::new(VRC2::new(data, variant, register_lines, chr_shift)))
+        }
+        // disk images have no iNES header and go through Cartridge::load_fds
+        20 => Err(String::from("FDS disk images need the FDS BIOS")),
+        24 => Ok(Box::new(VRC6::new(data, false))),
+        26 => Ok(Box::new(VRC6::new(data, true))),
+        66 => Ok(Box::new(GxROM::new(data, mirroring, has_bus_conflicts(header, true)))),
+        69 => Ok(Box::new(FME7::new(data))),
+        _ => Err(format!("mapper {} is not supported", header.mapper)),
+    }
+}
+
+// NES 2.0 submapper 1 marks boards without bus conflicts and 2 boards with them,
+// otherwise fall back to what the common board for the mapper does
+fn has_bus_conflicts(header: &Header, board_default: bool) -> bool {
+    match header.submapper {
+        1 => false,
+        2 => true,
+        _ => board_default,
+    }
+}
+
+// register select lines per board, from https://wiki.nesdev.com/w/index.php/NES_2.0_submappers
+// without a submapper the lines of all boards sharing the mapper number are combined
+fn get_vrc2_configuration(mapper: u16, submapper: u8) -> (VRC2Variant, (u16, u16), u8) {
+    match (mapper, submapper) {
+        // VRC4a, VRC4c
+        (21, 1) => (VRC2Variant::VRC4, (0x02, 0x04), 0),
+        (21, 2) => (VRC2Variant::VRC4, (0x40, 0x80), 0),
+        (21, _) => (VRC2Variant::VRC4, (0x42, 0x84), 0),
+        // VRC2a
+        (22, _) => (VRC2Variant::VRC2, (0x02, 0x01), 1),
+        // VRC4f, VRC4e, VRC2b
+        (23, 1) => (VRC2Variant::VRC4, (0x01, 0x02), 0),
+        (23, 2) => (VRC2Variant::VRC4, (0x04, 0x08), 0),
+        (23, 3) => (VRC2Variant::VRC2, (0x01, 0x02), 0),
+        (23, _) => (VRC2Variant::VRC4, (0x05, 0x0a), 0),
+        // VRC4b, VRC4d, VRC2c
+        (_, 1) => (VRC2Variant::VRC4, (0x02, 0x01), 0),
+        (_, 2) => (VRC2Variant::VRC4, (0x08, 0x04), 0),
+        (_, 3) => (VRC2Variant::VRC2, (0x02, 0x01), 0),
+        (_, _) => (VRC2Variant::VRC4, (0x0a, 0x05), 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRG_BANK_SIZE: usize = 8 * 1024;
+
+    // PRG ROM whose 8KB banks are filled with their number
+    fn create_vrc(mapper: u16, submapper: u8) -> VRC2 {
+        let prg_rom: Vec<u8> = (0..16).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect();
+        let chr_rom: Vec<u8> = (0..256).flat_map(|bank| vec![bank as u8; 1024]).collect();
+        let (variant, register_lines, chr_shift) = get_vrc2_configuration(mapper, submapper);
+        VRC2::new(CartridgeData::new(prg_rom, chr_rom, 0, 0x2000), variant, register_lines, chr_shift)
+    }
+
+    // $9002 on VRC4 selects the PRG swap mode, which moves the $8000 bank to $C000
+    fn is_swapped(mapper: &mut VRC2) -> bool {
+        mapper.write_prg(0x8000, 3);
+        mapper.read_prg(0xc000) == 3
+    }
+
+    #[test]
+    fn vrc4_register_lines_follow_the_submapper() {
+        let cases: [(u16, u8, &[u16]); 9] = [
+            (21, 1, &[0x9004]),
+            (21, 2, &[0x9080]),
+            (21, 0, &[0x9004, 0x9080]),
+            (23, 1, &[0x9002]),
+            (23, 2, &[0x9008]),
+            (23, 0, &[0x9002, 0x9008]),
+            (25, 1, &[0x9001]),
+            (25, 2, &[0x9004]),
+            (25, 0, &[0x9001, 0x9004]),
+        ];
+        for (mapper_number, submapper, addresses) in cases.iter() {
+            for address in addresses.iter() {
+                let mut mapper = create_vrc(*mapper_number, *submapper);
+                assert!(!is_swapped(&mut mapper));
+                mapper.write_prg(*address, 0b10);
+                assert!(is_swapped(&mut mapper), "mapper {}.{} ${:04X}", mapper_number, submapper, address);
+            }
+        }
+
+        // $9001 is $9002 on VRC4b, but only $9001 on VRC4f
+        let mut mapper = create_vrc(23, 1);
+        mapper.write_prg(0x9001, 0b10);
+        assert!(!is_swapped(&mut mapper));
+    }
+
+    #[test]
+    fn vrc2_variants_decode_chr_and_mirroring() {
+        // VRC2a drops the lowest CHR bank bit, and A0/A1 are swapped
+        let mut mapper = create_vrc(22, 0);
+        mapper.write_prg(0xb000, 0x06);
+        mapper.write_prg(0xb002, 0x01);
+        assert_eq!(mapper.read_chr(0x0000), 0x0b);
+
+        // VRC2b has no swap mode, every $9000 register sets the mirroring
+        let mut mapper = create_vrc(23, 3);
+        mapper.write_prg(0x9002, 0b11);
+        assert!(!is_swapped(&mut mapper));
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+    }
+}
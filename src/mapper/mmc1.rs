@@ -0,0 +1,278 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+
+// from https://wiki.nesdev.com/w/index.php/MMC1
+const PRG_BANK_SIZE: usize = 16 * 1024;
+const CHR_BANK_SIZE: usize = 4 * 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+const PRG_OUTER_BANK_SIZE: usize = 256 * 1024;
+
+const SHIFT_REGISTER_RESET: u8 = 0b10000;
+const CONTROL_POWER_ON: u8 = 0x0c;
+
+pub struct MMC1 {
+    data: CartridgeData,
+    shift_register: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+    cpu_cycle: u64,
+    last_write_cycle: Option<u64>,
+}
+
+impl MMC1 {
+    pub fn new(data: CartridgeData) -> MMC1 {
+        MMC1 {
+            data,
+            shift_register: SHIFT_REGISTER_RESET,
+            control: CONTROL_POWER_ON,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            cpu_cycle: 0,
+            last_write_cycle: None,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        // the MMC1 ignores writes on consecutive cycles, which drops the
+        // second write of read-modify-write instructions. Writes without a bus
+        // cycle between them are from the same instruction as well.
+        let consecutive = self.last_write_cycle.is_some_and(|cycle| self.cpu_cycle <= cycle + 1);
+        self.last_write_cycle = Some(self.cpu_cycle);
+        if consecutive {
+            return;
+        }
+
+        if (value & 0x80) != 0 {
+            self.shift_register = SHIFT_REGISTER_RESET;
+            self.control |= CONTROL_POWER_ON;
+            return;
+        }
+
+        // the initial 1 reaches bit 0 after four writes, the fifth write completes the value
+        let full = (self.shift_register & 0b1) == 1;
+        self.shift_register = (self.shift_register >> 1) | ((value & 0b1) << 4);
+        if !full {
+            return;
+        }
+
+        let register_value = self.shift_register & 0x1f;
+        match address {
+            0x8000..=0x9fff => { self.control = register_value; }
+            0xa000..=0xbfff => { self.chr_bank_0 = register_value; }
+            0xc000..=0xdfff => { self.chr_bank_1 = register_value; }
+            _ => { self.prg_bank = register_value; }
+        }
+        self.shift_register = SHIFT_REGISTER_RESET;
+    }
+
+    fn get_prg_mode(&self) -> u8 {
+        (self.control >> 2) & 0b11
+    }
+
+    fn is_chr_4k_mode(&self) -> bool {
+        ((self.control >> 4) & 0b1) == 1
+    }
+
+    fn is_prg_ram_enabled(&self) -> bool {
+        ((self.prg_bank >> 4) & 0b1) == 0
+    }
+
+    // SUROM and SXROM use CHR bank bit 4 to select a 256KB half of the 512KB PRG ROM
+    fn get_prg_outer_bank(&self) -> usize {
+        if self.data.prg_rom.len() > PRG_OUTER_BANK_SIZE {
+            ((self.chr_bank_0 >> 4) & 0b1) as usize
+        } else {
+            0
+        }
+    }
+
+    // SOROM uses CHR bank bit 3 and SXROM bits 2-3 to select the 8KB PRG RAM bank
+    fn get_prg_ram_bank(&self) -> usize {
+        match self.data.prg_ram.len() / PRG_RAM_BANK_SIZE {
+            0 | 1 => 0,
+            2 => ((self.chr_bank_0 >> 3) & 0b1) as usize,
+            _ => ((self.chr_bank_0 >> 2) & 0b11) as usize,
+        }
+    }
+
+    fn get_prg_bank(&self, address: u16) -> usize {
+        let inner_banks = PRG_OUTER_BANK_SIZE / PRG_BANK_SIZE;
+        let last_bank = (self.data.get_prg_bank_count(PRG_BANK_SIZE).min(inner_banks)).max(1) - 1;
+        let bank = (self.prg_bank & 0x0f) as usize;
+        let upper = address >= 0xc000;
+        let inner = match self.get_prg_mode() {
+            0 | 1 => (bank & !0b1) | if upper { 1 } else { 0 },
+            2 => if upper { bank } else { 0 },
+            _ => if upper { last_bank } else { bank },
+        };
+        self.get_prg_outer_bank() * inner_banks + inner
+    }
+
+    fn get_chr_bank(&self, address: u16) -> usize {
+        let upper = address >= 0x1000;
+        if self.is_chr_4k_mode() {
+            (if upper { self.chr_bank_1 } else { self.chr_bank_0 }) as usize
+        } else {
+            ((self.chr_bank_0 & !0b1) as usize) | if upper { 1 } else { 0 }
+        }
+    }
+}
+
+impl Mapper for MMC1 {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => {
+                self.data.read_prg_ram(PRG_RAM_BANK_SIZE, self.get_prg_ram_bank(), (address - 0x6000) as usize)
+            }
+            0x8000..=0xffff => {
+                let bank = self.get_prg_bank(address);
+                self.data.read_prg_rom(PRG_BANK_SIZE, bank, (address & 0x3fff) as usize)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => {
+                let bank = self.get_prg_ram_bank();
+                self.data.write_prg_ram(PRG_RAM_BANK_SIZE, bank, (address - 0x6000) as usize, value);
+            }
+            0x8000..=0xffff => {
+                self.write_register(address, value);
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.get_chr_bank(address);
+        self.data.read_chr(CHR_BANK_SIZE, bank, (address & 0x0fff) as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.get_chr_bank(address);
+        self.data.write_chr(CHR_BANK_SIZE, bank, (address & 0x0fff) as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn step_cpu(&mut self) {
+        self.cpu_cycle += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::cartridge::cartridge::Cartridge;
+    use crate::cpu::cpu::CPU;
+    use crate::memory::Memory;
+
+    // PRG ROM whose 16KB banks are filled with their number
+    fn create_mapper(prg_banks: usize) -> MMC1 {
+        let prg_rom: Vec<u8> = (0..prg_banks).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect();
+        MMC1::new(CartridgeData::new(prg_rom, Vec::new(), 0x2000, 0x2000))
+    }
+
+    // five serial writes, spaced out like STA instructions would
+    fn write_serial(mapper: &mut MMC1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.step_cpu();
+            mapper.step_cpu();
+            mapper.write_prg(address, (value >> bit) & 0b1);
+        }
+    }
+
+    #[test]
+    fn shift_register_loads_on_the_fifth_write() {
+        let mut mapper = create_mapper(8);
+        write_serial(&mut mapper, 0x8000, 0b00011);
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+
+        // a write with bit 7 set drops the bits shifted in so far
+        for _ in 0..3 {
+            mapper.step_cpu();
+            mapper.step_cpu();
+            mapper.write_prg(0x8000, 0);
+        }
+        mapper.step_cpu();
+        mapper.step_cpu();
+        mapper.write_prg(0x8000, 0x80);
+        write_serial(&mut mapper, 0x8000, 0b00010);
+        assert_eq!(mapper.get_mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn prg_modes_switch_and_fix_banks() {
+        let mut mapper = create_mapper(8);
+        write_serial(&mut mapper, 0xe000, 5);
+        // mode 3 after power on fixes the last bank at $C000
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xc000)), (5, 7));
+
+        write_serial(&mut mapper, 0x8000, 0b01000);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xc000)), (0, 5));
+
+        // 32KB mode ignores the low bit of the bank
+        write_serial(&mut mapper, 0x8000, 0b00000);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xc000)), (4, 5));
+    }
+
+    #[test]
+    fn surom_selects_the_outer_bank_with_chr_bank_0() {
+        let mut mapper = create_mapper(32);
+        write_serial(&mut mapper, 0xe000, 2);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xc000)), (2, 15));
+
+        write_serial(&mut mapper, 0xa000, 0x10);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xc000)), (18, 31));
+    }
+
+    // INC $8000 writes $7F back, shifting in a 1, then $80 on the next cycle.
+    // The reset is ignored, so four more writes complete control = 1.
+    #[test]
+    fn read_modify_write_drops_the_second_write() {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 2, 1, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg_rom = vec![0; 2 * PRG_BANK_SIZE];
+        prg_rom[0] = 0x7f;
+        rom.extend(prg_rom);
+        rom.extend(vec![0; 0x2000]);
+        let cartridge = Rc::new(RefCell::new(Cartridge::from_bytes(&rom).unwrap()));
+        let memory = Rc::new(RefCell::new(Memory::new()));
+        (*memory.borrow_mut()).insert_cartridge(Rc::clone(&cartridge));
+
+        let mut program = vec![0xee, 0x00, 0x80, 0xa9, 0x00]; // INC $8000, LDA #$00
+        for _ in 0..4 {
+            program.extend([0x8d, 0x00, 0x80]); // STA $8000
+        }
+        for (offset, byte) in program.iter().enumerate() {
+            (*memory.borrow_mut()).set_byte(0x0200 + offset as u16, *byte);
+        }
+        let mut cpu = CPU::new(Rc::clone(&memory));
+        cpu.set_pc(0x0200);
+        for _ in 0..6 {
+            cpu.step();
+        }
+        assert_eq!((*cartridge.borrow()).get_mirroring(), Mirroring::SingleScreenB);
+    }
+}
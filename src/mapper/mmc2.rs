@@ -0,0 +1,145 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+
+// from https://wiki.nesdev.com/w/index.php/MMC2 and https://wiki.nesdev.com/w/index.php/MMC4
+const MMC2_PRG_BANK_SIZE: usize = 8 * 1024;
+const MMC4_PRG_BANK_SIZE: usize = 16 * 1024;
+const CHR_BANK_SIZE: usize = 4 * 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+
+const LATCH_FD: u8 = 0xfd;
+const LATCH_FE: u8 = 0xfe;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MMC2Variant {
+    // Punch-Out!!, 8KB PRG banking
+    MMC2,
+    // Fire Emblem and Famicom Wars, 16KB PRG banking and PRG RAM
+    MMC4,
+}
+
+pub struct MMC2 {
+    data: CartridgeData,
+    variant: MMC2Variant,
+    prg_bank: u8,
+    // [pattern table][latch FD, latch FE]
+    chr_banks: [[u8; 2]; 2],
+    latches: [u8; 2],
+    mirroring: Mirroring,
+}
+
+impl MMC2 {
+    pub fn new(data: CartridgeData, variant: MMC2Variant) -> MMC2 {
+        MMC2 {
+            data,
+            variant,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [LATCH_FE; 2],
+            mirroring: Mirroring::Vertical,
+        }
+    }
+
+    fn get_chr_bank(&self, address: u16) -> usize {
+        let table = ((address >> 12) & 0b1) as usize;
+        let latch = if self.latches[table] == LATCH_FD { 0 } else { 1 };
+        self.chr_banks[table][latch] as usize
+    }
+
+    // the latches switch after the PPU fetches tile $FD or $FE, so the
+    // fetch that triggers the switch still sees the old bank
+    fn update_latches(&mut self, address: u16) {
+        let table = ((address >> 12) & 0b1) as usize;
+        let tile_address = address & 0x0ff8;
+        // the MMC2 only watches the first byte for the left pattern table
+        let exact = self.variant == MMC2Variant::MMC4 || table == 1 || (address & 0x0007) == 0;
+        if !exact {
+            return;
+        }
+        match tile_address {
+            0x0fd8 => { self.latches[table] = LATCH_FD; }
+            0x0fe8 => { self.latches[table] = LATCH_FE; }
+            _ => {}
+        }
+    }
+
+    fn read_prg_rom(&self, address: u16) -> u8 {
+        match self.variant {
+            MMC2Variant::MMC2 => {
+                let bank_count = self.data.get_prg_bank_count(MMC2_PRG_BANK_SIZE).max(3);
+                let bank = match address {
+                    0x8000..=0x9fff => self.prg_bank as usize,
+                    0xa000..=0xbfff => bank_count - 3,
+                    0xc000..=0xdfff => bank_count - 2,
+                    _ => bank_count - 1,
+                };
+                self.data.read_prg_rom(MMC2_PRG_BANK_SIZE, bank, (address & 0x1fff) as usize)
+            }
+            MMC2Variant::MMC4 => {
+                let bank = match address {
+                    0x8000..=0xbfff => self.prg_bank as usize,
+                    _ => self.data.get_prg_bank_count(MMC4_PRG_BANK_SIZE).max(1) - 1,
+                };
+                self.data.read_prg_rom(MMC4_PRG_BANK_SIZE, bank, (address & 0x3fff) as usize)
+            }
+        }
+    }
+}
+
+impl Mapper for MMC2 {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.variant == MMC2Variant::MMC4 => {
+                self.data.read_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize)
+            }
+            0x8000..=0xffff => self.read_prg_rom(address),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7fff if self.variant == MMC2Variant::MMC4 => {
+                self.data.write_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize, value);
+            }
+            0xa000..=0xafff => { self.prg_bank = value & 0x0f; }
+            0xb000..=0xbfff => { self.chr_banks[0][0] = value & 0x1f; }
+            0xc000..=0xcfff => { self.chr_banks[0][1] = value & 0x1f; }
+            0xd000..=0xdfff => { self.chr_banks[1][0] = value & 0x1f; }
+            0xe000..=0xefff => { self.chr_banks[1][1] = value & 0x1f; }
+            0xf000..=0xffff => {
+                self.mirroring = if (value & 0b1) == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.get_chr_bank(address);
+        let value = self.data.read_chr(CHR_BANK_SIZE, bank, (address & 0x0fff) as usize);
+        self.update_latches(address);
+        value
+    }
+
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        let bank = self.get_chr_bank(address);
+        self.data.read_chr(CHR_BANK_SIZE, bank, (address & 0x0fff) as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.get_chr_bank(address);
+        self.data.write_chr(CHR_BANK_SIZE, bank, (address & 0x0fff) as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
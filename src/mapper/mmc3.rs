@@ -0,0 +1,392 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+
+// from https://wiki.nesdev.com/w/index.php/MMC3 and https://wiki.nesdev.com/w/index.php/MMC6
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+const MMC6_RAM_SIZE: usize = 1024;
+
+// A12 has to stay low for this many M2 cycles before a rising edge clocks
+// the counter, which filters out the toggling during sprite fetches
+const A12_FILTER_CYCLES: u8 = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MMC3Variant {
+    // Sharp MMC3B/C: IRQ fires whenever the counter is zero after clocking
+    MMC3,
+    // NEC MMC3A: IRQ only fires when the counter is decremented or reloaded to zero
+    MMC3A,
+    MMC6,
+}
+
+pub struct MMC3 {
+    data: CartridgeData,
+    variant: MMC3Variant,
+    four_screen: bool,
+    bank_select: u8,
+    bank_registers: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_protect: u8,
+    mmc6_ram: [u8; MMC6_RAM_SIZE],
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq: bool,
+    a12: bool,
+    a12_low_cycles: u8,
+}
+
+impl MMC3 {
+    pub fn new(data: CartridgeData, mirroring: Mirroring, variant: MMC3Variant) -> MMC3 {
+        MMC3 {
+            data,
+            variant,
+            four_screen: mirroring == Mirroring::FourScreen,
+            bank_select: 0,
+            bank_registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring,
+            prg_ram_protect: 0,
+            mmc6_ram: [0; MMC6_RAM_SIZE],
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq: false,
+            a12: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        let even = (address & 0b1) == 0;
+        match (address & 0xe000, even) {
+            (0x8000, true) => {
+                self.bank_select = value;
+            }
+            (0x8000, false) => {
+                self.bank_registers[(self.bank_select & 0x07) as usize] = value;
+            }
+            (0xa000, true) => {
+                if !self.four_screen {
+                    self.mirroring = if (value & 0b1) == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+                }
+            }
+            (0xa000, false) => {
+                // MMC6 ignores protect writes while its RAM is disabled
+                if self.variant != MMC3Variant::MMC6 || self.is_mmc6_ram_enabled() {
+                    self.prg_ram_protect = value;
+                }
+            }
+            (0xc000, true) => {
+                self.irq_latch = value;
+            }
+            (0xc000, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xe000, true) => {
+                self.irq_enabled = false;
+                self.irq = false;
+            }
+            _ => {
+                self.irq_enabled = true;
+            }
+        }
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let previous = self.irq_counter;
+        let reloaded = self.irq_counter == 0 || self.irq_reload;
+        if reloaded {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        let fire = match self.variant {
+            MMC3Variant::MMC3A => self.irq_counter == 0 && (previous != 0 || self.irq_reload),
+            _ => self.irq_counter == 0,
+        };
+        if fire && self.irq_enabled {
+            self.irq = true;
+        }
+        self.irq_reload = false;
+    }
+
+    fn observe_a12(&mut self, address: u16) {
+        let a12 = (address & 0x1000) != 0;
+        if a12 && !self.a12 && self.a12_low_cycles >= A12_FILTER_CYCLES {
+            self.clock_irq_counter();
+        }
+        if a12 {
+            self.a12_low_cycles = 0;
+        }
+        self.a12 = a12;
+    }
+
+    fn get_prg_bank(&self, address: u16) -> usize {
+        let second_last = self.data.get_prg_bank_count(PRG_BANK_SIZE).max(2) - 2;
+        let swapped = ((self.bank_select >> 6) & 0b1) == 1;
+        let bank = match ((address >> 13) & 0b11, swapped) {
+            (0, false) | (2, true) => self.bank_registers[6] as usize,
+            (0, true) | (2, false) => second_last,
+            (1, _) => self.bank_registers[7] as usize,
+            _ => second_last + 1,
+        };
+        bank & 0x3f
+    }
+
+    fn get_chr_bank(&self, address: u16) -> usize {
+        let inverted = ((self.bank_select >> 7) & 0b1) == 1;
+        // inversion swaps the 2KB and 1KB halves of the pattern tables
+        let slot = ((address >> 10) & 0b111) ^ if inverted { 0b100 } else { 0 };
+        match slot {
+            0 => (self.bank_registers[0] & 0xfe) as usize,
+            1 => (self.bank_registers[0] | 0x01) as usize,
+            2 => (self.bank_registers[1] & 0xfe) as usize,
+            3 => (self.bank_registers[1] | 0x01) as usize,
+            _ => self.bank_registers[(slot - 2) as usize] as usize,
+        }
+    }
+
+    fn is_prg_ram_enabled(&self) -> bool {
+        ((self.prg_ram_protect >> 7) & 0b1) == 1
+    }
+
+    fn is_prg_ram_writable(&self) -> bool {
+        self.is_prg_ram_enabled() && ((self.prg_ram_protect >> 6) & 0b1) == 0
+    }
+
+    fn is_mmc6_ram_enabled(&self) -> bool {
+        ((self.bank_select >> 5) & 0b1) == 1
+    }
+
+    // MMC6 protect bits: 7 read and 6 write for $7200-$73FF, 5 read and 4 write for $7000-$71FF
+    fn get_mmc6_ram_permissions(&self, address: u16) -> (bool, bool) {
+        let shift = if (address & 0x0200) != 0 { 6 } else { 4 };
+        let read = ((self.prg_ram_protect >> (shift + 1)) & 0b1) == 1;
+        let write = ((self.prg_ram_protect >> shift) & 0b1) == 1;
+        (read, write)
+    }
+
+    fn read_mmc6_ram(&self, address: u16) -> u8 {
+        let any_readable = (self.prg_ram_protect & 0xa0) != 0;
+        let (read, _) = self.get_mmc6_ram_permissions(address);
+        if !self.is_mmc6_ram_enabled() || !any_readable {
+            return 0;
+        }
+        if read { self.mmc6_ram[(address as usize) & (MMC6_RAM_SIZE - 1)] } else { 0 }
+    }
+
+    fn write_mmc6_ram(&mut self, address: u16, value: u8) {
+        let (read, write) = self.get_mmc6_ram_permissions(address);
+        if self.is_mmc6_ram_enabled() && read && write {
+            self.mmc6_ram[(address as usize) & (MMC6_RAM_SIZE - 1)] = value;
+        }
+    }
+}
+
+impl Mapper for MMC3 {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x7000..=0x7fff if self.variant == MMC3Variant::MMC6 => self.read_mmc6_ram(address),
+            0x6000..=0x7fff if self.variant != MMC3Variant::MMC6 && self.is_prg_ram_enabled() => {
+                self.data.read_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize)
+            }
+            0x8000..=0xffff => {
+                let bank = self.get_prg_bank(address);
+                self.data.read_prg_rom(PRG_BANK_SIZE, bank, (address & 0x1fff) as usize)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x7000..=0x7fff if self.variant == MMC3Variant::MMC6 => self.write_mmc6_ram(address, value),
+            0x6000..=0x7fff if self.variant != MMC3Variant::MMC6 && self.is_prg_ram_writable() => {
+                self.data.write_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize, value);
+            }
+            0x8000..=0xffff => self.write_register(address, value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.observe_a12(address);
+        self.peek_chr(address)
+    }
+
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        let bank = self.get_chr_bank(address);
+        self.data.read_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.observe_a12(address);
+        let bank = self.get_chr_bank(address);
+        self.data.write_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize, value);
+    }
+
+    fn notify_ppu_address(&mut self, address: u16) {
+        self.observe_a12(address);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn step_cpu(&mut self) {
+        if !self.a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn get_irq(&self) -> bool {
+        self.irq
+    }
+
+    // the MMC6 keeps its saves in the 1KB of RAM inside the chip
+    fn get_battery_ram(&mut self) -> Vec<u8> {
+        match self.variant {
+            MMC3Variant::MMC6 => self.mmc6_ram.to_vec(),
+            _ => self.data.prg_ram.clone(),
+        }
+    }
+
+    fn load_battery_ram(&mut self, bytes: &[u8]) {
+        let ram = match self.variant {
+            MMC3Variant::MMC6 => &mut self.mmc6_ram[..],
+            _ => &mut self.data.prg_ram[..],
+        };
+        let length = ram.len().min(bytes.len());
+        ram[..length].copy_from_slice(&bytes[..length]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_mapper(variant: MMC3Variant) -> MMC3 {
+        MMC3::new(CartridgeData::new(vec![0; 0x8000], vec![0; 0x2000], 0, 0x2000), Mirroring::Vertical, variant)
+    }
+
+    // A12 low for the given M2 cycles, then high
+    fn raise_a12(mapper: &mut MMC3, low_cycles: u8) {
+        mapper.notify_ppu_address(0x0ff0);
+        for _ in 0..low_cycles {
+            mapper.step_cpu();
+        }
+        mapper.notify_ppu_address(0x1000);
+    }
+
+    fn start_counter(mapper: &mut MMC3, latch: u8) {
+        mapper.write_prg(0xc000, latch);
+        mapper.write_prg(0xc001, 0);
+        mapper.write_prg(0xe001, 0);
+    }
+
+    #[test]
+    fn a12_rises_after_three_low_cycles_clock_the_counter() {
+        let mut mapper = create_mapper(MMC3Variant::MMC3);
+        start_counter(&mut mapper, 2);
+        raise_a12(&mut mapper, 3);
+        assert_eq!(mapper.irq_counter, 2);
+
+        // A12 toggling between the 8x16 sprite fetches is filtered out
+        for _ in 0..4 {
+            raise_a12(&mut mapper, 2);
+        }
+        assert_eq!(mapper.irq_counter, 2);
+
+        // rising edges only, staying high does not clock
+        raise_a12(&mut mapper, 3);
+        mapper.notify_ppu_address(0x1ff0);
+        assert_eq!(mapper.irq_counter, 1);
+        assert!(!mapper.get_irq());
+
+        raise_a12(&mut mapper, 3);
+        assert_eq!(mapper.irq_counter, 0);
+        assert!(mapper.get_irq());
+
+        mapper.write_prg(0xe000, 0);
+        assert!(!mapper.get_irq());
+        raise_a12(&mut mapper, 3);
+        assert!(!mapper.get_irq());
+    }
+
+    #[test]
+    fn reload_requests_take_effect_on_the_next_clock() {
+        let mut mapper = create_mapper(MMC3Variant::MMC3);
+        start_counter(&mut mapper, 5);
+        raise_a12(&mut mapper, 3);
+        raise_a12(&mut mapper, 3);
+        assert_eq!(mapper.irq_counter, 4);
+
+        mapper.write_prg(0xc000, 8);
+        mapper.write_prg(0xc001, 0);
+        raise_a12(&mut mapper, 3);
+        assert_eq!(mapper.irq_counter, 8);
+    }
+
+    // a latch of 0 fires on every clock on the Sharp MMC3, the NEC MMC3A only
+    // fires when the reload was requested through $C001
+    #[test]
+    fn latch_of_zero_depends_on_the_revision() {
+        for (variant, expected) in [(MMC3Variant::MMC3, [true, true]), (MMC3Variant::MMC3A, [true, false])] {
+            let mut mapper = create_mapper(variant);
+            start_counter(&mut mapper, 0);
+            let mut fired = [false; 2];
+            for irq in fired.iter_mut() {
+                raise_a12(&mut mapper, 3);
+                *irq = mapper.get_irq();
+                mapper.write_prg(0xe000, 0);
+                mapper.write_prg(0xe001, 0);
+            }
+            assert_eq!(fired, expected, "{:?}", variant);
+        }
+    }
+
+    #[test]
+    fn mmc6_ram_follows_its_protect_bits() {
+        let mut mapper = create_mapper(MMC3Variant::MMC6);
+        // protect writes are ignored until the RAM is enabled
+        mapper.write_prg(0xa001, 0xf0);
+        mapper.write_prg(0x7000, 0x11);
+        assert_eq!(mapper.read_prg(0x7000), 0);
+
+        mapper.write_prg(0x8000, 0x20);
+        mapper.write_prg(0xa001, 0xf0);
+        mapper.write_prg(0x7000, 0x11);
+        mapper.write_prg(0x7200, 0x22);
+        // the 1KB repeats through $7000-$7FFF
+        assert_eq!((mapper.read_prg(0x7400), mapper.read_prg(0x7e00)), (0x11, 0x22));
+
+        // read only
+        mapper.write_prg(0xa001, 0xa0);
+        mapper.write_prg(0x7000, 0x33);
+        mapper.write_prg(0x7200, 0x44);
+        assert_eq!((mapper.read_prg(0x7000), mapper.read_prg(0x7200)), (0x11, 0x22));
+
+        // $7000-$71FF unreadable, and so not writable either
+        mapper.write_prg(0xa001, 0x90);
+        mapper.write_prg(0x7000, 0x55);
+        assert_eq!((mapper.read_prg(0x7000), mapper.read_prg(0x7200)), (0, 0x22));
+        mapper.write_prg(0xa001, 0xf0);
+        assert_eq!(mapper.read_prg(0x7000), 0x11);
+
+        mapper.write_prg(0x8000, 0x00);
+        assert_eq!(mapper.read_prg(0x7200), 0);
+    }
+}
@@ -0,0 +1,21 @@
+pub mod mapper;
+pub mod nrom;
+pub mod mmc1;
+pub mod mmc2;
+pub mod mmc3;
+pub mod uxrom;
+pub mod cnrom;
+pub mod axrom;
+pub mod gxrom;
+pub mod color_dreams;
+pub mod vrc_irq;
+pub mod vrc2;
+pub mod vrc6;
+pub mod vrc6_audio;
+pub mod namco163;
+pub mod namco163_audio;
+pub mod fme7;
+pub mod sunsoft5b_audio;
+pub mod fds;
+pub mod fds_audio;
+pub mod nsf;
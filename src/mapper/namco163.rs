@@ -0,0 +1,250 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring, NametableSource};
+use crate::mapper::mapper::Mapper;
+use crate::mapper::namco163_audio::Namco163Audio;
+
+// from https://wiki.nesdev.com/w/index.php/INES_Mapper_019
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+
+const IRQ_COUNTER_MAX: u16 = 0x7fff;
+// nametable and CHR registers at or above this value select the console's CIRAM
+const CIRAM_BANK: u8 = 0xe0;
+
+pub struct Namco163 {
+    data: CartridgeData,
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    // $E800 bits 6 and 7 keep CHR banks from $E0 on CHR ROM for $0000 and $1000
+    ciram_pattern_disabled: [bool; 2],
+    write_protect: u8,
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq: bool,
+    audio: Namco163Audio,
+}
+
+impl Namco163 {
+    pub fn new(data: CartridgeData) -> Namco163 {
+        Namco163 {
+            data,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [CIRAM_BANK, CIRAM_BANK + 1, CIRAM_BANK, CIRAM_BANK + 1],
+            ciram_pattern_disabled: [false; 2],
+            write_protect: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq: false,
+            audio: Namco163Audio::new(),
+        }
+    }
+
+    // writes need $4x in the upper nibble, the low nibble protects 2KB windows
+    fn is_prg_ram_writable(&self, address: u16) -> bool {
+        let window = (address - 0x6000) >> 11;
+        (self.write_protect & 0xf0) == 0x40 && ((self.write_protect >> window) & 0b1) == 0
+    }
+}
+
+impl Mapper for Namco163 {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x4800..=0x4fff => self.audio.read_data(),
+            0x5000..=0x57ff => (self.irq_counter & 0xff) as u8,
+            0x5800..=0x5fff => ((self.irq_counter >> 8) as u8) | if self.irq_enabled { 0x80 } else { 0 },
+            0x6000..=0x7fff => self.data.read_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize),
+            0x8000..=0xdfff => {
+                let bank = self.prg_banks[((address - 0x8000) >> 13) as usize] as usize;
+                self.data.read_prg_rom(PRG_BANK_SIZE, bank, (address & 0x1fff) as usize)
+            }
+            0xe000..=0xffff => {
+                let last_bank = self.data.get_prg_bank_count(PRG_BANK_SIZE).max(1) - 1;
+                self.data.read_prg_rom(PRG_BANK_SIZE, last_bank, (address & 0x1fff) as usize)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x4800..=0x4fff => self.audio.write_data(value),
+            0x5000..=0x57ff => {
+                self.irq_counter = (self.irq_counter & 0x7f00) | value as u16;
+                self.irq = false;
+            }
+            0x5800..=0x5fff => {
+                self.irq_counter = (self.irq_counter & 0x00ff) | (((value & 0x7f) as u16) << 8);
+                self.irq_enabled = (value & 0x80) != 0;
+                self.irq = false;
+            }
+            0x6000..=0x7fff if self.is_prg_ram_writable(address) => {
+                self.data.write_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize, value);
+            }
+            0x8000..=0xbfff => {
+                self.chr_banks[((address - 0x8000) >> 11) as usize] = value;
+            }
+            0xc000..=0xdfff => {
+                self.nametable_banks[((address - 0xc000) >> 11) as usize] = value;
+            }
+            0xe000..=0xe7ff => {
+                self.prg_banks[0] = value & 0x3f;
+                self.audio.set_disabled((value & 0x40) != 0);
+            }
+            0xe800..=0xefff => {
+                self.prg_banks[1] = value & 0x3f;
+                self.ciram_pattern_disabled = [(value & 0x40) != 0, (value & 0x80) != 0];
+            }
+            0xf000..=0xf7ff => {
+                self.prg_banks[2] = value & 0x3f;
+            }
+            0xf800..=0xffff => {
+                self.write_protect = value;
+                self.audio.write_address(value);
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.chr_banks[(address >> 10) as usize] as usize;
+        self.data.read_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_banks[(address >> 10) as usize] as usize;
+        self.data.write_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize, value);
+    }
+
+    // only a summary, fetches go through get_nametable_source
+    fn get_mirroring(&self) -> Mirroring {
+        match self.nametable_banks.map(|bank| bank >= CIRAM_BANK && (bank & 0b1) != 0) {
+            [false, false, true, true] => Mirroring::Horizontal,
+            [false, false, false, false] => Mirroring::SingleScreenA,
+            [true, true, true, true] => Mirroring::SingleScreenB,
+            _ => Mirroring::Vertical,
+        }
+    }
+
+    // banks from $E0 select a CIRAM page by their lowest bit, lower ones a CHR ROM bank
+    fn get_nametable_source(&self, table: usize) -> NametableSource {
+        let bank = self.nametable_banks[table];
+        if bank >= CIRAM_BANK {
+            NametableSource::Page((bank & 0b1) as usize)
+        } else {
+            NametableSource::Chr(bank as usize)
+        }
+    }
+
+    // CHR banks from $E0 select a CIRAM page too, unless $E800 disabled that
+    // for their pattern table
+    fn get_pattern_source(&self, address: u16) -> Option<NametableSource> {
+        let bank = self.chr_banks[((address >> 10) & 0b111) as usize];
+        if bank >= CIRAM_BANK && !self.ciram_pattern_disabled[((address >> 12) & 0b1) as usize] {
+            Some(NametableSource::Page((bank & 0b1) as usize))
+        } else {
+            None
+        }
+    }
+
+    fn step_cpu(&mut self) {
+        if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter += 1;
+            if self.irq_counter == IRQ_COUNTER_MAX {
+                self.irq = true;
+            }
+        }
+        self.audio.step_cpu();
+    }
+
+    fn get_irq(&self) -> bool {
+        self.irq
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+
+    // the sound RAM is battery backed too and some games keep saves in it
+    fn get_battery_ram(&mut self) -> Vec<u8> {
+        let mut bytes = self.data.prg_ram.clone();
+        bytes.extend_from_slice(self.audio.get_ram());
+        bytes
+    }
+
+    fn load_battery_ram(&mut self, bytes: &[u8]) {
+        let prg_ram_length = self.data.prg_ram.len().min(bytes.len());
+        self.data.prg_ram[..prg_ram_length].copy_from_slice(&bytes[..prg_ram_length]);
+        self.audio.load_ram(&bytes[prg_ram_length..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::cartridge::cartridge::Cartridge;
+    use crate::ppu::memory::PpuMemory;
+
+    #[test]
+    fn nametables_map_ciram_pages_and_chr_banks() {
+        let chr_rom: Vec<u8> = (0..16).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect();
+        let mut mapper = Namco163::new(CartridgeData::new(vec![0; 0x8000], chr_rom, 0, 0x2000));
+        assert_eq!(mapper.get_mirroring(), Mirroring::Vertical);
+
+        for (address, bank) in [(0xc000, 0xe1), (0xc800, 0xe0), (0xd000, 0x05), (0xd800, 0xff)] {
+            mapper.write_prg(address, bank);
+        }
+        let sources: Vec<NametableSource> = (0..4).map(|table| mapper.get_nametable_source(table)).collect();
+        assert_eq!(sources, [
+            NametableSource::Page(1), NametableSource::Page(0), NametableSource::Chr(5), NametableSource::Page(1),
+        ]);
+
+        for (address, bank) in [(0xc000, 0xe0), (0xc800, 0xe0), (0xd000, 0xe1), (0xd800, 0xe1)] {
+            mapper.write_prg(address, bank);
+        }
+        assert_eq!(mapper.get_mirroring(), Mirroring::Horizontal);
+    }
+
+    // iNES mapper 19 with 128KB of CHR ROM, each 1KB bank filled with its number
+    fn create_cartridge() -> Cartridge {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 2, 16, 0x30, 0x10, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend(vec![0; 0x8000]);
+        rom.extend((0..128).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]));
+        Cartridge::from_bytes(&rom).unwrap()
+    }
+
+    #[test]
+    fn chr_banks_from_e0_map_ciram_into_pattern_tables() {
+        let cartridge = Rc::new(RefCell::new(create_cartridge()));
+        let mut memory = PpuMemory::new();
+        memory.insert_cartridge(Rc::clone(&cartridge));
+        memory.set_byte(0x2000, 0xaa);
+        memory.set_byte(0x2400, 0xbb);
+
+        for (address, bank) in [(0x8000, 0xe1), (0x9000, 0x02), (0xa000, 0xe0)] {
+            (*cartridge.borrow_mut()).write_prg(address, bank);
+        }
+        assert_eq!(memory.get_byte(0x0000), 0xbb);
+        assert_eq!(memory.get_byte(0x0800), 0x02);
+        assert_eq!(memory.get_byte(0x1000), 0xaa);
+
+        // writes land in CIRAM and show up in the nametable
+        memory.set_byte(0x1001, 0xcc);
+        assert_eq!(memory.get_byte(0x2001), 0xcc);
+
+        // $E800 bit 7 keeps $1000-$1FFF on CHR ROM, bank $E0 wraps to $60
+        (*cartridge.borrow_mut()).write_prg(0xe800, 0x80);
+        assert_eq!((memory.get_byte(0x0000), memory.get_byte(0x1000)), (0xbb, 0x60));
+    }
+}
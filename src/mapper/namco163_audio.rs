@@ -0,0 +1,119 @@
+// from https://wiki.nesdev.com/w/index.php/Namco_163_audio
+
+const RAM_SIZE: usize = 128;
+const CHANNEL_REGISTERS: usize = 0x40;
+// one channel is updated every 15 CPU cycles
+const UPDATE_PERIOD: u8 = 15;
+// the mixing resistor differs between boards; this sits between the
+// quieter and louder cartridges
+const OUTPUT_SCALE: f32 = 0.0025;
+
+pub struct Namco163Audio {
+    ram: [u8; RAM_SIZE],
+    address: u8,
+    auto_increment: bool,
+    disabled: bool,
+    cycle: u8,
+    current_channel: usize,
+    outputs: [i16; 8],
+}
+
+impl Namco163Audio {
+    pub fn new() -> Namco163Audio {
+        Namco163Audio {
+            ram: [0; RAM_SIZE],
+            address: 0,
+            auto_increment: false,
+            disabled: false,
+            cycle: 0,
+            current_channel: 7,
+            outputs: [0; 8],
+        }
+    }
+
+    pub fn set_disabled(&mut self, disabled: bool) {
+        self.disabled = disabled;
+    }
+
+    pub fn write_address(&mut self, value: u8) {
+        self.address = value & 0x7f;
+        self.auto_increment = (value & 0x80) != 0;
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        let value = self.ram[self.address as usize];
+        self.increment_address();
+        value
+    }
+
+    pub fn write_data(&mut self, value: u8) {
+        self.ram[self.address as usize] = value;
+        self.increment_address();
+    }
+
+    pub fn get_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn load_ram(&mut self, bytes: &[u8]) {
+        let length = RAM_SIZE.min(bytes.len());
+        self.ram[..length].copy_from_slice(&bytes[..length]);
+    }
+
+    pub fn step_cpu(&mut self) {
+        if self.disabled {
+            return;
+        }
+        self.cycle += 1;
+        if self.cycle < UPDATE_PERIOD {
+            return;
+        }
+        self.cycle = 0;
+
+        self.update_channel(self.current_channel);
+        // channels 7 down to 8 - n are active, n taken from $7F bits 4-6
+        let first_channel = 8 - self.get_enabled_channels();
+        self.current_channel = if self.current_channel <= first_channel { 7 } else { self.current_channel - 1 };
+    }
+
+    pub fn get_output(&self) -> f32 {
+        // the chip time-multiplexes its channels, so each one is heard at 1/n of its level
+        let channels = self.get_enabled_channels();
+        let sum: i16 = self.outputs[(8 - channels)..].iter().sum();
+        (sum as f32 / channels as f32) * OUTPUT_SCALE
+    }
+
+    fn get_enabled_channels(&self) -> usize {
+        (((self.ram[0x7f] >> 4) & 0x07) + 1) as usize
+    }
+
+    fn increment_address(&mut self) {
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7f;
+        }
+    }
+
+    fn update_channel(&mut self, channel: usize) {
+        let base = CHANNEL_REGISTERS + channel * 8;
+        let frequency = (self.ram[base] as u32)
+            | ((self.ram[base + 2] as u32) << 8)
+            | (((self.ram[base + 4] & 0b11) as u32) << 16);
+        let mut phase = (self.ram[base + 1] as u32)
+            | ((self.ram[base + 3] as u32) << 8)
+            | ((self.ram[base + 5] as u32) << 16);
+        let length = 256 - (self.ram[base + 4] & 0xfc) as u32;
+        let offset = self.ram[base + 6] as u32;
+        let volume = (self.ram[base + 7] & 0x0f) as i16;
+
+        phase = (phase + frequency) % (length << 16);
+        self.ram[base + 1] = (phase & 0xff) as u8;
+        self.ram[base + 3] = ((phase >> 8) & 0xff) as u8;
+        self.ram[base + 5] = ((phase >> 16) & 0xff) as u8;
+
+        // samples are 4 bit, packed two per byte with the low nibble first
+        let sample_index = ((phase >> 16) + offset) & 0xff;
+        let byte = self.ram[(sample_index >> 1) as usize];
+        let sample = if (sample_index & 0b1) == 0 { byte & 0x0f } else { byte >> 4 };
+        self.outputs[channel] = (sample as i16 - 8) * volume;
+    }
+}
@@ -0,0 +1,57 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+
+// from https://wiki.nesdev.com/w/index.php/NROM
+const PRG_BANK_SIZE: usize = 32 * 1024;
+const CHR_BANK_SIZE: usize = 8 * 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+
+pub struct NROM {
+    data: CartridgeData,
+    mirroring: Mirroring,
+}
+
+impl NROM {
+    pub fn new(data: CartridgeData, mirroring: Mirroring) -> NROM {
+        NROM {
+            data,
+            mirroring,
+        }
+    }
+}
+
+impl Mapper for NROM {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff => self.data.read_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize),
+            0x8000..=0xffff => self.data.read_prg_rom(PRG_BANK_SIZE, 0, (address & 0x7fff) as usize),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7fff = address {
+            self.data.write_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize, value);
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.data.read_chr(CHR_BANK_SIZE, 0, address as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.data.write_chr(CHR_BANK_SIZE, 0, address as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
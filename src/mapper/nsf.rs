@@ -0,0 +1,245 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::cartridge::nsf::{NsfFile, CHIP_VRC6, CHIP_FDS, CHIP_NAMCO163, CHIP_SUNSOFT5B};
+use crate::mapper::mapper::Mapper;
+use crate::mapper::vrc6_audio::Vrc6Audio;
+use crate::mapper::fds_audio::FdsAudio;
+use crate::mapper::namco163_audio::Namco163Audio;
+use crate::mapper::sunsoft5b_audio::Sunsoft5bAudio;
+
+// from https://wiki.nesdev.com/w/index.php/NSF#Bankswitching
+const BANK_SIZE: usize = 4 * 1024;
+const PRG_RAM_SIZE: usize = 8 * 1024;
+// FDS tunes get RAM over the whole $6000-$FFFF range
+const FDS_RAM_SIZE: usize = 40 * 1024;
+
+// the board an NSF player provides: 4KB banks at $8000-$FFFF selected through
+// $5FF8-$5FFF, RAM at $6000-$7FFF and whichever expansion chips the tune uses.
+// VRC7 and MMC5 audio are not emulated, their registers are ignored.
+pub struct NSF {
+    data: CartridgeData,
+    banks: [u8; 8],
+    fds_mode: bool,
+    vrc6: Option<Vrc6Audio>,
+    fds: Option<FdsAudio>,
+    namco163: Option<Namco163Audio>,
+    sunsoft5b: Option<Sunsoft5bAudio>,
+}
+
+impl NSF {
+    pub fn new(nsf: &NsfFile) -> NSF {
+        let fds_mode = nsf.has_chip(CHIP_FDS);
+        let bankswitched = nsf.is_bankswitched();
+
+        // bank 0 starts at the 4KB boundary below the load address, or at the
+        // start of the window the tune is loaded into when it is not bankswitched
+        let window_start = if fds_mode { 0x6000 } else { 0x8000 };
+        let padding = if bankswitched {
+            (nsf.load_address & 0x0fff) as usize
+        } else {
+            nsf.load_address.saturating_sub(window_start) as usize
+        };
+        let mut prg_rom = vec![0; padding];
+        prg_rom.extend_from_slice(&nsf.data);
+        let length = prg_rom.len().div_ceil(BANK_SIZE) * BANK_SIZE;
+        prg_rom.resize(length, 0);
+
+        let prg_ram_size = if fds_mode { FDS_RAM_SIZE } else { PRG_RAM_SIZE };
+        let mut nsf_mapper = NSF {
+            data: CartridgeData::new(prg_rom, Vec::new(), 0, prg_ram_size),
+            banks: [0, 1, 2, 3, 4, 5, 6, 7],
+            fds_mode,
+            vrc6: if nsf.has_chip(CHIP_VRC6) { Some(Vrc6Audio::new()) } else { None },
+            fds: if fds_mode { Some(FdsAudio::new()) } else { None },
+            namco163: if nsf.has_chip(CHIP_NAMCO163) { Some(Namco163Audio::new()) } else { None },
+            sunsoft5b: if nsf.has_chip(CHIP_SUNSOFT5B) { Some(Sunsoft5bAudio::new()) } else { None },
+        };
+        nsf_mapper.reset(nsf);
+        nsf_mapper
+    }
+
+    // clears RAM and loads the initial banks, done before every song
+    fn reset(&mut self, nsf: &NsfFile) {
+        for byte in self.data.prg_ram.iter_mut() {
+            *byte = 0;
+        }
+
+        if !nsf.is_bankswitched() {
+            if self.fds_mode {
+                let length = self.data.prg_rom.len().min(FDS_RAM_SIZE);
+                self.data.prg_ram[..length].copy_from_slice(&self.data.prg_rom[..length]);
+            }
+            return;
+        }
+
+        for (register, bank) in nsf.bank_init.iter().enumerate() {
+            self.write_bank_register(0x5ff8 + register as u16, *bank);
+        }
+        // $6000-$7FFF start with the banks of $E000-$FFFF
+        if self.fds_mode {
+            self.write_bank_register(0x5ff6, nsf.bank_init[6]);
+            self.write_bank_register(0x5ff7, nsf.bank_init[7]);
+        }
+    }
+
+    // FDS tunes run from RAM, so switching a bank copies it in
+    fn write_bank_register(&mut self, address: u16, value: u8) {
+        let slot = (address - 0x5ff6) as usize;
+        if !self.fds_mode {
+            if slot >= 2 {
+                self.banks[slot - 2] = value;
+            }
+            return;
+        }
+        for offset in 0..BANK_SIZE {
+            let byte = self.data.read_prg_rom(BANK_SIZE, value as usize, offset);
+            self.data.prg_ram[slot * BANK_SIZE + offset] = byte;
+        }
+    }
+}
+
+impl Mapper for NSF {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x4040..=0x4092 => match &self.fds {
+                Some(fds) => fds.read_register(address),
+                None => 0,
+            },
+            0x4800..=0x4fff => match &mut self.namco163 {
+                Some(namco163) => namco163.read_data(),
+                None => 0,
+            },
+            0x6000..=0xffff if self.fds_mode => self.data.prg_ram[(address - 0x6000) as usize],
+            0x6000..=0x7fff => self.data.read_prg_ram(PRG_RAM_SIZE, 0, (address - 0x6000) as usize),
+            0x8000..=0xffff => {
+                let bank = self.banks[((address - 0x8000) as usize) / BANK_SIZE];
+                self.data.read_prg_rom(BANK_SIZE, bank as usize, (address as usize) % BANK_SIZE)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x4040..=0x408a => {
+                if let Some(fds) = &mut self.fds {
+                    fds.write_register(address, value);
+                }
+            }
+            0x4800..=0x4fff => {
+                if let Some(namco163) = &mut self.namco163 {
+                    namco163.write_data(value);
+                }
+            }
+            0x5ff6..=0x5fff => self.write_bank_register(address, value),
+            0x6000..=0xffff if self.fds_mode => self.data.prg_ram[(address - 0x6000) as usize] = value,
+            0x6000..=0x7fff => self.data.write_prg_ram(PRG_RAM_SIZE, 0, (address - 0x6000) as usize, value),
+            0x9000..=0x9003 | 0xa000..=0xa002 | 0xb000..=0xb002 => {
+                if let Some(vrc6) = &mut self.vrc6 {
+                    vrc6.write_register(address, value);
+                }
+            }
+            0xc000 | 0xe000 => {
+                if let Some(sunsoft5b) = &mut self.sunsoft5b {
+                    if address == 0xc000 {
+                        sunsoft5b.select_register(value);
+                    } else {
+                        sunsoft5b.write_register(value);
+                    }
+                }
+            }
+            0xf800..=0xffff => {
+                if let Some(namco163) = &mut self.namco163 {
+                    namco163.write_address(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, _address: u16) -> u8 {
+        0
+    }
+
+    fn write_chr(&mut self, _address: u16, _value: u8) {}
+
+    fn get_mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn step_cpu(&mut self) {
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.step_cpu();
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.step_cpu();
+        }
+        if let Some(namco163) = &mut self.namco163 {
+            namco163.step_cpu();
+        }
+        if let Some(sunsoft5b) = &mut self.sunsoft5b {
+            sunsoft5b.step_cpu();
+        }
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.vrc6.as_ref().map_or(0.0, |vrc6| vrc6.get_output()) +
+            self.fds.as_ref().map_or(0.0, |fds| fds.get_output()) +
+            self.namco163.as_ref().map_or(0.0, |namco163| namco163.get_output()) +
+            self.sunsoft5b.as_ref().map_or(0.0, |sunsoft5b| sunsoft5b.get_output())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::nsf::tests::create_nsf;
+
+    // program data whose 4KB pages are filled with their number plus one
+    fn create_mapper(load_address: u16, bank_init: [u8; 8], chips: u8) -> NSF {
+        let data: Vec<u8> = (0..4).flat_map(|page| vec![page as u8 + 1; BANK_SIZE]).collect();
+        NSF::new(&NsfFile::parse(&create_nsf(load_address, 0, 0, bank_init, chips, &data)).unwrap())
+    }
+
+    #[test]
+    fn loads_unbanked_tunes_at_their_load_address() {
+        let mut mapper = create_mapper(0x8100, [0; 8], 0);
+        assert_eq!((mapper.read_prg(0x80ff), mapper.read_prg(0x8100)), (0, 1));
+        assert_eq!((mapper.read_prg(0x9100), mapper.read_prg(0xc0ff)), (2, 4));
+        assert_eq!(mapper.read_prg(0xc100), 0);
+    }
+
+    #[test]
+    fn pads_bankswitched_tunes_to_the_4kb_boundary() {
+        let mut mapper = create_mapper(0x8100, [0, 1, 2, 3, 0, 0, 0, 0], 0);
+        assert_eq!((mapper.read_prg(0x80ff), mapper.read_prg(0x8100)), (0, 1));
+        assert_eq!((mapper.read_prg(0x90ff), mapper.read_prg(0x9100)), (1, 2));
+        assert_eq!(mapper.read_prg(0xf000), 0);
+
+        mapper.write_prg(0x5fff, 4);
+        assert_eq!((mapper.read_prg(0xf0ff), mapper.read_prg(0xf100)), (4, 0));
+    }
+
+    #[test]
+    fn fds_tunes_run_from_ram() {
+        let mut mapper = create_mapper(0x6000, [0; 8], CHIP_FDS);
+        assert_eq!((mapper.read_prg(0x6000), mapper.read_prg(0x9fff)), (1, 4));
+        mapper.write_prg(0x8000, 0x55);
+        assert_eq!(mapper.read_prg(0x8000), 0x55);
+
+        // $5FF6 and $5FF7 copy banks to $6000-$7FFF, starting with those of $E000-$FFFF
+        let mut mapper = create_mapper(0x8000, [0, 1, 2, 3, 0, 0, 2, 3], CHIP_FDS);
+        assert_eq!((mapper.read_prg(0x6000), mapper.read_prg(0x7000)), (3, 4));
+        mapper.write_prg(0x5ff6, 1);
+        assert_eq!(mapper.read_prg(0x6000), 2);
+        mapper.write_prg(0x6000, 0x55);
+        assert_eq!(mapper.read_prg(0x6000), 0x55);
+    }
+}
@@ -0,0 +1,210 @@
+// from https://wiki.nesdev.com/w/index.php/Sunsoft_5B_audio
+// the 5B is a YM2149F (AY-3-8910 compatible) clocked at half the CPU rate
+
+// units advance once per 16 CPU cycles, the tone period counts half waves
+const PRESCALER_PERIOD: u8 = 16;
+const OUTPUT_SCALE: f32 = 0.15;
+
+struct Tone {
+    period: u16,
+    counter: u16,
+    output: bool,
+}
+
+impl Tone {
+    fn new() -> Tone {
+        Tone {
+            period: 0,
+            counter: 0,
+            output: false,
+        }
+    }
+
+    fn tick(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            self.output = !self.output;
+        }
+    }
+}
+
+struct Noise {
+    period: u8,
+    counter: u16,
+    shift_register: u32,
+}
+
+impl Noise {
+    fn new() -> Noise {
+        Noise {
+            period: 0,
+            counter: 0,
+            shift_register: 1,
+        }
+    }
+
+    // 17 bit LFSR with taps on bits 0 and 3
+    fn tick(&mut self) {
+        self.counter += 1;
+        if self.counter >= 2 * (self.period.max(1) as u16) {
+            self.counter = 0;
+            let feedback = (self.shift_register ^ (self.shift_register >> 3)) & 0b1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 16);
+        }
+    }
+
+    fn get_output(&self) -> bool {
+        (self.shift_register & 0b1) == 1
+    }
+}
+
+struct Envelope {
+    period: u16,
+    counter: u32,
+    shape: u8,
+    step: u8,
+    attack: bool,
+    holding: bool,
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope {
+            period: 0,
+            counter: 0,
+            shape: 0,
+            step: 0,
+            attack: false,
+            holding: false,
+        }
+    }
+
+    fn set_shape(&mut self, shape: u8) {
+        self.shape = shape & 0x0f;
+        self.counter = 0;
+        self.step = 0;
+        self.attack = (self.shape & 0b0100) != 0;
+        self.holding = false;
+    }
+
+    fn tick(&mut self) {
+        self.counter += 1;
+        if self.counter < 2 * (self.period.max(1) as u32) {
+            return;
+        }
+        self.counter = 0;
+        if self.holding {
+            return;
+        }
+
+        self.step += 1;
+        if self.step < 16 {
+            return;
+        }
+        let continue_cycle = (self.shape & 0b1000) != 0;
+        let alternate = (self.shape & 0b0010) != 0;
+        let hold = (self.shape & 0b0001) != 0;
+        if !continue_cycle {
+            // shapes 0-7 drop to 0 after a single ramp
+            self.holding = true;
+            self.attack = false;
+            self.step = 15;
+        } else if hold {
+            self.holding = true;
+            self.attack ^= alternate;
+            self.step = 15;
+        } else {
+            self.attack ^= alternate;
+            self.step = 0;
+        }
+    }
+
+    fn get_level(&self) -> u8 {
+        if self.attack { self.step } else { 15 - self.step }
+    }
+}
+
+pub struct Sunsoft5bAudio {
+    register: u8,
+    tones: [Tone; 3],
+    noise: Noise,
+    envelope: Envelope,
+    mixer: u8,
+    volumes: [u8; 3],
+    volume_table: [f32; 16],
+    prescaler: u8,
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Sunsoft5bAudio {
+        // levels are 3dB apart, level 0 is silent
+        let mut volume_table = [0.0; 16];
+        for (level, volume) in volume_table.iter_mut().enumerate().skip(1) {
+            *volume = 10f32.powf((level as f32 - 15.0) * 3.0 / 20.0);
+        }
+        Sunsoft5bAudio {
+            register: 0,
+            tones: [Tone::new(), Tone::new(), Tone::new()],
+            noise: Noise::new(),
+            envelope: Envelope::new(),
+            mixer: 0xff,
+            volumes: [0; 3],
+            volume_table,
+            prescaler: 0,
+        }
+    }
+
+    pub fn select_register(&mut self, value: u8) {
+        self.register = value & 0x0f;
+    }
+
+    pub fn write_register(&mut self, value: u8) {
+        match self.register {
+            0 | 2 | 4 => {
+                let tone = &mut self.tones[(self.register >> 1) as usize];
+                tone.period = (tone.period & 0x0f00) | value as u16;
+            }
+            1 | 3 | 5 => {
+                let tone = &mut self.tones[(self.register >> 1) as usize];
+                tone.period = (tone.period & 0x00ff) | (((value & 0x0f) as u16) << 8);
+            }
+            6 => { self.noise.period = value & 0x1f; }
+            7 => { self.mixer = value; }
+            8..=10 => { self.volumes[(self.register - 8) as usize] = value & 0x1f; }
+            11 => { self.envelope.period = (self.envelope.period & 0xff00) | value as u16; }
+            12 => { self.envelope.period = (self.envelope.period & 0x00ff) | ((value as u16) << 8); }
+            13 => self.envelope.set_shape(value),
+            _ => {}
+        }
+    }
+
+    pub fn step_cpu(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < PRESCALER_PERIOD {
+            return;
+        }
+        self.prescaler = 0;
+        for tone in self.tones.iter_mut() {
+            tone.tick();
+        }
+        self.noise.tick();
+        self.envelope.tick();
+    }
+
+    pub fn get_output(&self) -> f32 {
+        let mut output = 0.0;
+        for channel in 0..3 {
+            let tone_enabled = ((self.mixer >> channel) & 0b1) == 0;
+            let noise_enabled = ((self.mixer >> (channel + 3)) & 0b1) == 0;
+            let high = (!tone_enabled || self.tones[channel].output) && (!noise_enabled || self.noise.get_output());
+            if !high {
+                continue;
+            }
+            let volume = self.volumes[channel];
+            let level = if (volume & 0x10) != 0 { self.envelope.get_level() } else { volume & 0x0f };
+            output += self.volume_table[level as usize];
+        }
+        output * OUTPUT_SCALE
+    }
+}
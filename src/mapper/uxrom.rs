@@ -0,0 +1,64 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+
+// from https://wiki.nesdev.com/w/index.php/UxROM
+const PRG_BANK_SIZE: usize = 16 * 1024;
+const CHR_BANK_SIZE: usize = 8 * 1024;
+
+pub struct UxROM {
+    data: CartridgeData,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    prg_bank: u8,
+}
+
+impl UxROM {
+    pub fn new(data: CartridgeData, mirroring: Mirroring, bus_conflicts: bool) -> UxROM {
+        UxROM {
+            data,
+            mirroring,
+            bus_conflicts,
+            prg_bank: 0,
+        }
+    }
+}
+
+impl Mapper for UxROM {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xbfff => self.data.read_prg_rom(PRG_BANK_SIZE, self.prg_bank as usize, (address & 0x3fff) as usize),
+            0xc000..=0xffff => {
+                let last_bank = self.data.get_prg_bank_count(PRG_BANK_SIZE).max(1) - 1;
+                self.data.read_prg_rom(PRG_BANK_SIZE, last_bank, (address & 0x3fff) as usize)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            let value = if self.bus_conflicts { value & self.read_prg(address) } else { value };
+            self.prg_bank = value;
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.data.read_chr(CHR_BANK_SIZE, 0, address as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.data.write_chr(CHR_BANK_SIZE, 0, address as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
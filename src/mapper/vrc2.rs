@@ -0,0 +1,202 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+use crate::mapper::vrc_irq::VrcIrq;
+
+// from https://wiki.nesdev.com/w/index.php/VRC2_and_VRC4
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VRC2Variant {
+    VRC2,
+    // adds PRG swap mode, single screen mirroring, 5 bit CHR high nibbles and the IRQ
+    VRC4,
+}
+
+pub struct VRC2 {
+    data: CartridgeData,
+    variant: VRC2Variant,
+    // CPU address lines wired to the chip's register select pins A0 and A1,
+    // several masks are or-ed together when the board is unknown
+    register_lines: (u16, u16),
+    // VRC2a ignores the lowest CHR bank bit
+    chr_shift: u8,
+    prg_banks: [u8; 2],
+    prg_swap_mode: bool,
+    // VRC4 enables PRG RAM through $9002, VRC2 boards always have it enabled
+    prg_ram_enabled: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    // VRC2 boards without PRG RAM have a single bit latch at $6000-$6FFF
+    latch: u8,
+    irq: VrcIrq,
+}
+
+impl VRC2 {
+    pub fn new(data: CartridgeData, variant: VRC2Variant, register_lines: (u16, u16), chr_shift: u8) -> VRC2 {
+        VRC2 {
+            data,
+            variant,
+            register_lines,
+            chr_shift,
+            prg_banks: [0; 2],
+            prg_swap_mode: false,
+            prg_ram_enabled: variant == VRC2Variant::VRC2,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            latch: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    fn get_register(&self, address: u16) -> u16 {
+        let a0 = if (address & self.register_lines.0) != 0 { 0b01 } else { 0 };
+        let a1 = if (address & self.register_lines.1) != 0 { 0b10 } else { 0 };
+        a0 | a1
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        let register = self.get_register(address);
+        let is_vrc4 = self.variant == VRC2Variant::VRC4;
+        match (address & 0xf000, register) {
+            (0x8000, _) => { self.prg_banks[0] = value & 0x1f; }
+            // VRC2 only decodes mirroring here, VRC4 uses $9002 for the PRG swap mode
+            (0x9000, register) if !is_vrc4 || register <= 1 => {
+                self.mirroring = match value & if is_vrc4 { 0b11 } else { 0b01 } {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                };
+            }
+            (0x9000, _) => {
+                self.prg_ram_enabled = (value & 0b01) != 0;
+                self.prg_swap_mode = (value & 0b10) != 0;
+            }
+            (0xa000, _) => { self.prg_banks[1] = value & 0x1f; }
+            (0xb000..=0xe000, _) => {
+                // each 1KB bank is written as a low and a high nibble
+                let bank = (((address & 0xf000) - 0xb000) >> 11) as usize | (register >> 1) as usize;
+                let high_mask = if is_vrc4 { 0x1f } else { 0x0f };
+                self.chr_banks[bank] = if (register & 0b1) == 0 {
+                    (self.chr_banks[bank] & 0x1f0) | (value & 0x0f) as u16
+                } else {
+                    (self.chr_banks[bank] & 0x00f) | (((value & high_mask) as u16) << 4)
+                };
+            }
+            (0xf000, 0) if is_vrc4 => self.irq.set_latch_low(value),
+            (0xf000, 1) if is_vrc4 => self.irq.set_latch_high(value),
+            (0xf000, 2) if is_vrc4 => self.irq.set_control(value),
+            (0xf000, 3) if is_vrc4 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn get_prg_bank(&self, address: u16) -> usize {
+        let second_last = self.data.get_prg_bank_count(PRG_BANK_SIZE).max(2) - 2;
+        match ((address >> 13) & 0b11, self.prg_swap_mode) {
+            (0, false) | (2, true) => self.prg_banks[0] as usize,
+            (0, true) | (2, false) => second_last,
+            (1, _) => self.prg_banks[1] as usize,
+            _ => second_last + 1,
+        }
+    }
+
+    fn get_chr_bank(&self, address: u16) -> usize {
+        (self.chr_banks[(address >> 10) as usize] >> self.chr_shift) as usize
+    }
+}
+
+impl Mapper for VRC2 {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.prg_ram_enabled && !self.data.prg_ram.is_empty() => {
+                self.data.read_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize)
+            }
+            0x6000..=0x6fff if self.data.prg_ram.is_empty() => self.latch,
+            0x8000..=0xffff => {
+                let bank = self.get_prg_bank(address);
+                self.data.read_prg_rom(PRG_BANK_SIZE, bank, (address & 0x1fff) as usize)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7fff if self.prg_ram_enabled && !self.data.prg_ram.is_empty() => {
+                self.data.write_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize, value);
+            }
+            0x6000..=0x6fff if self.data.prg_ram.is_empty() => { self.latch = value & 0b1; }
+            0x8000..=0xffff => self.write_register(address, value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.get_chr_bank(address);
+        self.data.read_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.get_chr_bank(address);
+        self.data.write_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn step_cpu(&mut self) {
+        if self.variant == VRC2Variant::VRC4 {
+            self.irq.step_cpu();
+        }
+    }
+
+    fn get_irq(&self) -> bool {
+        self.irq.get_irq()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vrc4_enables_prg_ram_through_9002() {
+        let data = CartridgeData::new(vec![0; 0x20000], vec![0; 0x2000], 0, 0x2000);
+        let mut mapper = VRC2::new(data, VRC2Variant::VRC4, (0x01, 0x02), 0);
+        mapper.write_prg(0x6000, 0x12);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+
+        mapper.write_prg(0x9002, 0b01);
+        mapper.write_prg(0x6000, 0x12);
+        assert_eq!(mapper.read_prg(0x6000), 0x12);
+        assert!(!mapper.prg_swap_mode);
+
+        // disabling keeps the contents, the swap mode shares the register
+        mapper.write_prg(0x9002, 0b10);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+        assert!(mapper.prg_swap_mode);
+        mapper.write_prg(0x9002, 0b11);
+        assert_eq!(mapper.read_prg(0x6000), 0x12);
+    }
+
+    #[test]
+    fn vrc2_prg_ram_is_always_enabled() {
+        let data = CartridgeData::new(vec![0; 0x20000], vec![0; 0x2000], 0, 0x2000);
+        let mut mapper = VRC2::new(data, VRC2Variant::VRC2, (0x01, 0x02), 0);
+        mapper.write_prg(0x9002, 0);
+        mapper.write_prg(0x7fff, 0x34);
+        assert_eq!(mapper.read_prg(0x7fff), 0x34);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::cartridge::cartridge::{CartridgeData, Mirroring};
+use crate::mapper::mapper::Mapper;
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::vrc6_audio::Vrc6Audio;
+
+// from https://wiki.nesdev.com/w/index.php/VRC6
+const PRG_BANK_SIZE_16K: usize = 16 * 1024;
+const PRG_BANK_SIZE_8K: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+
+pub struct VRC6 {
+    data: CartridgeData,
+    // VRC6b (mapper 26) has A0 and A1 swapped compared to VRC6a (mapper 24)
+    swap_address_lines: bool,
+    prg_bank_16k: u8,
+    prg_bank_8k: u8,
+    chr_banks: [u8; 8],
+    banking_control: u8,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl VRC6 {
+    pub fn new(data: CartridgeData, swap_address_lines: bool) -> VRC6 {
+        VRC6 {
+            data,
+            swap_address_lines,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; 8],
+            banking_control: 0,
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+        }
+    }
+
+    fn normalize_address(&self, address: u16) -> u16 {
+        let register = if self.swap_address_lines {
+            ((address & 0b01) << 1) | ((address & 0b10) >> 1)
+        } else {
+            address & 0b11
+        };
+        (address & 0xf000) | register
+    }
+
+    fn is_prg_ram_enabled(&self) -> bool {
+        (self.banking_control & 0x80) != 0
+    }
+
+    fn get_chr_bank(&self, address: u16) -> usize {
+        let slot = ((address >> 10) & 0b111) as usize;
+        let mode = self.banking_control & 0b11;
+        let register_a10 = (self.banking_control & 0x20) != 0;
+        // 2KB banks take CHR A10 either from the PPU or from the bank register itself
+        let two_kb = |register: u8| -> usize {
+            let a10 = ((address >> 10) & 0b1) as u8;
+            (if register_a10 { register } else { (register & 0xfe) | a10 }) as usize
+        };
+        match (mode, slot) {
+            (0, _) => self.chr_banks[slot] as usize,
+            (1, _) => two_kb(self.chr_banks[slot >> 1]),
+            (_, 0..=3) => self.chr_banks[slot] as usize,
+            (_, _) => two_kb(self.chr_banks[4 + ((slot - 4) >> 1)]),
+        }
+    }
+}
+
+impl Mapper for VRC6 {
+    fn get_data(&self) -> &CartridgeData {
+        &self.data
+    }
+
+    fn get_data_mut(&mut self) -> &mut CartridgeData {
+        &mut self.data
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => {
+                self.data.read_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize)
+            }
+            0x8000..=0xbfff => {
+                self.data.read_prg_rom(PRG_BANK_SIZE_16K, self.prg_bank_16k as usize, (address & 0x3fff) as usize)
+            }
+            0xc000..=0xdfff => {
+                self.data.read_prg_rom(PRG_BANK_SIZE_8K, self.prg_bank_8k as usize, (address & 0x1fff) as usize)
+            }
+            0xe000..=0xffff => {
+                let last_bank = self.data.get_prg_bank_count(PRG_BANK_SIZE_8K).max(1) - 1;
+                self.data.read_prg_rom(PRG_BANK_SIZE_8K, last_bank, (address & 0x1fff) as usize)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7fff = address {
+            if self.is_prg_ram_enabled() {
+                self.data.write_prg_ram(PRG_RAM_BANK_SIZE, 0, (address - 0x6000) as usize, value);
+            }
+            return;
+        }
+        if address < 0x8000 {
+            return;
+        }
+
+        let address = self.normalize_address(address);
+        match address {
+            0x8000..=0x8003 => { self.prg_bank_16k = value & 0x0f; }
+            0x9000..=0xb002 => self.audio.write_register(address, value),
+            0xb003 => { self.banking_control = value; }
+            0xc000..=0xc003 => { self.prg_bank_8k = value & 0x1f; }
+            0xd000..=0xd003 => { self.chr_banks[(address & 0b11) as usize] = value; }
+            0xe000..=0xe003 => { self.chr_banks[4 + (address & 0b11) as usize] = value; }
+            0xf000 => self.irq.set_latch(value),
+            0xf001 => self.irq.set_control(value),
+            0xf002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.get_chr_bank(address);
+        self.data.read_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.get_chr_bank(address);
+        self.data.write_chr(CHR_BANK_SIZE, bank, (address & 0x03ff) as usize, value);
+    }
+
+    fn get_mirroring(&self) -> Mirroring {
+        match (self.banking_control >> 2) & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenA,
+            _ => Mirroring::SingleScreenB,
+        }
+    }
+
+    fn step_cpu(&mut self) {
+        self.irq.step_cpu();
+        self.audio.step_cpu();
+    }
+
+    fn get_irq(&self) -> bool {
+        self.irq.get_irq()
+    }
+
+    fn get_audio_output(&self) -> f32 {
+        self.audio.get_output()
+    }
+}
@@ -0,0 +1,181 @@
+// from https://wiki.nesdev.com/w/index.php/VRC6_audio
+
+// the VRC6 pulses are about as loud as the APU pulses, so the channel sum
+// uses the slope of the linear APU pulse approximation
+const OUTPUT_SCALE: f32 = 0.00752;
+
+struct Pulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+
+impl Pulse {
+    fn new() -> Pulse {
+        Pulse {
+            volume: 0,
+            duty: 0,
+            ignore_duty: false,
+            period: 0,
+            enabled: false,
+            timer: 0,
+            step: 15,
+        }
+    }
+
+    fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.volume = value & 0x0f;
+                self.duty = (value >> 4) & 0x07;
+                self.ignore_duty = (value & 0x80) != 0;
+            }
+            1 => {
+                self.period = (self.period & 0x0f00) | value as u16;
+            }
+            _ => {
+                self.period = (self.period & 0x00ff) | (((value & 0x0f) as u16) << 8);
+                self.enabled = (value & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = if self.step == 0 { 15 } else { self.step - 1 };
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn get_output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+struct Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn new() -> Sawtooth {
+        Sawtooth {
+            rate: 0,
+            period: 0,
+            enabled: false,
+            timer: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+
+    fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.rate = value & 0x3f;
+            }
+            1 => {
+                self.period = (self.period & 0x0f00) | value as u16;
+            }
+            _ => {
+                self.period = (self.period & 0x00ff) | (((value & 0x0f) as u16) << 8);
+                self.enabled = (value & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    // the accumulator grows on every second step and resets on the 14th
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if (self.step & 0b1) == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn get_output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+pub struct Vrc6Audio {
+    pulses: [Pulse; 2],
+    sawtooth: Sawtooth,
+    halt: bool,
+    shift: u8,
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Vrc6Audio {
+        Vrc6Audio {
+            pulses: [Pulse::new(), Pulse::new()],
+            sawtooth: Sawtooth::new(),
+            halt: false,
+            shift: 0,
+        }
+    }
+
+    // address is already normalized to $9000-$B003 with A0/A1 in VRC6a order
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        let register = address & 0b11;
+        match (address & 0xf000, register) {
+            (0x9000, 3) => {
+                self.halt = (value & 0b001) != 0;
+                self.shift = if (value & 0b100) != 0 { 8 } else if (value & 0b010) != 0 { 4 } else { 0 };
+            }
+            (0x9000, _) => self.pulses[0].write_register(register, value),
+            (0xa000, 0..=2) => self.pulses[1].write_register(register, value),
+            (0xb000, 0..=2) => self.sawtooth.write_register(register, value),
+            _ => {}
+        }
+    }
+
+    pub fn step_cpu(&mut self) {
+        if self.halt {
+            return;
+        }
+        for pulse in self.pulses.iter_mut() {
+            pulse.clock(self.shift);
+        }
+        self.sawtooth.clock(self.shift);
+    }
+
+    pub fn get_output(&self) -> f32 {
+        let sum = self.pulses[0].get_output() as f32 + self.pulses[1].get_output() as f32 + self.sawtooth.get_output() as f32;
+        sum * OUTPUT_SCALE
+    }
+}
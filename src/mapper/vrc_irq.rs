@@ -0,0 +1,85 @@
+// from https://wiki.nesdev.com/w/index.php/VRC_IRQ
+// shared by the VRC4, VRC6 and VRC7
+
+const PRESCALER_PERIOD: i16 = 341;
+
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_acknowledge: bool,
+    cycle_mode: bool,
+    irq: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> VrcIrq {
+        VrcIrq {
+            latch: 0,
+            counter: 0,
+            prescaler: PRESCALER_PERIOD,
+            enabled: false,
+            enable_after_acknowledge: false,
+            cycle_mode: false,
+            irq: false,
+        }
+    }
+
+    pub fn set_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    pub fn set_latch_low(&mut self, value: u8) {
+        self.latch = (self.latch & 0xf0) | (value & 0x0f);
+    }
+
+    pub fn set_latch_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x0f) | ((value & 0x0f) << 4);
+    }
+
+    pub fn set_control(&mut self, value: u8) {
+        self.enable_after_acknowledge = (value & 0b001) != 0;
+        self.enabled = (value & 0b010) != 0;
+        self.cycle_mode = (value & 0b100) != 0;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+        self.irq = false;
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.irq = false;
+        self.enabled = self.enable_after_acknowledge;
+    }
+
+    // in scanline mode the prescaler divides CPU cycles by 113.667 (341 / 3)
+    pub fn step_cpu(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock_counter();
+            }
+        }
+    }
+
+    pub fn get_irq(&self) -> bool {
+        self.irq
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.irq = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
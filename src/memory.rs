@@ -1,19 +1,342 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::apu::apu::APU;
+use crate::cartridge::cartridge::Cartridge;
+use crate::controller::Controller;
+use crate::ppu::ppu::PPU;
+use crate::timing::{Timing, NTSC};
+
 pub struct Memory {
     bytes: [u8; 64 * 1024],
+    cartridge: Option<Rc<RefCell<Cartridge>>>,
+    ppu: Option<Rc<RefCell<PPU>>>,
+    // part of the CPU chip, so every bus has one
+    apu: APU,
+    // ports 1 and 2, read at $4016 and $4017
+    controllers: [Controller; 2],
+    timing: Timing,
+    // PPU dots owed to the PPU, in fifths of a dot on PAL
+    ppu_dot_fraction: u32,
+    // the last autosave that failed, until the frontend takes it
+    save_error: Option<String>,
+    // page written to $4014, copied to OAM on the CPU's next read
+    oam_dma_page: Option<u8>,
 }
 
 impl Memory {
     pub fn new() -> Memory {
         Memory {
             bytes: [0; 64 * 1024],
+            cartridge: None,
+            ppu: None,
+            apu: APU::new(),
+            controllers: [Controller::new(), Controller::new()],
+            timing: NTSC,
+            ppu_dot_fraction: 0,
+            save_error: None,
+            oam_dma_page: None,
+        }
+    }
+
+    // the timing follows the cartridge's region, set_timing overrides it afterwards
+    pub fn insert_cartridge(&mut self, cartridge: Rc<RefCell<Cartridge>>) {
+        let region = (*cartridge.borrow()).get_header().region;
+        self.cartridge = Some(cartridge);
+        self.set_timing(Timing::from_region(region));
+    }
+
+    pub fn insert_ppu(&mut self, ppu: Rc<RefCell<PPU>>) {
+        (*ppu.borrow_mut()).set_timing(&self.timing);
+        self.ppu = Some(ppu);
+    }
+
+    pub fn set_buttons(&mut self, port: usize, buttons: u8) {
+        self.controllers[port].set_buttons(buttons);
+    }
+
+    pub fn get_timing(&self) -> &Timing {
+        &self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.ppu_dot_fraction = 0;
+        self.apu.set_timing(&timing);
+        if let Some(ppu) = &self.ppu {
+            (*ppu.borrow_mut()).set_timing(&timing);
+        }
+    }
+
+    // level of the shared /IRQ line
+    pub fn get_irq(&self) -> bool {
+        let cartridge_irq = match &self.cartridge {
+            Some(cartridge) => (*cartridge.borrow()).get_irq(),
+            None => false,
+        };
+        cartridge_irq || self.apu.get_irq()
+    }
+
+    // an NMI the PPU asserted and the CPU has not taken yet
+    pub fn get_nmi(&self) -> bool {
+        match &self.ppu {
+            Some(ppu) => (*ppu.borrow()).is_nmi_pending(),
+            None => false,
+        }
+    }
+
+    pub fn acknowledge_nmi(&mut self) {
+        if let Some(ppu) = &self.ppu {
+            (*ppu.borrow_mut()).acknowledge_nmi();
+        }
+    }
+
+    // mixed output of the sound sources on the bus
+    pub fn get_audio_output(&self) -> f32 {
+        let cartridge_output = match &self.cartridge {
+            Some(cartridge) => (*cartridge.borrow()).get_audio_output(),
+            None => 0.0,
+        };
+        self.apu.get_output() + cartridge_output
+    }
+
+    // runs everything else on the bus for one CPU cycle
+    pub fn step_cpu(&mut self) {
+        let mut frame_ended = false;
+        if let Some(ppu) = &self.ppu {
+            let mut ppu = ppu.borrow_mut();
+            let frame = (*ppu).get_frame();
+            self.ppu_dot_fraction += self.timing.ppu_dots;
+            while self.ppu_dot_fraction >= self.timing.cpu_cycles {
+                self.ppu_dot_fraction -= self.timing.cpu_cycles;
+                (*ppu).tick();
+            }
+            frame_ended = (*ppu).get_frame() != frame;
+        }
+        self.apu.step_cpu();
+        if let Some(cartridge) = &self.cartridge {
+            (*cartridge.borrow_mut()).step_cpu();
+        }
+        if frame_ended {
+            self.end_frame();
+        }
+    }
+
+    // the cartridge autosaves between frames, a failed save is kept for the
+    // frontend to report
+    fn end_frame(&mut self) {
+        if let Some(cartridge) = &self.cartridge {
+            if let Err(error) = (*cartridge.borrow_mut()).end_frame(&self.timing) {
+                self.save_error = Some(error);
+            }
+        }
+    }
+
+    pub fn take_save_error(&mut self) -> Option<String> {
+        self.save_error.take()
+    }
+
+    // fetches a pending DMC sample byte, halting the CPU on its read of
+    // halted_address. Returns the cycles stolen from the CPU, from
+    // https://wiki.nesdev.com/w/index.php/DMA#DMC_DMA
+    pub fn run_dmc_dma(&mut self, halted_address: u16) -> u8 {
+        let address = match self.apu.get_dma_address() {
+            Some(address) => address,
+            None => return 0,
+        };
+
+        // the halt and dummy cycles, and an alignment cycle when the fetch
+        // would land on a put cycle. The CPU keeps repeating its read on each
+        // of them. The controller ports only notice the first of consecutive
+        // reads, which still clocks their shift registers once more than the
+        // program asked for.
+        let mut cycles = 0;
+        let waiting_cycles = if self.apu.is_next_cycle_get() { 2 } else { 3 };
+        let controller_read = halted_address == 0x4016 || halted_address == 0x4017;
+        while cycles < waiting_cycles {
+            if !controller_read || cycles == 0 {
+                self.get_byte(halted_address);
+            }
+            self.step_cpu();
+            cycles += 1;
         }
+
+        let value = self.get_byte(address);
+        self.apu.load_dma_sample(value);
+        self.step_cpu();
+        cycles + 1
+    }
+
+    // copies the page written to $4014 into OAM through $2004, halting the CPU
+    // on its read of halted_address. Takes 513 cycles, 514 with an alignment
+    // cycle when the first read would land on a put cycle, from
+    // https://wiki.nesdev.com/w/index.php/PPU_registers#OAMDMA
+    pub fn run_oam_dma(&mut self, halted_address: u16) -> u16 {
+        let page = match self.oam_dma_page.take() {
+            Some(page) => page,
+            None => return 0,
+        };
+
+        // the halted read is repeated on the waiting cycles, like with DMC
+        // fetches only the first one reaches the controller ports
+        let mut cycles = 0;
+        let waiting_cycles = if self.apu.is_next_cycle_get() { 2 } else { 1 };
+        let controller_read = halted_address == 0x4016 || halted_address == 0x4017;
+        while cycles < waiting_cycles {
+            if !controller_read || cycles == 0 {
+                self.get_byte(halted_address);
+            }
+            self.step_cpu();
+            cycles += 1;
+        }
+
+        for offset in 0..=0xff {
+            let value = self.get_byte(((page as u16) << 8) | offset);
+            self.step_cpu();
+            self.set_byte(0x2004, value);
+            self.step_cpu();
+            cycles += 2;
+        }
+        cycles
     }
 
     pub fn get_byte(&mut self, location: u16) -> u8 {
+        if let (0x2000..=0x3fff, Some(ppu)) = (location, &self.ppu) {
+            return (*ppu.borrow_mut()).read_register(location);
+        }
+        match location {
+            0x4015 => return self.apu.read_status(),
+            0x4016 => return self.controllers[0].read(),
+            0x4017 => return self.controllers[1].read(),
+            _ => {}
+        }
+        if let (0x4020..=0xffff, Some(cartridge)) = (location, &self.cartridge) {
+            return (*cartridge.borrow_mut()).read_prg(location);
+        }
         self.bytes[location as usize]
     }
 
     pub fn set_byte(&mut self, location: u16, value: u8) {
+        if let (0x2000..=0x3fff, Some(ppu)) = (location, &self.ppu) {
+            (*ppu.borrow_mut()).write_register(location, value);
+            return;
+        }
+        if location == 0x4014 {
+            self.oam_dma_page = Some(value);
+            return;
+        }
+        // the strobe goes out to both ports
+        if location == 0x4016 {
+            for controller in self.controllers.iter_mut() {
+                controller.write_strobe(value);
+            }
+            return;
+        }
+        if let 0x4000..=0x4013 | 0x4015 | 0x4017 = location {
+            self.apu.write_register(location, value);
+            return;
+        }
+        if let (0x4020..=0xffff, Some(cartridge)) = (location, &self.cartridge) {
+            (*cartridge.borrow_mut()).write_prg(location, value);
+            return;
+        }
         self.bytes[location as usize] = value;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::{BUTTON_A, BUTTON_START};
+
+    fn strobe(memory: &mut Memory) {
+        memory.set_byte(0x4016, 1);
+        memory.set_byte(0x4016, 0);
+    }
+
+    #[test]
+    fn controllers_shift_out_buttons() {
+        let mut memory = Memory::new();
+        memory.set_buttons(0, BUTTON_A | BUTTON_START);
+        memory.set_buttons(1, BUTTON_START);
+        strobe(&mut memory);
+        let port_1: Vec<u8> = (0..9).map(|_| memory.get_byte(0x4016) & 0b1).collect();
+        let port_2: Vec<u8> = (0..9).map(|_| memory.get_byte(0x4017) & 0b1).collect();
+        assert_eq!(port_1, [1, 0, 0, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(port_2, [0, 0, 0, 1, 0, 0, 0, 0, 1]);
+    }
+
+    // a DMC fetch halting a $4016 read clocks the controller once more, the
+    // read that follows sees B instead of A
+    #[test]
+    fn dmc_dma_drops_a_controller_bit() {
+        let mut memory = Memory::new();
+        memory.set_buttons(0, BUTTON_A);
+        strobe(&mut memory);
+        memory.set_byte(0x4013, 0);
+        memory.set_byte(0x4015, 0x10);
+        assert!(memory.run_dmc_dma(0x4016) > 0);
+        assert_eq!(memory.get_byte(0x4016) & 0b1, 0);
+
+        strobe(&mut memory);
+        assert_eq!(memory.get_byte(0x4016) & 0b1, 1);
+    }
+
+    // NROM with battery backed PRG RAM, saving to a file of the test's own
+    fn create_battery_cartridge(name: &str) -> (Rc<RefCell<Cartridge>>, std::path::PathBuf) {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 1, 1, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend(vec![0; 0x6000]);
+        let path = std::env::temp_dir().join(format!("emulator_{}_{}.sav", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut cartridge = Cartridge::from_bytes(&rom).unwrap();
+        cartridge.attach_battery_file(path.clone()).unwrap();
+        (Rc::new(RefCell::new(cartridge)), path)
+    }
+
+    #[test]
+    fn battery_autosaves_as_frames_end() {
+        let (cartridge, path) = create_battery_cartridge("autosave");
+        let ppu = Rc::new(RefCell::new(PPU::new()));
+        (*ppu.borrow_mut()).insert_cartridge(Rc::clone(&cartridge));
+        let mut memory = Memory::new();
+        memory.insert_cartridge(Rc::clone(&cartridge));
+        memory.insert_ppu(Rc::clone(&ppu));
+        memory.set_byte(0x6000, 0x42);
+
+        let run_until_frame = |memory: &mut Memory, frame: u64| {
+            while (*ppu.borrow()).get_frame() < frame {
+                memory.step_cpu();
+            }
+        };
+        run_until_frame(&mut memory, 300);
+        assert!(!path.exists());
+        run_until_frame(&mut memory, 301);
+        assert_eq!(std::fs::read(&path).unwrap()[0], 0x42);
+        assert!(memory.take_save_error().is_none());
+
+        // whatever changed since is flushed once the cartridge goes away
+        memory.set_byte(0x6000, 0x43);
+        drop(memory);
+        drop(ppu);
+        drop(cartridge);
+        assert_eq!(std::fs::read(&path).unwrap()[0], 0x43);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_autosaves_are_kept_for_the_frontend() {
+        // a directory where the save should go makes the write fail
+        let (cartridge, path) = create_battery_cartridge("failed");
+        std::fs::create_dir(&path).unwrap();
+        let mut memory = Memory::new();
+        memory.insert_cartridge(Rc::clone(&cartridge));
+        memory.set_byte(0x6000, 0x42);
+        for _ in 0..301 {
+            memory.end_frame();
+        }
+        assert!(memory.take_save_error().unwrap().contains("emulator_failed"));
+        assert!(memory.take_save_error().is_none());
+        drop(memory);
+        drop(cartridge);
+        std::fs::remove_dir(&path).unwrap();
+    }
+}
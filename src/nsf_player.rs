@@ -0,0 +1,245 @@
+use std::fs;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::cartridge::cartridge::Cartridge;
+use crate::cartridge::header::Region;
+use crate::cartridge::nsf::NsfFile;
+use crate::cpu::cpu::CPU;
+use crate::cpu::registers::RegisterType;
+use crate::memory::Memory;
+use crate::timing::{NTSC, PAL};
+
+// INIT and PLAY are called as subroutines returning here, an address no tune
+// runs code from. While the CPU sits on it the player is idle.
+const RETURN_ADDRESS: u16 = 0x4100;
+
+// plays NSF rips on a bare CPU bus, calling INIT once per song and PLAY at the
+// rate the file asks for, from https://wiki.nesdev.com/w/index.php/NSF#Playing_a_song
+pub struct NsfPlayer {
+    nsf: NsfFile,
+    memory: Rc<RefCell<Memory>>,
+    cpu: CPU,
+    song: u8,
+    pal: bool,
+    sample_rate: u32,
+    // CPU cycles between PLAY calls and per output sample
+    play_period: f64,
+    cycles_per_sample: f64,
+    play_timer: f64,
+    sample_cycles: f64,
+    samples: u64,
+}
+
+impl NsfPlayer {
+    pub fn load(path: &str, sample_rate: u32) -> Result<NsfPlayer, String> {
+        let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        Ok(NsfPlayer::new(NsfFile::parse(&bytes)?, sample_rate))
+    }
+
+    // dual region tunes play at NTSC speed
+    pub fn new(nsf: NsfFile, sample_rate: u32) -> NsfPlayer {
+        let memory = Rc::new(RefCell::new(Memory::new()));
+        let cpu = CPU::new(Rc::clone(&memory));
+        let song = nsf.starting_song;
+        let pal = nsf.region == Region::Pal;
+        let mut player = NsfPlayer {
+            nsf,
+            memory,
+            cpu,
+            song,
+            pal,
+            sample_rate,
+            play_period: 0.0,
+            cycles_per_sample: 0.0,
+            play_timer: 0.0,
+            sample_cycles: 0.0,
+            samples: 0,
+        };
+        player.select_song(song);
+        player
+    }
+
+    pub fn get_nsf(&self) -> &NsfFile {
+        &self.nsf
+    }
+
+    pub fn get_song_count(&self) -> u8 {
+        self.nsf.songs
+    }
+
+    // 0 based
+    pub fn get_song(&self) -> u8 {
+        self.song
+    }
+
+    // only matters for dual region tunes, takes effect on the next song selection
+    pub fn set_pal(&mut self, pal: bool) {
+        self.pal = pal;
+    }
+
+    // restarts the bus with fresh RAM and banks, then calls INIT for the song
+    pub fn select_song(&mut self, song: u8) {
+        self.song = song.min(self.nsf.songs.saturating_sub(1));
+
+        let cartridge = Rc::new(RefCell::new(Cartridge::from_nsf(&self.nsf)));
+        self.memory = Rc::new(RefCell::new(Memory::new()));
+        (*self.memory.borrow_mut()).insert_cartridge(cartridge);
+        (*self.memory.borrow_mut()).set_timing(if self.pal { PAL } else { NTSC });
+        self.cpu = CPU::new(Rc::clone(&self.memory));
+        self.initialize_apu();
+
+        let (clock, speed) = if self.pal {
+            (PAL.cpu_clock, self.nsf.pal_speed)
+        } else {
+            (NTSC.cpu_clock, self.nsf.ntsc_speed)
+        };
+        self.play_period = clock * speed as f64 / 1_000_000.0;
+        self.cycles_per_sample = clock / self.sample_rate as f64;
+        self.play_timer = 0.0;
+        self.sample_cycles = 0.0;
+        self.samples = 0;
+
+        self.cpu.set_register(RegisterType::S, 0xfd);
+        self.cpu.set_register(RegisterType::A, self.song);
+        self.cpu.set_register(RegisterType::X, if self.pal { 1 } else { 0 });
+        self.call(self.nsf.init_address);
+    }
+
+    pub fn next_song(&mut self) {
+        if self.song + 1 < self.nsf.songs {
+            self.select_song(self.song + 1);
+        }
+    }
+
+    pub fn previous_song(&mut self) {
+        if self.song > 0 {
+            self.select_song(self.song - 1);
+        }
+    }
+
+    // time played of the current song
+    pub fn get_elapsed_ms(&self) -> u64 {
+        self.samples * 1000 / self.sample_rate as u64
+    }
+
+    // past the NSFe duration and fade of the song, never for songs without one
+    pub fn is_song_finished(&self) -> bool {
+        match self.nsf.get_track_duration(self.song) {
+            Some(duration) => {
+                let fade = self.nsf.get_track_fade(self.song).unwrap_or(0);
+                self.get_elapsed_ms() >= duration as u64 + fade as u64
+            }
+            None => false,
+        }
+    }
+
+    // fills the buffer with mono samples at the player's sample rate, each the
+    // average output over the CPU cycles it spans
+    pub fn render(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            self.sample_cycles += self.cycles_per_sample;
+            let mut output = 0.0;
+            let mut cycles = 0;
+            while self.sample_cycles >= 1.0 {
+                let taken = self.step();
+                output += (*self.memory.borrow()).get_audio_output() * taken as f32;
+                cycles += taken;
+                self.sample_cycles -= taken as f64;
+            }
+            *sample = if cycles > 0 { output / cycles as f32 } else { (*self.memory.borrow()).get_audio_output() };
+            self.samples += 1;
+        }
+    }
+
+    fn step(&mut self) -> u32 {
+        // an idle CPU still has DMC samples fetched from under it
+        let cycles = if self.is_idle() {
+            let mut memory = self.memory.borrow_mut();
+            (*memory).step_cpu();
+            1 + (*memory).run_dmc_dma(RETURN_ADDRESS) as u32
+        } else {
+            self.cpu.step() as u32
+        };
+
+        // a PLAY call running long delays the next one until it returns
+        self.play_timer += cycles as f64;
+        if self.play_timer >= self.play_period && self.is_idle() {
+            self.play_timer -= self.play_period;
+            self.call(self.nsf.play_address);
+        }
+        cycles
+    }
+
+    fn is_idle(&self) -> bool {
+        self.cpu.get_pc() == RETURN_ADDRESS
+    }
+
+    // same stack layout as a JSR from the byte before the return address
+    fn call(&mut self, address: u16) {
+        let return_address = RETURN_ADDRESS - 1;
+        self.cpu.push((return_address >> 8) as u8);
+        self.cpu.push((return_address & 0xff) as u8);
+        self.cpu.set_pc(address);
+    }
+
+    // silences the channels and enables all but DMC, as players do before INIT
+    fn initialize_apu(&mut self) {
+        let mut memory = self.memory.borrow_mut();
+        for address in 0x4000..=0x4013 {
+            (*memory).set_byte(address, 0);
+        }
+        (*memory).set_byte(0x4015, 0x00);
+        (*memory).set_byte(0x4015, 0x0f);
+        (*memory).set_byte(0x4017, 0x40);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::nsf::tests::create_nsf;
+
+    // INIT stores the song number to $01, PLAY counts its calls in $00
+    fn create_player(speed: u16, sample_rate: u32) -> NsfPlayer {
+        let program = [
+            0x85, 0x01, 0x60, // STA $01, RTS
+            0xe6, 0x00, 0x60, // INC $00, RTS
+        ];
+        let mut bytes = create_nsf(0x8000, 0x8000, 0x8003, [0; 8], 0, &program);
+        bytes[0x6e..0x70].copy_from_slice(&speed.to_le_bytes());
+        NsfPlayer::new(NsfFile::parse(&bytes).unwrap(), sample_rate)
+    }
+
+    fn get_play_calls(player: &NsfPlayer) -> u8 {
+        (*player.memory.borrow_mut()).get_byte(0x0000)
+    }
+
+    #[test]
+    fn init_gets_the_song() {
+        let mut player = create_player(0, 1000);
+        player.render(&mut [0.0; 1]);
+        assert_eq!((*player.memory.borrow_mut()).get_byte(0x0001), 1);
+        player.next_song();
+        player.render(&mut [0.0; 1]);
+        assert_eq!((*player.memory.borrow_mut()).get_byte(0x0001), 2);
+        player.next_song();
+        assert_eq!(player.get_song(), 2);
+    }
+
+    #[test]
+    fn play_is_called_at_the_file_rate() {
+        // 10000us is 100 calls a second
+        let mut player = create_player(10000, 1000);
+        let mut buffer = [0.0; 500];
+        player.render(&mut buffer);
+        assert!((49..=50).contains(&get_play_calls(&player)), "{}", get_play_calls(&player));
+        player.render(&mut buffer);
+        assert!((99..=100).contains(&get_play_calls(&player)), "{}", get_play_calls(&player));
+        assert_eq!(player.get_elapsed_ms(), 1000);
+
+        // a speed of 0 falls back to the NTSC frame rate
+        let mut player = create_player(0, 1000);
+        player.render(&mut [0.0; 1000]);
+        assert!((59..=60).contains(&get_play_calls(&player)), "{}", get_play_calls(&player));
+    }
+}
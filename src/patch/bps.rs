@@ -0,0 +1,162 @@
+use crate::crc32::crc32;
+use crate::patch::ups::{read_varint, read_u32, MAX_TARGET_SIZE};
+
+// from https://www.romhacking.net/documents/746/
+const MAGIC: &[u8] = b"BPS1";
+// CRC32 of the source, the target and the patch itself
+const FOOTER_SIZE: usize = 12;
+
+const SOURCE_READ: usize = 0;
+const TARGET_READ: usize = 1;
+const SOURCE_COPY: usize = 2;
+const TARGET_COPY: usize = 3;
+
+pub fn is_bps(patch: &[u8]) -> bool {
+    patch.starts_with(MAGIC)
+}
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !is_bps(patch) {
+        return Err(String::from("not a BPS patch"));
+    }
+    if patch.len() < MAGIC.len() + FOOTER_SIZE {
+        return Err(String::from("BPS patch is truncated"));
+    }
+    let footer = patch.len() - FOOTER_SIZE;
+    if crc32(&patch[..footer + 8]) != read_u32(&patch[footer + 8..]) {
+        return Err(String::from("BPS patch is corrupted, checksum mismatch"));
+    }
+
+    let mut position = MAGIC.len();
+    let source_size = read_varint(patch, &mut position, footer)?;
+    let target_size = read_varint(patch, &mut position, footer)?;
+    let metadata_size = read_varint(patch, &mut position, footer)?;
+    position = position.checked_add(metadata_size).ok_or("patch number overflows")?;
+    if source.len() != source_size || crc32(source) != read_u32(&patch[footer..]) {
+        return Err(String::from("BPS patch was made for a different ROM"));
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(String::from("BPS patch target is too large"));
+    }
+
+    let mut output: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+    while position < footer {
+        let action = read_varint(patch, &mut position, footer)?;
+        let length = (action >> 2) + 1;
+        if length > target_size - output.len() {
+            return Err(String::from("BPS patch writes past the end of the target"));
+        }
+        match action & 0b11 {
+            SOURCE_READ => {
+                let start = output.len();
+                if length > source.len().saturating_sub(start) {
+                    return Err(String::from("BPS patch reads past the end of the source"));
+                }
+                output.extend_from_slice(&source[start..start + length]);
+            }
+            TARGET_READ => {
+                if length > footer.saturating_sub(position) {
+                    return Err(String::from("BPS patch is truncated"));
+                }
+                output.extend_from_slice(&patch[position..position + length]);
+                position += length;
+            }
+            SOURCE_COPY => {
+                source_offset = apply_relative(source_offset, read_varint(patch, &mut position, footer)?)?;
+                if length > source.len().saturating_sub(source_offset) {
+                    return Err(String::from("BPS patch reads past the end of the source"));
+                }
+                output.extend_from_slice(&source[source_offset..source_offset + length]);
+                source_offset += length;
+            }
+            TARGET_COPY => {
+                target_offset = apply_relative(target_offset, read_varint(patch, &mut position, footer)?)?;
+                // byte by byte, the copy may overlap what it is writing to repeat patterns
+                for _ in 0..length {
+                    if target_offset >= output.len() {
+                        return Err(String::from("BPS patch reads past the end of the target"));
+                    }
+                    output.push(output[target_offset]);
+                    target_offset += 1;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if output.len() != target_size || crc32(&output) != read_u32(&patch[footer + 4..]) {
+        return Err(String::from("BPS patch produced a wrong result, target checksum mismatch"));
+    }
+    Ok(output)
+}
+
+// the lowest bit is the sign of the offset
+fn apply_relative(offset: usize, value: usize) -> Result<usize, String> {
+    let distance = value >> 1;
+    let result = if (value & 0b1) != 0 { offset.checked_sub(distance) } else { offset.checked_add(distance) };
+    result.ok_or_else(|| String::from("BPS patch copies from before the start of the file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::ups::tests::{write_varint, write_footer};
+
+    const SOURCE: &[u8] = b"ABCDEFGH";
+    const TARGET: &[u8] = b"ABCDxyxyxyEF";
+
+    fn write_action(patch: &mut Vec<u8>, action: usize, length: usize) {
+        write_varint(patch, ((length - 1) << 2) | action);
+    }
+
+    fn build_patch() -> Vec<u8> {
+        let mut patch = MAGIC.to_vec();
+        write_varint(&mut patch, SOURCE.len());
+        write_varint(&mut patch, TARGET.len());
+        write_varint(&mut patch, 0);
+        write_action(&mut patch, SOURCE_READ, 4);
+        write_action(&mut patch, TARGET_READ, 2);
+        patch.extend_from_slice(b"xy");
+        // repeats the "xy" just written, forward 4 from the start of the target
+        write_action(&mut patch, TARGET_COPY, 4);
+        write_varint(&mut patch, 4 << 1);
+        write_action(&mut patch, SOURCE_COPY, 2);
+        write_varint(&mut patch, 4 << 1);
+        write_footer(&mut patch, SOURCE, TARGET);
+        patch
+    }
+
+    #[test]
+    fn applies_patch() {
+        assert_eq!(apply(SOURCE, &build_patch()), Ok(TARGET.to_vec()));
+    }
+
+    #[test]
+    fn rejects_other_source() {
+        assert_eq!(apply(b"ABCDEFGX", &build_patch()), Err(String::from("BPS patch was made for a different ROM")));
+    }
+
+    #[test]
+    fn rejects_truncated_patch() {
+        // drops the footer and the offset of the last copy
+        let mut patch = build_patch();
+        patch.truncate(patch.len() - FOOTER_SIZE - 1);
+        write_footer(&mut patch, SOURCE, TARGET);
+        assert_eq!(apply(SOURCE, &patch), Err(String::from("patch is truncated")));
+        assert_eq!(apply(SOURCE, b"BPS1"), Err(String::from("BPS patch is truncated")));
+    }
+
+    #[test]
+    fn rejects_copies_before_the_start() {
+        let mut patch = MAGIC.to_vec();
+        write_varint(&mut patch, SOURCE.len());
+        write_varint(&mut patch, 2);
+        write_varint(&mut patch, 0);
+        write_action(&mut patch, SOURCE_COPY, 2);
+        write_varint(&mut patch, (1 << 1) | 1);
+        write_footer(&mut patch, SOURCE, b"AB");
+        assert_eq!(apply(SOURCE, &patch), Err(String::from("BPS patch copies from before the start of the file")));
+    }
+}
@@ -0,0 +1,170 @@
+// from https://zerosoft.zophar.net/ips.php
+const MAGIC: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+// offset 0x454f46 spells "EOF" and would end the patch early
+const EOF_OFFSET: usize = 0x454f46;
+const MAX_OFFSET: usize = 0xffffff;
+const MAX_RECORD_SIZE: usize = 0xffff;
+// unchanged runs shorter than a record header are cheaper to include in the record
+const MERGE_DISTANCE: usize = 5;
+
+pub fn is_ips(patch: &[u8]) -> bool {
+    patch.starts_with(MAGIC)
+}
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !is_ips(patch) {
+        return Err(String::from("not an IPS patch"));
+    }
+
+    let mut output = source.to_vec();
+    let mut position = MAGIC.len();
+    loop {
+        if position + 3 > patch.len() {
+            return Err(String::from("IPS patch is truncated"));
+        }
+        if &patch[position..position + 3] == FOOTER {
+            position += 3;
+            break;
+        }
+        if position + 5 > patch.len() {
+            return Err(String::from("IPS patch is truncated"));
+        }
+        let offset = read_u24(&patch[position..]);
+        let size = read_u16(&patch[position + 3..]);
+        position += 5;
+
+        let (data, length) = if size == 0 {
+            // run length encoded record: 2 byte count and the byte to repeat
+            if position + 3 > patch.len() {
+                return Err(String::from("IPS patch is truncated"));
+            }
+            let count = read_u16(&patch[position..]);
+            let value = patch[position + 2];
+            position += 3;
+            (vec![value; count], count)
+        } else {
+            if position + size > patch.len() {
+                return Err(String::from("IPS patch is truncated"));
+            }
+            let data = patch[position..position + size].to_vec();
+            position += size;
+            (data, size)
+        };
+
+        if output.len() < offset + length {
+            output.resize(offset + length, 0);
+        }
+        output[offset..offset + length].copy_from_slice(&data);
+    }
+
+    // optional truncation extension after the footer
+    if position + 3 <= patch.len() {
+        output.truncate(read_u24(&patch[position..]));
+    }
+    Ok(output)
+}
+
+pub fn create(original: &[u8], modified: &[u8]) -> Result<Vec<u8>, String> {
+    if modified.len() > MAX_OFFSET + 1 {
+        return Err(String::from("file is too large for an IPS patch"));
+    }
+
+    let mut patch = MAGIC.to_vec();
+    let mut position = 0;
+    while position < modified.len() {
+        if position < original.len() && original[position] == modified[position] {
+            position += 1;
+            continue;
+        }
+
+        let start = if position == EOF_OFFSET { position - 1 } else { position };
+        let mut end = position + 1;
+        let mut changed_end = end;
+        while end < modified.len() && end - start < MAX_RECORD_SIZE {
+            if end >= original.len() || original[end] != modified[end] {
+                changed_end = end + 1;
+            } else if end - changed_end >= MERGE_DISTANCE {
+                break;
+            }
+            end += 1;
+        }
+        let end = changed_end;
+
+        write_u24(&mut patch, start);
+        write_u16(&mut patch, end - start);
+        patch.extend_from_slice(&modified[start..end]);
+        position = end;
+    }
+    patch.extend_from_slice(FOOTER);
+    if modified.len() < original.len() {
+        write_u24(&mut patch, modified.len());
+    }
+    Ok(patch)
+}
+
+fn read_u24(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | bytes[2] as usize
+}
+
+fn read_u16(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 8) | bytes[1] as usize
+}
+
+fn write_u24(patch: &mut Vec<u8>, value: usize) {
+    patch.extend_from_slice(&[(value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
+
+fn write_u16(patch: &mut Vec<u8>, value: usize) {
+    patch.extend_from_slice(&[(value >> 8) as u8, value as u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_run_length_encoded_records() {
+        // 4 bytes of $AA at 2, then 3 bytes of $BB past the end of the file
+        let mut patch = MAGIC.to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0xaa]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x03, 0xbb]);
+        patch.extend_from_slice(FOOTER);
+        assert_eq!(apply(&[0; 8], &patch), Ok(vec![0, 0, 0xaa, 0xaa, 0xaa, 0xaa, 0, 0xbb, 0xbb, 0xbb]));
+    }
+
+    #[test]
+    fn round_trips() {
+        let original: Vec<u8> = (0..=255).cycle().take(0x1000).collect();
+        let mut modified = original.clone();
+        modified[0x10] = 0;
+        modified[0x12] = 0;
+        modified[0x800..0x900].fill(0xff);
+        modified.extend_from_slice(&[1, 2, 3]);
+        let patch = create(&original, &modified).unwrap();
+        assert_eq!(apply(&original, &patch), Ok(modified));
+
+        // shrinking the file uses the truncation extension
+        let truncated = original[..0x100].to_vec();
+        let patch = create(&original, &truncated).unwrap();
+        assert_eq!(apply(&original, &patch), Ok(truncated));
+    }
+
+    #[test]
+    fn writes_around_the_eof_offset() {
+        let original = vec![0; EOF_OFFSET + 2];
+        let mut modified = original.clone();
+        modified[EOF_OFFSET] = 1;
+        let patch = create(&original, &modified).unwrap();
+        assert_eq!(&patch[MAGIC.len()..MAGIC.len() + 3], &[0x45, 0x4f, 0x45]);
+        assert_eq!(apply(&original, &patch), Ok(modified));
+    }
+
+    #[test]
+    fn rejects_truncated_patch() {
+        let truncated = Err(String::from("IPS patch is truncated"));
+        assert_eq!(apply(&[0; 8], b"PATCH\x00\x00\x01\x00\x05\x01\x02"), truncated);
+        assert_eq!(apply(&[0; 8], b"PATCH\x00\x00\x01\x00\x00\x00"), truncated);
+        assert_eq!(apply(&[0; 8], b"PATCH"), truncated);
+    }
+}
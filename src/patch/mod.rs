@@ -0,0 +1,4 @@
+pub mod patch;
+pub mod ips;
+pub mod ups;
+pub mod bps;
@@ -0,0 +1,15 @@
+use crate::patch::{ips, ups, bps};
+
+// picks the format from the patch's magic. IPS has no checksums, so only
+// UPS and BPS can tell when they are applied to the wrong ROM
+pub fn apply_patch(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if ips::is_ips(patch) {
+        ips::apply(source, patch)
+    } else if ups::is_ups(patch) {
+        ups::apply(source, patch)
+    } else if bps::is_bps(patch) {
+        bps::apply(source, patch)
+    } else {
+        Err(String::from("unknown patch format, expected IPS, UPS or BPS"))
+    }
+}
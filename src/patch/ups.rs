@@ -0,0 +1,191 @@
+use crate::crc32::crc32;
+
+// from http://www.romhacking.net/documents/392/
+const MAGIC: &[u8] = b"UPS1";
+// CRC32 of the source, the target and the patch itself
+const FOOTER_SIZE: usize = 12;
+// far beyond any NES ROM, keeps a corrupt size from allocating the whole memory
+pub const MAX_TARGET_SIZE: usize = 64 * 1024 * 1024;
+
+pub fn is_ups(patch: &[u8]) -> bool {
+    patch.starts_with(MAGIC)
+}
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !is_ups(patch) {
+        return Err(String::from("not a UPS patch"));
+    }
+    if patch.len() < MAGIC.len() + FOOTER_SIZE {
+        return Err(String::from("UPS patch is truncated"));
+    }
+    let footer = patch.len() - FOOTER_SIZE;
+    if crc32(&patch[..footer + 8]) != read_u32(&patch[footer + 8..]) {
+        return Err(String::from("UPS patch is corrupted, checksum mismatch"));
+    }
+
+    let mut position = MAGIC.len();
+    let source_size = read_varint(patch, &mut position, footer)?;
+    let target_size = read_varint(patch, &mut position, footer)?;
+    if source.len() != source_size || crc32(source) != read_u32(&patch[footer..]) {
+        return Err(String::from("UPS patch was made for a different ROM"));
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(String::from("UPS patch target is too large"));
+    }
+
+    let mut output = source.to_vec();
+    output.resize(target_size, 0);
+    let mut offset: usize = 0;
+    while position < footer {
+        offset = offset.checked_add(read_varint(patch, &mut position, footer)?).ok_or("patch number overflows")?;
+        // XORed bytes up to and including a zero, which stands for an unchanged byte
+        loop {
+            if position >= footer {
+                return Err(String::from("UPS patch is truncated"));
+            }
+            let value = patch[position];
+            position += 1;
+            if offset < target_size {
+                output[offset] ^= value;
+            }
+            offset = offset.saturating_add(1);
+            if value == 0 {
+                break;
+            }
+        }
+    }
+
+    if crc32(&output) != read_u32(&patch[footer + 4..]) {
+        return Err(String::from("UPS patch produced a wrong result, target checksum mismatch"));
+    }
+    Ok(output)
+}
+
+// variable length number, 7 bits at a time with the end marked by the high bit.
+// Each byte after the first also adds 1 << shift, so no number has two encodings.
+pub fn read_varint(patch: &[u8], position: &mut usize, end: usize) -> Result<usize, String> {
+    let mut value: usize = 0;
+    let mut shift: u32 = 0;
+    loop {
+        if *position >= end {
+            return Err(String::from("patch is truncated"));
+        }
+        let byte = patch[*position];
+        *position += 1;
+        value = ((byte & 0x7f) as usize).checked_mul(1_usize << shift)
+            .and_then(|digit| value.checked_add(digit))
+            .ok_or("patch number overflows")?;
+        if (byte & 0x80) != 0 {
+            return Ok(value);
+        }
+        shift += 7;
+        if shift >= usize::BITS {
+            return Err(String::from("patch number overflows"));
+        }
+        value = value.checked_add(1 << shift).ok_or("patch number overflows")?;
+    }
+}
+
+pub fn read_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) | ((bytes[1] as u32) << 8) | ((bytes[2] as u32) << 16) | ((bytes[3] as u32) << 24)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn write_varint(patch: &mut Vec<u8>, mut value: usize) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                patch.push(0x80 | byte);
+                return;
+            }
+            patch.push(byte);
+            value -= 1;
+        }
+    }
+
+    // appends the CRC32 of the source, the target and the patch so far
+    pub fn write_footer(patch: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let checksum = crc32(patch);
+        patch.extend_from_slice(&checksum.to_le_bytes());
+    }
+
+    const SOURCE: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+    const TARGET: [u8; 10] = [1, 2, 9, 4, 5, 6, 7, 8, 10, 11];
+
+    // skips 2 bytes, changes 1, then skips to the end of the source and appends 2
+    fn build_patch(terminated: bool) -> Vec<u8> {
+        let mut patch = MAGIC.to_vec();
+        write_varint(&mut patch, SOURCE.len());
+        write_varint(&mut patch, TARGET.len());
+        write_varint(&mut patch, 2);
+        patch.extend_from_slice(&[3 ^ 9, 0]);
+        write_varint(&mut patch, 4);
+        patch.extend_from_slice(&[10, 11]);
+        if terminated {
+            patch.push(0);
+        }
+        write_footer(&mut patch, &SOURCE, &TARGET);
+        patch
+    }
+
+    #[test]
+    fn applies_patch() {
+        assert_eq!(apply(&SOURCE, &build_patch(true)), Ok(TARGET.to_vec()));
+    }
+
+    #[test]
+    fn rejects_other_source() {
+        let mut source = SOURCE;
+        source[0] = 0xff;
+        assert_eq!(apply(&source, &build_patch(true)), Err(String::from("UPS patch was made for a different ROM")));
+    }
+
+    #[test]
+    fn rejects_truncated_patch() {
+        assert_eq!(apply(&SOURCE, &build_patch(false)), Err(String::from("UPS patch is truncated")));
+        assert_eq!(apply(&SOURCE, b"UPS1"), Err(String::from("UPS patch is truncated")));
+    }
+
+    #[test]
+    fn reads_varints() {
+        for value in [0, 127, 128, 16511, 16512, usize::MAX] {
+            let mut patch = Vec::new();
+            write_varint(&mut patch, value);
+            let mut position = 0;
+            assert_eq!(read_varint(&patch, &mut position, patch.len()), Ok(value));
+            assert_eq!(position, patch.len());
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_varints() {
+        let overflow = Err(String::from("patch number overflows"));
+        // one more than usize::MAX
+        let mut patch = Vec::new();
+        write_varint(&mut patch, usize::MAX);
+        *patch.last_mut().unwrap() += 1;
+        assert_eq!(read_varint(&patch, &mut 0, patch.len()), overflow);
+        // more bytes than a usize has bits for
+        let patch = [0; 12];
+        assert_eq!(read_varint(&patch, &mut 0, patch.len()), overflow);
+    }
+
+    #[test]
+    fn rejects_overflowing_offsets() {
+        let mut patch = MAGIC.to_vec();
+        write_varint(&mut patch, SOURCE.len());
+        write_varint(&mut patch, SOURCE.len());
+        write_varint(&mut patch, 2);
+        patch.extend_from_slice(&[0]);
+        write_varint(&mut patch, usize::MAX);
+        patch.extend_from_slice(&[0]);
+        write_footer(&mut patch, &SOURCE, &SOURCE);
+        assert_eq!(apply(&SOURCE, &patch), Err(String::from("patch number overflows")));
+    }
+}
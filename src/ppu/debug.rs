@@ -0,0 +1,134 @@
+use crate::image::Image;
+use crate::ppu::palette::Palette;
+use crate::ppu::ppu::{PPU, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+// views of PPU memory for debugging. Memory is peeked, so mappers watching
+// the PPU bus do not notice, and emphasis and grayscale are left out.
+
+const TILE_SIZE: usize = 8;
+const SCROLL_OVERLAY_COLOR: [u8; 3] = [255, 0, 255];
+const PALETTE_SWATCH_SIZE: usize = 16;
+
+// 2 bit pixel of a tile in a pattern table
+fn get_tile_pixel(ppu: &PPU, tile_address: u16, row: usize, column: usize) -> u8 {
+    let low = ppu.peek_byte(tile_address + row as u16);
+    let high = ppu.peek_byte(tile_address + 8 + row as u16);
+    let shift = 7 - column;
+    (((high >> shift) & 1) << 1) | ((low >> shift) & 1)
+}
+
+// palettes 0-3 are the background ones, 4-7 the sprite ones
+fn get_color(ppu: &PPU, palette_number: u8, pixel: u8) -> u16 {
+    let address = if pixel == 0 { 0x3f00 } else { 0x3f00 | ((palette_number as u16) << 2) | pixel as u16 };
+    (ppu.peek_byte(address) & 0x3f) as u16
+}
+
+// flips come from sprite attribute bits 6 and 7
+fn draw_tile(image: &mut Image, ppu: &PPU, palette: &Palette, tile_address: u16, palette_number: u8, (x, y): (usize, usize), attributes: u8) {
+    let flip_horizontal = (attributes & 0x40) != 0;
+    let flip_vertical = (attributes & 0x80) != 0;
+    for row in 0..TILE_SIZE {
+        for column in 0..TILE_SIZE {
+            let source_row = if flip_vertical { 7 - row } else { row };
+            let source_column = if flip_horizontal { 7 - column } else { column };
+            let pixel = get_tile_pixel(ppu, tile_address, source_row, source_column);
+            let color = get_color(ppu, palette_number, pixel);
+            image.set_pixel(x + column, y + row, palette.get_color(color));
+        }
+    }
+}
+
+// the 256 tiles of pattern table 0 or 1 in a 128x128 image, drawn with one of the 8 palettes
+pub fn render_pattern_table(ppu: &PPU, table: u16, palette_number: u8, palette: &Palette) -> Image {
+    let mut image = Image::new(16 * TILE_SIZE, 16 * TILE_SIZE);
+    for tile in 0..256 {
+        let tile_address = (table & 1) * 0x1000 + tile * 16;
+        let x = (tile as usize % 16) * TILE_SIZE;
+        let y = (tile as usize / 16) * TILE_SIZE;
+        draw_tile(&mut image, ppu, palette, tile_address, palette_number & 0b111, (x, y), 0);
+    }
+    image
+}
+
+// the four nametables as they are mirrored, in a 512x480 image with the
+// visible 256x240 area outlined, wrapping around the edges like the scroll does
+pub fn render_nametables(ppu: &PPU, palette: &Palette) -> Image {
+    let mut image = Image::new(2 * SCREEN_WIDTH, 2 * SCREEN_HEIGHT);
+    let background_table = ppu.get_background_table();
+    for nametable in 0..4u16 {
+        let base = 0x2000 + nametable * 0x400;
+        let left = (nametable as usize & 1) * SCREEN_WIDTH;
+        let top = (nametable as usize >> 1) * SCREEN_HEIGHT;
+        for row in 0..30u16 {
+            for column in 0..32u16 {
+                let tile = ppu.peek_byte(base + row * 32 + column) as u16;
+                let attribute = ppu.peek_byte(base + 0x3c0 + (row / 4) * 8 + column / 4);
+                let shift = ((row & 0b10) << 1) | (column & 0b10);
+                let palette_number = (attribute >> shift) & 0b11;
+                let x = left + column as usize * TILE_SIZE;
+                let y = top + row as usize * TILE_SIZE;
+                draw_tile(&mut image, ppu, palette, background_table + tile * 16, palette_number, (x, y), 0);
+            }
+        }
+    }
+
+    let (scroll_x, scroll_y) = ppu.get_scroll();
+    let (width, height) = (image.get_width(), image.get_height());
+    for offset in 0..SCREEN_WIDTH {
+        let x = (scroll_x + offset) % width;
+        image.set_pixel(x, scroll_y % height, SCROLL_OVERLAY_COLOR);
+        image.set_pixel(x, (scroll_y + SCREEN_HEIGHT - 1) % height, SCROLL_OVERLAY_COLOR);
+    }
+    for offset in 0..SCREEN_HEIGHT {
+        let y = (scroll_y + offset) % height;
+        image.set_pixel(scroll_x % width, y, SCROLL_OVERLAY_COLOR);
+        image.set_pixel((scroll_x + SCREEN_WIDTH - 1) % width, y, SCROLL_OVERLAY_COLOR);
+    }
+    image
+}
+
+// the 64 OAM sprites in an 8x8 grid of 8x16 cells, with their palettes and flips
+pub fn render_oam(ppu: &PPU, palette: &Palette) -> Image {
+    let mut image = Image::new(8 * TILE_SIZE, 8 * 2 * TILE_SIZE);
+    let backdrop = palette.get_color(get_color(ppu, 0, 0));
+    for y in 0..image.get_height() {
+        for x in 0..image.get_width() {
+            image.set_pixel(x, y, backdrop);
+        }
+    }
+
+    let tall = ppu.get_sprite_height() == 16;
+    for (index, sprite) in ppu.get_oam().chunks(4).enumerate() {
+        let (tile, attributes) = (sprite[1] as u16, sprite[2]);
+        let palette_number = 4 + (attributes & 0b11);
+        let x = (index % 8) * TILE_SIZE;
+        let y = (index / 8) * 2 * TILE_SIZE;
+        if tall {
+            // a vertical flip also swaps the two halves
+            let table = (tile & 1) * 0x1000;
+            let (top, bottom) = if (attributes & 0x80) != 0 { (tile | 1, tile & 0xfe) } else { (tile & 0xfe, tile | 1) };
+            draw_tile(&mut image, ppu, palette, table + top * 16, palette_number, (x, y), attributes);
+            draw_tile(&mut image, ppu, palette, table + bottom * 16, palette_number, (x, y + TILE_SIZE), attributes);
+        } else {
+            let tile_address = ppu.get_sprite_table() + tile * 16;
+            draw_tile(&mut image, ppu, palette, tile_address, palette_number, (x, y), attributes);
+        }
+    }
+    image
+}
+
+// palette RAM as two rows of 16 swatches, background palettes on top
+pub fn render_palettes(ppu: &PPU, palette: &Palette) -> Image {
+    let mut image = Image::new(16 * PALETTE_SWATCH_SIZE, 2 * PALETTE_SWATCH_SIZE);
+    for entry in 0..32u16 {
+        let color = palette.get_color((ppu.peek_byte(0x3f00 + entry) & 0x3f) as u16);
+        let left = (entry as usize % 16) * PALETTE_SWATCH_SIZE;
+        let top = (entry as usize / 16) * PALETTE_SWATCH_SIZE;
+        for y in top..top + PALETTE_SWATCH_SIZE {
+            for x in left..left + PALETTE_SWATCH_SIZE {
+                image.set_pixel(x, y, color);
+            }
+        }
+    }
+    image
+}
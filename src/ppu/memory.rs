@@ -0,0 +1,147 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::cartridge::cartridge::{Cartridge, Mirroring, NametableSource};
+
+// from https://wiki.nesdev.com/w/index.php/PPU_memory_map
+const NAMETABLE_SIZE: usize = 1024;
+// the console's own 2KB of nametable RAM
+const CIRAM_SIZE: usize = 2 * NAMETABLE_SIZE;
+const PALETTE_SIZE: usize = 32;
+
+// $3F10, $3F14, $3F18 and $3F1C mirror the backdrop entries below them
+fn get_palette_index(address: u16) -> usize {
+    let index = (address as usize) & (PALETTE_SIZE - 1);
+    if (index & 0b11) == 0 { index & 0x0f } else { index }
+}
+
+// PPU address space, $0000-$3FFF: pattern tables on the cartridge, nametables
+// wired to CIRAM or cartridge VRAM by the mapper's mirroring, and palette RAM
+pub struct PpuMemory {
+    ciram: [u8; CIRAM_SIZE],
+    palette: [u8; PALETTE_SIZE],
+    cartridge: Option<Rc<RefCell<Cartridge>>>,
+}
+
+impl PpuMemory {
+    pub fn new() -> PpuMemory {
+        PpuMemory {
+            ciram: [0; CIRAM_SIZE],
+            palette: [0; PALETTE_SIZE],
+            cartridge: None,
+        }
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge: Rc<RefCell<Cartridge>>) {
+        self.cartridge = Some(cartridge);
+    }
+
+    // asked on every access, mappers switch nametables at any time
+    fn get_nametable_source(&self, table: usize) -> NametableSource {
+        match &self.cartridge {
+            Some(cartridge) => (*cartridge.borrow()).get_nametable_source(table),
+            None => NametableSource::Page(Mirroring::Horizontal.get_nametable_page(table)),
+        }
+    }
+
+    fn get_pattern_source(&self, address: u16) -> Option<NametableSource> {
+        self.cartridge.as_ref().and_then(|cartridge| (*cartridge.borrow()).get_pattern_source(address))
+    }
+
+    pub fn get_byte(&mut self, address: u16) -> u8 {
+        let address = address & 0x3fff;
+        match address {
+            0x0000..=0x1fff => match (self.get_pattern_source(address), &self.cartridge) {
+                (Some(source), _) => {
+                    self.notify_address(address);
+                    self.read_source(source, address)
+                }
+                (None, Some(cartridge)) => (*cartridge.borrow_mut()).read_chr(address),
+                (None, None) => 0,
+            },
+            0x2000..=0x3eff => {
+                self.notify_address(address);
+                self.read_nametable(address)
+            }
+            _ => self.palette[get_palette_index(address)],
+        }
+    }
+
+    // reads without notifying the mapper, for debuggers
+    pub fn peek_byte(&self, address: u16) -> u8 {
+        let address = address & 0x3fff;
+        match address {
+            0x0000..=0x1fff => match (self.get_pattern_source(address), &self.cartridge) {
+                (Some(source), _) => self.read_source(source, address),
+                (None, Some(cartridge)) => (*cartridge.borrow_mut()).peek_chr(address),
+                (None, None) => 0,
+            },
+            0x2000..=0x3eff => self.read_nametable(address),
+            _ => self.palette[get_palette_index(address)],
+        }
+    }
+
+    pub fn set_byte(&mut self, address: u16, value: u8) {
+        let address = address & 0x3fff;
+        match address {
+            0x0000..=0x1fff => match (self.get_pattern_source(address), &self.cartridge) {
+                (Some(source), _) => {
+                    self.notify_address(address);
+                    self.write_source(source, address, value);
+                }
+                (None, Some(cartridge)) => (*cartridge.borrow_mut()).write_chr(address, value),
+                (None, None) => {}
+            },
+            0x2000..=0x3eff => {
+                self.notify_address(address);
+                self.write_nametable(address, value);
+            }
+            _ => {
+                self.palette[get_palette_index(address)] = value & 0x3f;
+            }
+        }
+    }
+
+    // $3000-$3EFF mirrors $2000-$2EFF
+    fn read_nametable(&self, address: u16) -> u8 {
+        let table = ((address as usize) >> 10) & 0b11;
+        self.read_source(self.get_nametable_source(table), address)
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8) {
+        let table = ((address as usize) >> 10) & 0b11;
+        self.write_source(self.get_nametable_source(table), address, value);
+    }
+
+    // 1KB of whatever memory the mapper wired in, at the offset of the address
+    fn read_source(&self, source: NametableSource, address: u16) -> u8 {
+        let offset = (address as usize) & (NAMETABLE_SIZE - 1);
+        match (source, &self.cartridge) {
+            (NametableSource::Page(page @ 0..=1), _) => self.ciram[page * NAMETABLE_SIZE + offset],
+            (NametableSource::Page(page), Some(cartridge)) => (*cartridge.borrow()).read_vram((page - 2) * NAMETABLE_SIZE + offset),
+            (NametableSource::Chr(bank), Some(cartridge)) => (*cartridge.borrow()).read_chr_bank(bank, offset),
+            _ => 0,
+        }
+    }
+
+    fn write_source(&mut self, source: NametableSource, address: u16, value: u8) {
+        let offset = (address as usize) & (NAMETABLE_SIZE - 1);
+        match (source, &self.cartridge) {
+            (NametableSource::Page(page @ 0..=1), _) => self.ciram[page * NAMETABLE_SIZE + offset] = value,
+            (NametableSource::Page(page), Some(cartridge)) => {
+                (*cartridge.borrow_mut()).write_vram((page - 2) * NAMETABLE_SIZE + offset, value);
+            }
+            // only CHR RAM takes the write
+            (NametableSource::Chr(bank), Some(cartridge)) => {
+                (*cartridge.borrow_mut()).write_chr_bank(bank, offset, value);
+            }
+            _ => {}
+        }
+    }
+
+    // mappers watching the PPU address lines also see addresses set through PPUADDR
+    pub fn notify_address(&self, address: u16) {
+        if let Some(cartridge) = &self.cartridge {
+            (*cartridge.borrow_mut()).notify_ppu_address(address);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod memory;
+pub mod ppu;
+pub mod palette;
+pub mod ntsc;
+pub mod debug;
@@ -0,0 +1,192 @@
+use std::f32::consts::PI;
+use crate::ppu::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+// the composite signal the PPU puts out and its decoding, from
+// https://wiki.nesdev.com/w/index.php/NTSC_video
+
+// 8 samples per pixel, 12 per color subcarrier cycle
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_CYCLE: usize = 12;
+const LINE_SAMPLES: usize = SCREEN_WIDTH * SAMPLES_PER_PIXEL;
+// the decoding window reaches past both ends of the line
+const PADDING: usize = SAMPLES_PER_CYCLE;
+// one output pixel every 3 samples
+const SAMPLES_PER_OUTPUT: usize = 3;
+pub const NTSC_WIDTH: usize = LINE_SAMPLES / SAMPLES_PER_OUTPUT;
+
+// a scanline is 341 * 8 samples long, 4 more than a multiple of the
+// subcarrier cycle, so each line starts 4 samples later in it
+const LINE_PHASE_SHIFT: usize = 4;
+
+// voltages relative to sync, low and high levels of the square wave for the
+// four luma levels
+const BLACK: f32 = 0.518;
+const WHITE: f32 = 1.962;
+const LOW_LEVELS: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const HIGH_LEVELS: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+// subcarrier phase of color 0 relative to the hue the decoder takes as 0
+const BASE_HUE: f32 = 4.0;
+
+// normalized signal level of a framebuffer color at a subcarrier phase
+fn get_signal(color: u16, phase: usize) -> f32 {
+    let hue = (color & 0x0f) as usize;
+    let mut level = ((color >> 4) & 0b11) as usize;
+    let emphasis = (color >> 6) & 0b111;
+    // colors $xE and $xF are black
+    if hue > 13 {
+        level = 1;
+    }
+    let in_color_phase = |hue: usize| (hue + phase) % SAMPLES_PER_CYCLE < 6;
+
+    let mut low = LOW_LEVELS[level];
+    let mut high = HIGH_LEVELS[level];
+    // color 0 only has the high level and colors $xD-$xF only the low one
+    if hue == 0 {
+        low = high;
+    }
+    if hue > 12 {
+        high = low;
+    }
+    let mut signal = if in_color_phase(hue) { high } else { low };
+
+    // emphasis attenuates the signal during the phases of its color
+    if ((emphasis & 0b001) != 0 && in_color_phase(0)) ||
+        ((emphasis & 0b010) != 0 && in_color_phase(4)) ||
+        ((emphasis & 0b100) != 0 && in_color_phase(8)) {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+    (signal - BLACK) / (WHITE - BLACK)
+}
+
+// synthesizes the composite signal of every scanline and decodes it back to
+// RGB, with the dot crawl and color fringing of a real NTSC console
+pub struct NtscFilter {
+    // in degrees
+    hue: f32,
+    saturation: f32,
+    // -1 averages luma over a whole subcarrier cycle, 1 over half of one
+    sharpness: f32,
+    // 0 decodes every pixel on its own, 1 is the full composite crosstalk
+    artifacts: f32,
+    cosines: [f32; SAMPLES_PER_CYCLE],
+    sines: [f32; SAMPLES_PER_CYCLE],
+}
+
+impl NtscFilter {
+    pub fn new() -> NtscFilter {
+        let mut filter = NtscFilter {
+            hue: 0.0,
+            saturation: 1.0,
+            sharpness: 0.0,
+            artifacts: 1.0,
+            cosines: [0.0; SAMPLES_PER_CYCLE],
+            sines: [0.0; SAMPLES_PER_CYCLE],
+        };
+        filter.update_subcarrier();
+        filter
+    }
+
+    pub fn get_hue(&self) -> f32 {
+        self.hue
+    }
+
+    pub fn set_hue(&mut self, hue: f32) {
+        self.hue = hue;
+        self.update_subcarrier();
+    }
+
+    pub fn get_saturation(&self) -> f32 {
+        self.saturation
+    }
+
+    pub fn set_saturation(&mut self, saturation: f32) {
+        self.saturation = saturation.max(0.0);
+    }
+
+    pub fn get_sharpness(&self) -> f32 {
+        self.sharpness
+    }
+
+    pub fn set_sharpness(&mut self, sharpness: f32) {
+        self.sharpness = sharpness.clamp(-1.0, 1.0);
+    }
+
+    pub fn get_artifacts(&self) -> f32 {
+        self.artifacts
+    }
+
+    pub fn set_artifacts(&mut self, artifacts: f32) {
+        self.artifacts = artifacts.clamp(0.0, 1.0);
+    }
+
+    // 30 degrees of hue per sample
+    fn update_subcarrier(&mut self) {
+        for phase in 0..SAMPLES_PER_CYCLE {
+            let angle = PI * (phase as f32 + BASE_HUE + self.hue / 30.0) / 6.0;
+            self.cosines[phase] = angle.cos();
+            self.sines[phase] = angle.sin();
+        }
+    }
+
+    // RGBA8 image of NTSC_WIDTH x 240 pixels for a frame of framebuffer colors.
+    // The frame number places it in the subcarrier: odd frames with rendering
+    // enabled are a dot short, which makes the artifacts crawl between frames.
+    pub fn render(&self, framebuffer: &[u16], frame: u64) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(NTSC_WIDTH * SCREEN_HEIGHT * 4);
+        let mut signal = vec![0.0; LINE_SAMPLES + 2 * PADDING];
+        let frame_phase = ((frame & 1) as usize) * LINE_PHASE_SHIFT;
+
+        for y in 0..SCREEN_HEIGHT {
+            let row = &framebuffer[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH];
+            let line_phase = (frame_phase + y * LINE_PHASE_SHIFT) % SAMPLES_PER_CYCLE;
+            // PADDING is a whole cycle, so sample s has phase line_phase + s
+            for (sample, level) in signal.iter_mut().enumerate() {
+                let pixel = (sample.saturating_sub(PADDING) / SAMPLES_PER_PIXEL).min(SCREEN_WIDTH - 1);
+                *level = get_signal(row[pixel], (line_phase + sample) % SAMPLES_PER_CYCLE);
+            }
+
+            for x in 0..NTSC_WIDTH {
+                let center = PADDING + x * SAMPLES_PER_OUTPUT + 1;
+                let color = row[(center - PADDING) / SAMPLES_PER_PIXEL];
+                let composite = self.decode(line_phase, center, |sample, _| signal[sample]);
+                let clean = self.decode(line_phase, center, |_, phase| get_signal(color, phase));
+                let mix = |index: usize| clean[index] + self.artifacts * (composite[index] - clean[index]);
+                let [red, green, blue] = yiq_to_rgb(mix(0), mix(1), mix(2));
+                rgba.extend_from_slice(&[red, green, blue, 0xff]);
+            }
+        }
+        rgba
+    }
+
+    // YIQ over the subcarrier cycle centered on a sample
+    fn decode<F: Fn(usize, usize) -> f32>(&self, line_phase: usize, center: usize, signal: F) -> [f32; 3] {
+        let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+        let mut narrow_y = 0.0;
+        for sample in center - SAMPLES_PER_CYCLE / 2..center + SAMPLES_PER_CYCLE / 2 {
+            let phase = (line_phase + sample) % SAMPLES_PER_CYCLE;
+            let level = signal(sample, phase);
+            y += level;
+            i += level * self.cosines[phase];
+            q += level * self.sines[phase];
+            if (center - 3..center + 3).contains(&sample) {
+                narrow_y += level;
+            }
+        }
+        y /= SAMPLES_PER_CYCLE as f32;
+        narrow_y /= 6.0;
+        let y = y + (self.sharpness + 1.0) / 2.0 * (narrow_y - y);
+        let scale = 2.0 * self.saturation / SAMPLES_PER_CYCLE as f32;
+        [y, i * scale, q * scale]
+    }
+}
+
+fn yiq_to_rgb(y: f32, i: f32, q: f32) -> [u8; 3] {
+    let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [
+        to_byte(y + 0.946_882 * i + 0.623_557 * q),
+        to_byte(y - 0.274_788 * i - 0.635_691 * q),
+        to_byte(y - 1.108_545 * i + 1.709_007 * q),
+    ]
+}
@@ -0,0 +1,90 @@
+use std::fs;
+
+// 64 colors, each with 8 combinations of the emphasis bits
+pub const PALETTE_COLORS: usize = 64;
+pub const EMPHASIS_COLORS: usize = 8 * PALETTE_COLORS;
+
+// from https://wiki.nesdev.com/w/index.php/PPU_palettes#2C02
+const NTSC_PALETTE: [[u8; 3]; PALETTE_COLORS] = [
+    [84, 84, 84], [0, 30, 116], [8, 16, 144], [48, 0, 136], [68, 0, 100], [92, 0, 48], [84, 4, 0], [60, 24, 0],
+    [32, 42, 0], [8, 58, 0], [0, 64, 0], [0, 60, 0], [0, 50, 60], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [152, 150, 152], [8, 76, 196], [48, 50, 236], [92, 30, 228], [136, 20, 176], [160, 20, 100], [152, 34, 32], [120, 60, 0],
+    [84, 90, 0], [40, 114, 0], [8, 124, 0], [0, 118, 40], [0, 102, 120], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [76, 154, 236], [120, 124, 236], [176, 98, 236], [228, 84, 236], [236, 88, 180], [236, 106, 100], [212, 136, 32],
+    [160, 170, 0], [116, 196, 0], [76, 208, 32], [56, 204, 108], [56, 180, 204], [60, 60, 60], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236], [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180], [160, 214, 228], [160, 162, 160], [0, 0, 0], [0, 0, 0],
+];
+
+// each emphasis bit darkens the two other channels, measured on a 2C02
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+// turns framebuffer colors, palette index plus emphasis bits, into RGB
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    pub fn new() -> Palette {
+        Palette::from_colors(&NTSC_PALETTE)
+    }
+
+    // .pal files are RGB triplets, 64 of them or 512 with the emphasized
+    // colors included. Emphasis is computed for 64 color files.
+    pub fn load(path: &str) -> Result<Palette, String> {
+        let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        Palette::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Palette, String> {
+        let colors: Vec<[u8; 3]> = bytes.chunks(3)
+            .filter(|color| color.len() == 3)
+            .map(|color| [color[0], color[1], color[2]])
+            .collect();
+        match bytes.len() {
+            length if length == PALETTE_COLORS * 3 => Ok(Palette::from_colors(&colors)),
+            length if length == EMPHASIS_COLORS * 3 => Ok(Palette { colors }),
+            length => Err(format!("palette is {} bytes, expected {} or {}", length, PALETTE_COLORS * 3, EMPHASIS_COLORS * 3)),
+        }
+    }
+
+    fn from_colors(base: &[[u8; 3]]) -> Palette {
+        let mut colors = Vec::with_capacity(EMPHASIS_COLORS);
+        for emphasis in 0..8 {
+            for color in base.iter() {
+                colors.push(emphasize(*color, emphasis));
+            }
+        }
+        Palette { colors }
+    }
+
+    pub fn get_color(&self, color: u16) -> [u8; 3] {
+        self.colors[(color as usize) % EMPHASIS_COLORS]
+    }
+
+    // RGBA8 pixels, 4 bytes each, for a frame of framebuffer colors
+    pub fn to_rgba(&self, framebuffer: &[u16]) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(framebuffer.len() * 4);
+        for color in framebuffer.iter() {
+            let [red, green, blue] = self.get_color(*color);
+            rgba.extend_from_slice(&[red, green, blue, 0xff]);
+        }
+        rgba
+    }
+}
+
+// emphasis bit 0 is red, 1 green and 2 blue
+fn emphasize(color: [u8; 3], emphasis: usize) -> [u8; 3] {
+    let mut channels = [color[0] as f32, color[1] as f32, color[2] as f32];
+    for bit in 0..3 {
+        if (emphasis & (1 << bit)) == 0 {
+            continue;
+        }
+        for (channel, value) in channels.iter_mut().enumerate() {
+            if channel != bit {
+                *value *= EMPHASIS_ATTENUATION;
+            }
+        }
+    }
+    [channels[0].round() as u8, channels[1].round() as u8, channels[2].round() as u8]
+}
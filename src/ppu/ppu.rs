@@ -0,0 +1,850 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::cartridge::cartridge::Cartridge;
+use crate::ppu::memory::PpuMemory;
+use crate::timing::{Timing, NTSC};
+
+// from https://wiki.nesdev.com/w/index.php/PPU_registers
+const PPUCTRL: u16 = 0;
+const PPUMASK: u16 = 1;
+const PPUSTATUS: u16 = 2;
+const OAMADDR: u16 = 3;
+const OAMDATA: u16 = 4;
+const PPUSCROLL: u16 = 5;
+const PPUADDR: u16 = 6;
+const PPUDATA: u16 = 7;
+
+// PPUCTRL bits
+const CTRL_NAMETABLE: u8 = 0b0000_0011;
+const CTRL_INCREMENT_32: u8 = 0b0000_0100;
+const CTRL_SPRITE_TABLE: u8 = 0b0000_1000;
+const CTRL_BACKGROUND_TABLE: u8 = 0b0001_0000;
+const CTRL_SPRITE_8X16: u8 = 0b0010_0000;
+const CTRL_NMI: u8 = 0b1000_0000;
+
+// PPUMASK bits
+const MASK_GRAYSCALE: u8 = 0b0000_0001;
+const MASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SPRITES: u8 = 0b0001_0000;
+const MASK_EMPHASIS: u8 = 0b1110_0000;
+
+// PPUSTATUS bits
+const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+
+// sprite attribute bits
+const SPRITE_PALETTE: u8 = 0b0000_0011;
+const SPRITE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const SPRITE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const SPRITE_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+const OAM_SIZE: usize = 256;
+const SECONDARY_OAM_SIZE: usize = 32;
+const SPRITES_PER_SCANLINE: usize = 8;
+
+// from https://wiki.nesdev.com/w/index.php/PPU_rendering
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+const DOTS_PER_SCANLINE: u16 = 341;
+
+pub struct PPU {
+    memory: PpuMemory,
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam: [u8; OAM_SIZE],
+    oam_address: u8,
+
+    // internal scroll registers, from https://wiki.nesdev.com/w/index.php/PPU_scrolling
+    // v: current VRAM address, t: temporary VRAM address (the top left
+    // onscreen tile), x: fine X scroll, w: first or second write toggle
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+
+    // PPUDATA reads below the palettes return the previous read
+    read_buffer: u8,
+    // the data bus between CPU and PPU, write-only registers read it back
+    io_latch: u8,
+
+    scanline: u16,
+    dot: u16,
+    frame: u64,
+    // frame layout of the console region, the last scanline is the pre-render one
+    scanlines: u16,
+    vblank_scanline: u16,
+    odd_frame_skip: bool,
+    swap_emphasis: bool,
+    // the CPU's NMI input is edge triggered, so the PPU keeps the last level of
+    // its /NMI output and latches the edges until the CPU takes them
+    nmi_output: bool,
+    nmi_pending: bool,
+    // a PPUSTATUS read right before VBlank starts keeps the flag from being set
+    suppress_vblank: bool,
+    // colors of the last frame drawn, the 6 bit palette index with the
+    // red, green and blue emphasis bits above it, 512 possible colors in all
+    framebuffer: [u16; SCREEN_WIDTH * SCREEN_HEIGHT],
+
+    // background tile being fetched for the shift registers
+    next_tile_id: u8,
+    next_tile_attribute: u8,
+    next_tile_low: u8,
+    next_tile_high: u8,
+    // two tiles of pattern bits, the high byte is drawn while the low byte waits
+    pattern_low: u16,
+    pattern_high: u16,
+    attribute_low: u16,
+    attribute_high: u16,
+
+    // sprite evaluation, from https://wiki.nesdev.com/w/index.php/PPU_sprite_evaluation
+    // secondary OAM collects the sprites of the next scanline while n and m
+    // walk primary OAM, m being the byte within the sprite
+    secondary_oam: [u8; SECONDARY_OAM_SIZE],
+    secondary_index: usize,
+    evaluation_n: u8,
+    evaluation_m: u8,
+    evaluation_copy: u8,
+    evaluation_done: bool,
+    sprites_found: usize,
+    sprite_zero_found: bool,
+
+    // rows fetched for the scanline being drawn
+    sprite_count: usize,
+    sprite_zero_in_line: bool,
+    sprite_patterns_low: [u8; SPRITES_PER_SCANLINE],
+    sprite_patterns_high: [u8; SPRITES_PER_SCANLINE],
+    sprite_attributes: [u8; SPRITES_PER_SCANLINE],
+    sprite_x: [u8; SPRITES_PER_SCANLINE],
+}
+
+impl PPU {
+    pub fn new() -> PPU {
+        PPU {
+            memory: PpuMemory::new(),
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam: [0; OAM_SIZE],
+            oam_address: 0,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            scanlines: NTSC.scanlines,
+            vblank_scanline: NTSC.vblank_scanline,
+            odd_frame_skip: NTSC.odd_frame_skip,
+            swap_emphasis: NTSC.swap_emphasis,
+            nmi_output: false,
+            nmi_pending: false,
+            suppress_vblank: false,
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            next_tile_id: 0,
+            next_tile_attribute: 0,
+            next_tile_low: 0,
+            next_tile_high: 0,
+            pattern_low: 0,
+            pattern_high: 0,
+            attribute_low: 0,
+            attribute_high: 0,
+            secondary_oam: [0xff; SECONDARY_OAM_SIZE],
+            secondary_index: 0,
+            evaluation_n: 0,
+            evaluation_m: 0,
+            evaluation_copy: 0,
+            evaluation_done: false,
+            sprites_found: 0,
+            sprite_zero_found: false,
+            sprite_count: 0,
+            sprite_zero_in_line: false,
+            sprite_patterns_low: [0; SPRITES_PER_SCANLINE],
+            sprite_patterns_high: [0; SPRITES_PER_SCANLINE],
+            sprite_attributes: [0; SPRITES_PER_SCANLINE],
+            sprite_x: [0; SPRITES_PER_SCANLINE],
+        }
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge: Rc<RefCell<Cartridge>>) {
+        self.memory.insert_cartridge(cartridge);
+    }
+
+    pub fn set_timing(&mut self, timing: &Timing) {
+        self.scanlines = timing.scanlines;
+        self.vblank_scanline = timing.vblank_scanline;
+        self.odd_frame_skip = timing.odd_frame_skip;
+        self.swap_emphasis = timing.swap_emphasis;
+        if self.scanline >= self.scanlines {
+            self.scanline = 0;
+        }
+    }
+
+    // CPU bus, $2000-$3FFF with the eight registers mirrored throughout
+    pub fn read_register(&mut self, address: u16) -> u8 {
+        let value = match address & 0b111 {
+            PPUSTATUS => {
+                let value = (self.status & 0xe0) | (self.io_latch & 0x1f);
+                self.status &= !STATUS_VBLANK;
+                self.w = false;
+                self.update_nmi();
+                // from https://wiki.nesdev.com/w/index.php/PPU_frame_timing#VBL_Flag_Timing
+                // reading one dot before VBlank starts reads it clear and skips it
+                // for the frame, reading on the dot or the one after suppresses the NMI
+                if self.scanline == self.vblank_scanline {
+                    match self.dot {
+                        1 => self.suppress_vblank = true,
+                        2..=3 => self.nmi_pending = false,
+                        _ => {}
+                    }
+                }
+                value
+            }
+            // secondary OAM is being cleared and reads back $FF
+            OAMDATA if self.is_clearing_secondary_oam() => 0xff,
+            OAMDATA => {
+                let value = self.oam[self.oam_address as usize];
+                // bits 2-4 of sprite attributes do not exist
+                if (self.oam_address & 0b11) == 2 { value & 0xe3 } else { value }
+            }
+            PPUDATA => self.read_data(),
+            _ => self.io_latch,
+        };
+        self.io_latch = value;
+        value
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        self.io_latch = value;
+        match address & 0b111 {
+            PPUCTRL => {
+                self.ctrl = value;
+                // t: ...GH.. ........ <- d: ......GH
+                self.t = (self.t & !0x0c00) | (((value & CTRL_NAMETABLE) as u16) << 10);
+                // enabling NMI during VBlank triggers one right away, disabling it
+                // on the dot VBlank starts cancels the one just raised
+                self.update_nmi();
+                if !self.is_nmi_enabled() && self.scanline == self.vblank_scanline && self.dot <= 2 {
+                    self.nmi_pending = false;
+                }
+            }
+            PPUMASK => {
+                self.mask = value;
+            }
+            OAMADDR => {
+                self.oam_address = value;
+            }
+            OAMDATA => {
+                self.oam[self.oam_address as usize] = value;
+                self.oam_address = self.oam_address.wrapping_add(1);
+            }
+            PPUSCROLL => {
+                if !self.w {
+                    // t: ....... ...HGFED <- d: HGFED...
+                    self.t = (self.t & !0x001f) | ((value >> 3) as u16);
+                    self.x = value & 0b111;
+                } else {
+                    // t: CBA..HG FED..... <- d: HGFEDCBA
+                    self.t = (self.t & !0x73e0) | (((value & 0b111) as u16) << 12) | (((value & 0xf8) as u16) << 2);
+                }
+                self.w = !self.w;
+            }
+            PPUADDR => {
+                if !self.w {
+                    // t: .FEDCBA ........ <- d: ..FEDCBA, bit 14 is cleared
+                    self.t = (self.t & 0x00ff) | (((value & 0x3f) as u16) << 8);
+                } else {
+                    self.t = (self.t & 0xff00) | value as u16;
+                    self.v = self.t;
+                    self.memory.notify_address(self.v);
+                }
+                self.w = !self.w;
+            }
+            PPUDATA => {
+                self.memory.set_byte(self.v, value);
+                self.increment_address();
+            }
+            _ => {}
+        }
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let address = self.v & 0x3fff;
+        let value = if address >= 0x3f00 {
+            // palettes answer right away, the buffer gets the nametable byte underneath
+            self.read_buffer = self.memory.get_byte(address - 0x1000);
+            (self.memory.get_byte(address) & 0x3f) | (self.io_latch & 0xc0)
+        } else {
+            let value = self.read_buffer;
+            self.read_buffer = self.memory.get_byte(address);
+            value
+        };
+        self.increment_address();
+        value
+    }
+
+    fn increment_address(&mut self) {
+        let increment = if (self.ctrl & CTRL_INCREMENT_32) != 0 { 32 } else { 1 };
+        self.v = self.v.wrapping_add(increment) & 0x7fff;
+        self.memory.notify_address(self.v);
+    }
+
+    // one dot, three per CPU cycle on NTSC
+    pub fn tick(&mut self) {
+        let visible = self.scanline < SCREEN_HEIGHT as u16;
+        let prerender = self.scanline == self.scanlines - 1;
+
+        if (visible || prerender) && self.is_rendering_enabled() {
+            self.render_dot(prerender);
+        }
+        if visible && (1..=SCREEN_WIDTH as u16).contains(&self.dot) {
+            self.draw_pixel();
+        }
+
+        if self.scanline == self.vblank_scanline && self.dot == 1 {
+            if !self.suppress_vblank {
+                self.set_vblank(true);
+            }
+            self.suppress_vblank = false;
+            self.update_nmi();
+        }
+        if prerender && self.dot == 1 {
+            self.status &= !(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW);
+            self.update_nmi();
+        }
+
+        // odd frames skip the last dot of the pre-render scanline when rendering on NTSC,
+        // from https://wiki.nesdev.com/w/index.php/PPU_frame_timing#Even.2FOdd_Frames
+        if prerender && self.dot == 339 && self.odd_frame_skip && (self.frame & 1) == 1 && self.is_rendering_enabled() {
+            self.dot += 1;
+        }
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == self.scanlines {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+    }
+
+    // fetches and scroll updates of the visible and pre-render scanlines,
+    // from https://wiki.nesdev.com/w/images/d/d1/Ntsc_timing.png
+    fn render_dot(&mut self, prerender: bool) {
+        let dot = self.dot;
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.shift_background();
+        }
+        match dot {
+            1..=256 | 321..=336 => match (dot - 1) % 8 {
+                0 => {
+                    if dot != 1 && dot != 321 {
+                        self.load_background();
+                    }
+                    self.fetch_tile_id();
+                }
+                2 => self.fetch_tile_attribute(),
+                4 => self.next_tile_low = self.fetch_tile_pattern(0),
+                6 => self.next_tile_high = self.fetch_tile_pattern(8),
+                7 => self.increment_x(),
+                _ => {}
+            },
+            // two unused nametable fetches end the scanline
+            337 | 339 => {
+                if dot == 337 {
+                    self.load_background();
+                }
+                self.fetch_tile_id();
+            }
+            _ => {}
+        }
+        if dot == 256 {
+            self.increment_y();
+        }
+        if dot == 257 {
+            self.load_background();
+            self.copy_x();
+            // no sprites are evaluated on the pre-render scanline, so none show on the first
+            self.sprite_count = if prerender { 0 } else { self.sprites_found };
+            self.sprite_zero_in_line = !prerender && self.sprite_zero_found;
+        }
+        if prerender && (280..=304).contains(&dot) {
+            self.copy_y();
+        }
+        if !prerender {
+            match dot {
+                1..=64 if (dot & 1) == 1 => self.secondary_oam[(dot / 2) as usize] = 0xff,
+                65 => {
+                    self.start_sprite_evaluation();
+                    self.evaluate_sprite();
+                }
+                66..=256 if (dot & 1) == 1 => self.evaluate_sprite(),
+                _ => {}
+            }
+        }
+        if (257..=320).contains(&dot) {
+            self.oam_address = 0;
+            self.fetch_sprite(dot);
+        }
+    }
+
+    fn fetch_tile_id(&mut self) {
+        self.next_tile_id = self.memory.get_byte(0x2000 | (self.v & 0x0fff));
+    }
+
+    // one attribute byte covers 4x4 tiles, two bits for each 2x2 quadrant
+    fn fetch_tile_attribute(&mut self) {
+        let address = 0x23c0 | (self.v & 0x0c00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07);
+        let shift = ((self.v >> 4) & 0b100) | (self.v & 0b10);
+        self.next_tile_attribute = (self.memory.get_byte(address) >> shift) & 0b11;
+    }
+
+    fn fetch_tile_pattern(&mut self, plane: u16) -> u8 {
+        let fine_y = (self.v >> 12) & 0b111;
+        let address = self.get_background_table() + ((self.next_tile_id as u16) << 4) + plane + fine_y;
+        self.memory.get_byte(address)
+    }
+
+    fn load_background(&mut self) {
+        self.pattern_low = (self.pattern_low & 0xff00) | self.next_tile_low as u16;
+        self.pattern_high = (self.pattern_high & 0xff00) | self.next_tile_high as u16;
+        self.attribute_low = (self.attribute_low & 0xff00) | if (self.next_tile_attribute & 0b01) != 0 { 0xff } else { 0 };
+        self.attribute_high = (self.attribute_high & 0xff00) | if (self.next_tile_attribute & 0b10) != 0 { 0xff } else { 0 };
+    }
+
+    fn shift_background(&mut self) {
+        self.pattern_low <<= 1;
+        self.pattern_high <<= 1;
+        self.attribute_low <<= 1;
+        self.attribute_high <<= 1;
+    }
+
+    // from https://wiki.nesdev.com/w/index.php/PPU_scrolling#Wrapping_around
+    fn increment_x(&mut self) {
+        if (self.v & 0x001f) == 31 {
+            self.v &= !0x001f;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if (self.v & 0x7000) != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03e0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // rows 30 and 31 hold attributes, scrolling into them wraps without switching nametables
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (coarse_y << 5);
+    }
+
+    fn copy_x(&mut self) {
+        self.v = (self.v & !0x041f) | (self.t & 0x041f);
+    }
+
+    fn copy_y(&mut self) {
+        self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
+    }
+
+    fn is_clearing_secondary_oam(&self) -> bool {
+        self.is_rendering_enabled() && self.scanline < SCREEN_HEIGHT as u16 && (1..=64).contains(&self.dot)
+    }
+
+    fn is_sprite_in_range(&self, y: u8) -> bool {
+        let row = self.scanline as i16 - y as i16;
+        (0..self.get_sprite_height() as i16).contains(&row)
+    }
+
+    fn start_sprite_evaluation(&mut self) {
+        self.secondary_index = 0;
+        self.evaluation_n = 0;
+        self.evaluation_m = 0;
+        self.evaluation_copy = 0;
+        self.evaluation_done = false;
+        self.sprites_found = 0;
+        self.sprite_zero_found = false;
+    }
+
+    // one read from OAM and one write to secondary OAM, every two dots of 65-256.
+    // Sprites in range for the next scanline are copied until eight are found.
+    fn evaluate_sprite(&mut self) {
+        if self.evaluation_copy > 0 {
+            let value = self.oam[self.evaluation_n as usize * 4 + self.evaluation_m as usize];
+            self.secondary_oam[self.secondary_index] = value;
+            self.secondary_index += 1;
+            self.evaluation_m += 1;
+            self.evaluation_copy -= 1;
+            if self.evaluation_copy == 0 {
+                self.evaluation_m = 0;
+                self.next_evaluated_sprite();
+            }
+            return;
+        }
+        if self.evaluation_done {
+            return;
+        }
+
+        if self.sprites_found < SPRITES_PER_SCANLINE {
+            let y = self.oam[self.evaluation_n as usize * 4];
+            self.secondary_oam[self.secondary_index] = y;
+            if self.is_sprite_in_range(y) {
+                if self.evaluation_n == 0 {
+                    self.sprite_zero_found = true;
+                }
+                self.sprites_found += 1;
+                self.secondary_index += 1;
+                self.evaluation_m = 1;
+                self.evaluation_copy = 3;
+            } else {
+                self.next_evaluated_sprite();
+            }
+            return;
+        }
+
+        // with secondary OAM full the search for a ninth sprite goes on, but
+        // m is incremented along with n, so bytes other than Y get compared
+        // against the scanline, from https://wiki.nesdev.com/w/index.php/PPU_sprite_evaluation#Sprite_overflow_bug
+        let value = self.oam[self.evaluation_n as usize * 4 + self.evaluation_m as usize];
+        if self.is_sprite_in_range(value) {
+            self.set_sprite_overflow(true);
+            self.evaluation_done = true;
+        } else {
+            self.evaluation_m = (self.evaluation_m + 1) & 0b11;
+            self.next_evaluated_sprite();
+        }
+    }
+
+    fn next_evaluated_sprite(&mut self) {
+        self.evaluation_n = (self.evaluation_n + 1) & 0x3f;
+        if self.evaluation_n == 0 {
+            self.evaluation_done = true;
+        }
+    }
+
+    // dots 257-320 fetch the pattern rows of the eight sprite slots, empty
+    // slots still fetch tile $FF
+    fn fetch_sprite(&mut self, dot: u16) {
+        let slot = ((dot - 257) / 8) as usize;
+        let phase = (dot - 257) % 8;
+        if phase != 4 && phase != 6 {
+            return;
+        }
+        let sprite = &self.secondary_oam[slot * 4..slot * 4 + 4];
+        let (y, tile, attributes, x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+        let height = self.get_sprite_height() as u16;
+        let mut row = self.scanline.wrapping_sub(y as u16) & (height - 1);
+        if (attributes & SPRITE_FLIP_VERTICAL) != 0 {
+            row = height - 1 - row;
+        }
+        // 8x16 sprites take their table from bit 0 of the tile number, the
+        // bottom half is the next tile
+        let tile_address = if height == 16 {
+            let table = if (tile & 1) != 0 { 0x1000 } else { 0x0000 };
+            table + (((tile & 0xfe) as u16 + row / 8) << 4) + (row & 0b111)
+        } else {
+            self.get_sprite_table() + ((tile as u16) << 4) + row
+        };
+        let plane = if phase == 4 { 0 } else { 8 };
+        let mut pattern = self.memory.get_byte(tile_address + plane);
+        if slot >= self.sprite_count {
+            pattern = 0;
+        } else if (attributes & SPRITE_FLIP_HORIZONTAL) != 0 {
+            pattern = pattern.reverse_bits();
+        }
+        if phase == 4 {
+            self.sprite_patterns_low[slot] = pattern;
+        } else {
+            self.sprite_patterns_high[slot] = pattern;
+            self.sprite_attributes[slot] = attributes;
+            self.sprite_x[slot] = x;
+        }
+    }
+
+    // 2 bit pixel and palette of the background under the current dot
+    fn get_background_pixel(&self, x: usize) -> (u8, u8) {
+        if (self.mask & MASK_BACKGROUND) == 0 || (x < 8 && (self.mask & MASK_BACKGROUND_LEFT) == 0) {
+            return (0, 0);
+        }
+        let bit = 0x8000 >> self.x;
+        let pixel = (((self.pattern_high & bit) != 0) as u8) << 1 | ((self.pattern_low & bit) != 0) as u8;
+        let palette = (((self.attribute_high & bit) != 0) as u8) << 1 | ((self.attribute_low & bit) != 0) as u8;
+        (pixel, palette)
+    }
+
+    // the first opaque sprite in OAM order wins, whatever its priority.
+    // Returns the pixel, the attributes and the slot it came from.
+    fn get_sprite_pixel(&self, x: usize) -> Option<(u8, u8, usize)> {
+        if (self.mask & MASK_SPRITES) == 0 || (x < 8 && (self.mask & MASK_SPRITES_LEFT) == 0) {
+            return None;
+        }
+        for slot in 0..self.sprite_count {
+            let offset = x as i16 - self.sprite_x[slot] as i16;
+            if !(0..8).contains(&offset) {
+                continue;
+            }
+            let shift = 7 - offset;
+            let pixel = ((self.sprite_patterns_high[slot] >> shift) & 1) << 1 | ((self.sprite_patterns_low[slot] >> shift) & 1);
+            if pixel != 0 {
+                return Some((pixel, self.sprite_attributes[slot], slot));
+            }
+        }
+        None
+    }
+
+    // from https://wiki.nesdev.com/w/index.php/PPU_rendering#Preparing_sprites
+    fn draw_pixel(&mut self) {
+        let x = (self.dot - 1) as usize;
+        let y = self.scanline as usize;
+
+        let address = if !self.is_rendering_enabled() && (self.v & 0x3f00) == 0x3f00 {
+            // with rendering off the backdrop shows the palette entry v points at
+            self.v
+        } else {
+            let (background, background_palette) = self.get_background_pixel(x);
+            let sprite_pixel = self.get_sprite_pixel(x);
+            // sprite 0 is always slot 0 when on the line. The hit needs both
+            // pixels opaque, which left column clipping prevents, and never
+            // happens at x=255.
+            if let Some((_, _, 0)) = sprite_pixel {
+                if self.sprite_zero_in_line && background != 0 && x != 255 {
+                    self.set_sprite_zero_hit(true);
+                }
+            }
+            match sprite_pixel {
+                Some((sprite, attributes, _)) if background == 0 || (attributes & SPRITE_BEHIND_BACKGROUND) == 0 => {
+                    0x3f10 | (((attributes & SPRITE_PALETTE) as u16) << 2) | sprite as u16
+                }
+                _ if background != 0 => 0x3f00 | ((background_palette as u16) << 2) | background as u16,
+                _ => 0x3f00,
+            }
+        };
+
+        let mut color = self.memory.get_byte(address) & 0x3f;
+        if (self.mask & MASK_GRAYSCALE) != 0 {
+            color &= 0x30;
+        }
+        let mut emphasis = (self.mask & MASK_EMPHASIS) >> 5;
+        if self.swap_emphasis {
+            emphasis = (emphasis & 0b100) | ((emphasis & 0b01) << 1) | ((emphasis & 0b10) >> 1);
+        }
+        let emphasis = (emphasis as u16) << 6;
+        self.framebuffer[y * SCREEN_WIDTH + x] = emphasis | color as u16;
+    }
+
+    pub fn is_rendering_enabled(&self) -> bool {
+        (self.mask & (MASK_BACKGROUND | MASK_SPRITES)) != 0
+    }
+
+    // 256x240 colors, row by row, turned into RGB by a Palette
+    pub fn get_framebuffer(&self) -> &[u16] {
+        &self.framebuffer
+    }
+
+    // frames completed since power on, counted as the pre-render scanline ends
+    pub fn get_frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn get_scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn get_dot(&self) -> u16 {
+        self.dot
+    }
+
+    pub fn get_oam(&self) -> &[u8] {
+        &self.oam
+    }
+
+    pub fn get_memory(&mut self) -> &mut PpuMemory {
+        &mut self.memory
+    }
+
+    pub fn peek_byte(&self, address: u16) -> u8 {
+        self.memory.peek_byte(address)
+    }
+
+    // level of the /NMI output, the CPU reacts to it going active
+    pub fn get_nmi(&self) -> bool {
+        self.is_vblank() && self.is_nmi_enabled()
+    }
+
+    fn update_nmi(&mut self) {
+        let output = self.get_nmi();
+        if output && !self.nmi_output {
+            self.nmi_pending = true;
+        }
+        self.nmi_output = output;
+    }
+
+    pub fn is_nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    pub fn acknowledge_nmi(&mut self) {
+        self.nmi_pending = false;
+    }
+
+    pub fn is_nmi_enabled(&self) -> bool {
+        (self.ctrl & CTRL_NMI) != 0
+    }
+
+    pub fn get_sprite_table(&self) -> u16 {
+        if (self.ctrl & CTRL_SPRITE_TABLE) != 0 { 0x1000 } else { 0x0000 }
+    }
+
+    pub fn get_background_table(&self) -> u16 {
+        if (self.ctrl & CTRL_BACKGROUND_TABLE) != 0 { 0x1000 } else { 0x0000 }
+    }
+
+    pub fn get_sprite_height(&self) -> u8 {
+        if (self.ctrl & CTRL_SPRITE_8X16) != 0 { 16 } else { 8 }
+    }
+
+    // top left corner of the picture in the 512x480 nametable plane, from t
+    // as it is copied into v at the start of the frame
+    pub fn get_scroll(&self) -> (usize, usize) {
+        let x = ((self.t & 0x0400) >> 2) | ((self.t & 0x001f) << 3) | self.x as u16;
+        let top = if (self.t & 0x0800) != 0 { 240 } else { 0 };
+        let y = top + ((self.t & 0x03e0) >> 2) + ((self.t & 0x7000) >> 12);
+        (x as usize, y as usize)
+    }
+
+    pub fn get_mask(&self) -> u8 {
+        self.mask
+    }
+
+    pub fn is_vblank(&self) -> bool {
+        (self.status & STATUS_VBLANK) != 0
+    }
+
+    pub fn set_vblank(&mut self, vblank: bool) {
+        self.set_status(STATUS_VBLANK, vblank);
+    }
+
+    pub fn set_sprite_zero_hit(&mut self, hit: bool) {
+        self.set_status(STATUS_SPRITE_ZERO_HIT, hit);
+    }
+
+    pub fn set_sprite_overflow(&mut self, overflow: bool) {
+        self.set_status(STATUS_SPRITE_OVERFLOW, overflow);
+    }
+
+    fn set_status(&mut self, flag: u8, value: bool) {
+        if value {
+            self.status |= flag;
+        } else {
+            self.status &= !flag;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NROM with CHR RAM where tile 0 is solid, so the background is opaque
+    // everywhere. OAM holds the given sprites and $FF after them.
+    fn create_ppu(sprites: &[[u8; 4]]) -> PPU {
+        let mut rom = vec![0x4e, 0x45, 0x53, 0x1a, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend(vec![0; 0x4000]);
+        let mut ppu = PPU::new();
+        ppu.insert_cartridge(Rc::new(RefCell::new(Cartridge::from_bytes(&rom).unwrap())));
+        ppu.write_register(PPUADDR, 0x00);
+        ppu.write_register(PPUADDR, 0x00);
+        for _ in 0..8 {
+            ppu.write_register(PPUDATA, 0xff);
+        }
+        ppu.write_register(PPUADDR, 0x00);
+        ppu.write_register(PPUADDR, 0x00);
+
+        ppu.write_register(OAMADDR, 0);
+        for index in 0..OAM_SIZE {
+            let value = sprites.get(index / 4).map_or(0xff, |sprite| sprite[index % 4]);
+            ppu.write_register(OAMDATA, value);
+        }
+        ppu
+    }
+
+    // renders the visible scanlines of the first frame, returns PPUSTATUS
+    fn render(ppu: &mut PPU, mask: u8) -> u8 {
+        ppu.write_register(PPUMASK, mask);
+        while ppu.get_scanline() < SCREEN_HEIGHT as u16 {
+            ppu.tick();
+        }
+        ppu.status
+    }
+
+    #[test]
+    fn ninth_sprite_on_a_line_sets_overflow() {
+        let sprites = [[20, 0, 0, 0]; 9];
+        let mut ppu = create_ppu(&sprites[..8]);
+        assert_eq!(render(&mut ppu, 0x1e) & STATUS_SPRITE_OVERFLOW, 0);
+        let mut ppu = create_ppu(&sprites);
+        assert_ne!(render(&mut ppu, 0x1e) & STATUS_SPRITE_OVERFLOW, 0);
+
+        // only eight of them are drawn
+        let mut ppu = create_ppu(&sprites);
+        ppu.write_register(PPUMASK, 0x1e);
+        while ppu.get_scanline() < 21 {
+            ppu.tick();
+        }
+        assert_eq!(ppu.sprite_count, SPRITES_PER_SCANLINE);
+    }
+
+    // after eight sprites a sprite out of range moves m on, so the next
+    // sprite gets its tile number compared against the scanline instead of Y
+    #[test]
+    fn overflow_search_walks_oam_diagonally() {
+        let mut sprites = vec![[20, 0, 0, 0]; 8];
+        sprites.push([0xff, 0xff, 0xff, 0xff]);
+        sprites.push([0xff, 20, 0xff, 0xff]);
+        let mut ppu = create_ppu(&sprites);
+        assert_ne!(render(&mut ppu, 0x1e) & STATUS_SPRITE_OVERFLOW, 0);
+
+        // a ninth sprite in range is missed the same way
+        sprites[9] = [20, 0xff, 0xff, 0xff];
+        let mut ppu = create_ppu(&sprites);
+        assert_eq!(render(&mut ppu, 0x1e) & STATUS_SPRITE_OVERFLOW, 0);
+    }
+
+    #[test]
+    fn sprite_zero_hit_misses_the_last_column() {
+        let mut ppu = create_ppu(&[[20, 0, 0, 255]]);
+        assert_eq!(render(&mut ppu, 0x1e) & STATUS_SPRITE_ZERO_HIT, 0);
+        let mut ppu = create_ppu(&[[20, 0, 0, 254]]);
+        assert_ne!(render(&mut ppu, 0x1e) & STATUS_SPRITE_ZERO_HIT, 0);
+    }
+
+    #[test]
+    fn sprite_zero_hit_needs_the_left_column_shown() {
+        // either layer clipped in the left column prevents the hit
+        for (mask, hit) in [(0x1a, false), (0x1c, false), (0x1e, true)] {
+            let mut ppu = create_ppu(&[[20, 0, 0, 0]]);
+            let status = render(&mut ppu, mask);
+            assert_eq!((status & STATUS_SPRITE_ZERO_HIT) != 0, hit, "{:02X}", mask);
+        }
+
+        // a sprite other than sprite 0 never hits
+        let mut ppu = create_ppu(&[[0xff, 0, 0, 0], [20, 0, 0, 0]]);
+        assert_eq!(render(&mut ppu, 0x1e) & STATUS_SPRITE_ZERO_HIT, 0);
+    }
+}
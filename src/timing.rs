@@ -0,0 +1,105 @@
+use crate::cartridge::header::Region;
+
+// clock rates and frame layout of each console region, from
+// https://wiki.nesdev.com/w/index.php/Cycle_reference_chart
+#[derive(Debug, Copy, Clone)]
+pub struct Timing {
+    pub region: Region,
+    pub cpu_clock: f64,
+    // PPU dots per CPU cycle as a fraction, 3.2 on PAL
+    pub ppu_dots: u32,
+    pub cpu_cycles: u32,
+    pub scanlines: u16,
+    pub vblank_scanline: u16,
+    pub odd_frame_skip: bool,
+    // PAL and Dendy PPUs have the red and green emphasis bits the other way around
+    pub swap_emphasis: bool,
+    // APU frame counter steps in CPU cycles, from https://wiki.nesdev.com/w/index.php/APU_Frame_Counter
+    // the first three steps are shared, then the last step of each sequence
+    pub frame_counter_steps: [u32; 3],
+    pub frame_counter_4_step_end: u32,
+    pub frame_counter_5_step_end: u32,
+    // timer periods in CPU cycles, from https://wiki.nesdev.com/w/index.php/APU_Noise
+    // and https://wiki.nesdev.com/w/index.php/APU_DMC
+    pub noise_periods: [u16; 16],
+    pub dmc_rates: [u16; 16],
+}
+
+const DOTS_PER_SCANLINE: u32 = 341;
+
+const NTSC_NOISE_PERIODS: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const PAL_NOISE_PERIODS: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+const NTSC_DMC_RATES: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const PAL_DMC_RATES: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+pub const NTSC: Timing = Timing {
+    region: Region::Ntsc,
+    cpu_clock: 1_789_773.0,
+    ppu_dots: 3,
+    cpu_cycles: 1,
+    scanlines: 262,
+    vblank_scanline: 241,
+    odd_frame_skip: true,
+    swap_emphasis: false,
+    frame_counter_steps: [7457, 14913, 22371],
+    frame_counter_4_step_end: 29829,
+    frame_counter_5_step_end: 37281,
+    noise_periods: NTSC_NOISE_PERIODS,
+    dmc_rates: NTSC_DMC_RATES,
+};
+
+pub const PAL: Timing = Timing {
+    region: Region::Pal,
+    cpu_clock: 1_662_607.0,
+    ppu_dots: 16,
+    cpu_cycles: 5,
+    scanlines: 312,
+    vblank_scanline: 241,
+    odd_frame_skip: false,
+    swap_emphasis: true,
+    frame_counter_steps: [8313, 16627, 24939],
+    frame_counter_4_step_end: 33253,
+    frame_counter_5_step_end: 41565,
+    noise_periods: PAL_NOISE_PERIODS,
+    dmc_rates: PAL_DMC_RATES,
+};
+
+// a PAL frame with the NTSC CPU to PPU ratio, where the extra scanlines come
+// after the picture and before VBlank. Its APU runs at NTSC rates.
+pub const DENDY: Timing = Timing {
+    region: Region::Dendy,
+    cpu_clock: 1_773_448.0,
+    ppu_dots: 3,
+    cpu_cycles: 1,
+    scanlines: 312,
+    vblank_scanline: 291,
+    odd_frame_skip: false,
+    swap_emphasis: true,
+    frame_counter_steps: [7457, 14913, 22371],
+    frame_counter_4_step_end: 29829,
+    frame_counter_5_step_end: 37281,
+    noise_periods: NTSC_NOISE_PERIODS,
+    dmc_rates: NTSC_DMC_RATES,
+};
+
+impl Timing {
+    // games for both regions run with NTSC timing
+    pub fn from_region(region: Region) -> Timing {
+        match region {
+            Region::Pal => PAL,
+            Region::Dendy => DENDY,
+            Region::Ntsc | Region::Multi => NTSC,
+        }
+    }
+
+    // frames per second, ignoring the dot NTSC skips on odd frames
+    pub fn get_frame_rate(&self) -> f64 {
+        let dots_per_second = self.cpu_clock * self.ppu_dots as f64 / self.cpu_cycles as f64;
+        dots_per_second / (DOTS_PER_SCANLINE * self.scanlines as u32) as f64
+    }
+
+    // the region from the ROM header or the game database, unless the user picked one
+    pub fn select(header_region: Region, override_region: Option<Region>) -> Timing {
+        Timing::from_region(override_region.unwrap_or(header_region))
+    }
+}